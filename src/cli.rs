@@ -1,15 +1,137 @@
-use std::io;
-use std::path::PathBuf;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
 
-use crate::recorder::Recorder;
+use serde_json::{json, Value as Json};
 
+use crate::config::Config;
+use crate::downloader;
+use crate::notify::CURL;
+use crate::recorder::{JobState, RecorderError};
+
+/// Removes empty job dirs, through the running server if there is one.
 pub fn gc() -> io::Result<()> {
-    dotenv::dotenv().ok();
+    let config = Config::from_env();
+
+    let recorder = config.recorder();
+
+    let removed = match recorder.lock() {
+        Ok(_lock) => recorder.prune_job_dirs().map_err(io::Error::other)?,
+        Err(RecorderError::WorkDirLocked(_)) => {
+            println!("vrec is running; asking it to remove the dirs");
+            let result = call_admin_api(&config, "gc")?;
+            result["removed"].as_u64().unwrap_or(0) as usize
+        }
+        Err(err) => return Err(io::Error::other(err.to_string())),
+    };
+    println!("removed {} job dir(s)", removed);
+    Ok(())
+}
+
+/// Runs the downloader update as a job and waits for it to finish.
+pub fn update_downloader() -> io::Result<()> {
+    let config = Config::from_env();
+
+    let recorder = config.recorder();
+
+    let _lock = match recorder.lock() {
+        Ok(lock) => lock,
+        Err(RecorderError::WorkDirLocked(_)) => {
+            let result = call_admin_api(&config, "update-downloader")?;
+            println!(
+                "vrec is running; started job {} there",
+                result["id"].as_str().unwrap_or("(unknown)")
+            );
+            return Ok(());
+        }
+        Err(err) => return Err(io::Error::other(err.to_string())),
+    };
+
+    let job = downloader::spawn_update(&config, &recorder).map_err(io::Error::other)?;
+    println!("started job {}", job.id());
 
-    let var_dir_path = dotenv::var("VAR_DIR").unwrap_or_else(|_| "var".to_owned());
-    let recorder_dir_path = PathBuf::from(var_dir_path).join("jobs");
+    // A job held in the queue for a slot starts once one is free, but not while the queue is
+    // paused.
+    loop {
+        match job.state() {
+            Some(JobState::Running) => {}
+            Some(JobState::Created | JobState::Queued) if !recorder.is_queue_paused() => {}
+            _ => break,
+        }
+        thread::sleep(Duration::from_millis(500));
+    }
+
+    let state = job.state();
+    if state == Some(JobState::Queued) {
+        println!(
+            "job {} is queued; the queue is paused, and the update runs once it is resumed",
+            job.id()
+        );
+        return Ok(());
+    }
+    println!(
+        "job {} {}",
+        job.id(),
+        state.map_or("unknown", JobState::as_str)
+    );
+    println!("output: {}", job.path().join("info/stdout.txt").display());
+
+    if state == Some(JobState::Succeeded) {
+        Ok(())
+    } else {
+        Err(io::Error::other("downloader update failed"))
+    }
+}
+
+/// Calls an admin endpoint of the server that holds the work dir lock, assuming it listens on
+/// the first address of `BIND`.
+fn call_admin_api(config: &Config, action: &str) -> io::Result<Json> {
+    let access_key = config
+        .admin_key
+        .as_ref()
+        .or(config.access_key.as_ref())
+        .ok_or_else(|| io::Error::other("ADMIN_KEY or ACCESS_KEY must be set"))?;
+    let url = format!("http://{}/api/admin/{}", config.local_addr(), action);
+    post_json(&url, &json!({ "accessKey": access_key }))
+        .map_err(|err| io::Error::other(format!("{} failed: {}", action, err)))
+}
+
+/// Posts `body` to a vrec API route with curl and returns the JSON response, or `null` if it had
+/// none. API errors are returned as errors.
+pub(crate) fn post_json(url: &str, body: &Json) -> io::Result<Json> {
+    let args = ["-X", "POST", "-H", "Content-Type: application/json"];
+    // The body is passed on stdin so that keys in it do not show up in the process list.
+    curl_json(
+        &[&args[..], &["--data-binary", "@-", url]].concat(),
+        &body.to_string(),
+    )
+}
 
-    let recorder = Recorder::new(recorder_dir_path);
+/// Runs curl with `args` and `stdin` and returns the JSON it printed, or `null` if it printed
+/// nothing. API errors are returned as errors.
+pub(crate) fn curl_json(args: &[&str], stdin: &str) -> io::Result<Json> {
+    let mut child = Command::new(CURL)
+        .arg("-sS")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    child.stdin.take().unwrap().write_all(stdin.as_bytes())?;
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "curl exited with {}",
+            output.status
+        )));
+    }
 
-    recorder.prune_job_dirs()
+    if output.stdout.is_empty() {
+        return Ok(Json::Null);
+    }
+    let result: Json = serde_json::from_slice(&output.stdout)?;
+    if let Some(message) = result["error"]["message"].as_str() {
+        return Err(io::Error::other(message.to_owned()));
+    }
+    Ok(result)
 }
@@ -0,0 +1,148 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Duration, NaiveTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::disk_stat::DiskStat;
+use crate::display_time::DisplayTimezone;
+use crate::notify::{Event, Notification};
+use crate::recorder::{Job, JobState, Recorder};
+
+/// Jobs listed of each state; the rest are only counted.
+const MAX_LISTED_JOBS: usize = 20;
+
+/// What was sent last, kept in `$VAR_DIR/digest.json` so that the disk usage can be compared
+/// across restarts.
+#[derive(Debug, Serialize, Deserialize)]
+struct DigestRecord {
+    sent_at: DateTime<Utc>,
+    disk_used: Option<u64>,
+}
+
+/// A summary of the jobs that finished since the last one, sent once a day.
+pub struct Digest {
+    since: DateTime<Utc>,
+    succeeded: Vec<Job>,
+    failed: Vec<Job>,
+    disk_used: Option<u64>,
+    previous_disk_used: Option<u64>,
+}
+
+impl Digest {
+    /// Collects the jobs that succeeded or failed after `since`.
+    pub fn new(recorder: &Recorder, config: &Config, since: DateTime<Utc>) -> Digest {
+        let mut succeeded = vec![];
+        let mut failed = vec![];
+        for job in recorder.jobs() {
+            let finished = job
+                .state_record()
+                .and_then(|record| record.transitions.last().cloned())
+                .filter(|transition| transition.at > since);
+            match finished.map(|transition| transition.state) {
+                Some(JobState::Succeeded) => succeeded.push(job),
+                Some(JobState::Failed) => failed.push(job),
+                _ => {}
+            }
+        }
+        succeeded.sort_by(|a, b| a.id().cmp(b.id()));
+        failed.sort_by(|a, b| a.id().cmp(b.id()));
+
+        Digest {
+            since,
+            succeeded,
+            failed,
+            disk_used: DiskStat::new(config.jobs_dir()).map(|stat| stat.used),
+            previous_disk_used: read_record(config).and_then(|record| record.disk_used),
+        }
+    }
+
+    pub fn to_notification(&self, config: &Config) -> Notification {
+        let mut lines = vec![format!(
+            "Since {}:",
+            config.display_timezone.format(self.since)
+        )];
+        for (label, jobs) in &[("Succeeded", &self.succeeded), ("Failed", &self.failed)] {
+            lines.push(String::new());
+            lines.push(format!("{} ({})", label, jobs.len()));
+            for job in jobs.iter().take(MAX_LISTED_JOBS) {
+                let url = config.job_url(job.id());
+                match job.title() {
+                    Some(title) => lines.push(format!("- {} {}", title, url)),
+                    None => lines.push(format!("- {}", url)),
+                }
+            }
+            if jobs.len() > MAX_LISTED_JOBS {
+                lines.push(format!("- and {} more", jobs.len() - MAX_LISTED_JOBS));
+            }
+        }
+        if let Some(used) = self.disk_used {
+            lines.push(String::new());
+            let change = match self.previous_disk_used {
+                Some(previous) if used >= previous => {
+                    format!(" (+{})", config.byte_units.humanize(used - previous))
+                }
+                Some(previous) => format!(" (-{})", config.byte_units.humanize(previous - used)),
+                None => String::new(),
+            };
+            lines.push(format!(
+                "Disk used: {}{}",
+                config.byte_units.humanize(used),
+                change
+            ));
+        }
+
+        Notification {
+            event: Event::Digest,
+            title: format!(
+                "Daily digest: {} succeeded, {} failed",
+                self.succeeded.len(),
+                self.failed.len()
+            ),
+            message: lines.join("\n"),
+            url: None,
+        }
+    }
+
+    /// Remembers that the digest was sent, and the disk usage to compare the next one with.
+    pub fn save(&self, config: &Config, sent_at: DateTime<Utc>) -> io::Result<()> {
+        let record = DigestRecord {
+            sent_at,
+            disk_used: self.disk_used,
+        };
+        fs::write(record_path(config), serde_json::to_vec(&record)?)
+    }
+}
+
+/// When the last digest was sent, or a day before `now` if none has been.
+pub fn last_sent_at(config: &Config, now: DateTime<Utc>) -> DateTime<Utc> {
+    read_record(config)
+        .map(|record| record.sent_at)
+        .unwrap_or_else(|| now - Duration::days(1))
+}
+
+/// The first time after `now` that is `at` in `timezone`.
+pub fn next_time(at: NaiveTime, timezone: DisplayTimezone, now: DateTime<Utc>) -> DateTime<Utc> {
+    let offset = timezone.offset(now);
+    let local_now = now.with_timezone(&offset);
+    let mut date = local_now.date_naive();
+    if local_now.time() >= at {
+        date = date.succ_opt().unwrap_or(date);
+    }
+    offset
+        .from_local_datetime(&date.and_time(at))
+        .single()
+        .map(|time| time.with_timezone(&Utc))
+        .unwrap_or_else(|| now + Duration::days(1))
+}
+
+fn record_path(config: &Config) -> PathBuf {
+    config.var_dir.join("digest.json")
+}
+
+fn read_record(config: &Config) -> Option<DigestRecord> {
+    let json = fs::read(record_path(config)).ok()?;
+    serde_json::from_slice(&json).ok()
+}
@@ -1,36 +1,188 @@
-use std::path::Path;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
+use serde::Serialize;
+
+use crate::platform;
+
+#[derive(Clone, Debug, Serialize)]
 pub struct DiskStat {
     pub available: u64,
     pub total: u64,
     pub used: u64,
+    /// Free inodes, if the file system has a fixed number of them. Archives of many small files,
+    /// such as thumbnails, may run out of inodes before space.
+    pub inodes_available: Option<u64>,
+    pub inodes_total: Option<u64>,
 }
 
 impl DiskStat {
     pub fn new<P: AsRef<Path>>(path: P) -> Option<DiskStat> {
-        let path = std::ffi::CString::new(path.as_ref().to_str()?).ok()?;
-        let mut stat = unsafe { std::mem::zeroed() };
+        let space = platform::disk_space(path.as_ref())?;
+        let used = space.total.checked_sub(space.available)?;
+
+        Some(DiskStat {
+            available: space.available,
+            total: space.total,
+            used,
+            inodes_available: space.inodes.as_ref().map(|inodes| inodes.available),
+            inodes_total: space.inodes.as_ref().map(|inodes| inodes.total),
+        })
+    }
+}
+
+/// The stat of a disk, taken again once it is older than a TTL, so that pages, stats and size
+/// checks don't each ask the file system.
+pub struct DiskStatCache {
+    path: PathBuf,
+    ttl: Duration,
+    cached: Mutex<Option<(Instant, Option<DiskStat>)>>,
+}
+
+impl DiskStatCache {
+    pub fn new(path: PathBuf, ttl: Duration) -> Self {
+        DiskStatCache {
+            path,
+            ttl,
+            cached: Mutex::new(None),
+        }
+    }
 
-        if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
-            return None;
+    /// Returns the cached stat, taking it again if it is older than the TTL.
+    pub fn get(&self) -> Option<DiskStat> {
+        let mut cached = self.cached.lock().unwrap();
+        match &*cached {
+            Some((taken_at, stat)) if taken_at.elapsed() < self.ttl => stat.clone(),
+            _ => {
+                let stat = DiskStat::new(&self.path);
+                *cached = Some((Instant::now(), stat.clone()));
+                stat
+            }
         }
+    }
 
-        let available = u64::from(stat.f_bavail).checked_mul(stat.f_frsize)?;
-        let total = u64::from(stat.f_blocks).checked_mul(stat.f_frsize)?;
-        let used = total.checked_sub(available)?;
+    /// Takes the stat again, e.g. on a background thread so that requests rarely have to.
+    pub fn refresh(&self) -> Option<DiskStat> {
+        let stat = DiskStat::new(&self.path);
+        *self.cached.lock().unwrap() = Some((Instant::now(), stat.clone()));
+        stat
+    }
+}
 
-        Some(DiskStat {
-            available,
-            total,
-            used,
+/// Remembers whether the available space of a disk is below a threshold.
+pub struct DiskMonitor {
+    threshold: u64,
+    low: Mutex<Option<DiskStat>>,
+}
+
+impl DiskMonitor {
+    pub fn new(threshold: u64) -> Self {
+        DiskMonitor {
+            threshold,
+            low: Mutex::new(None),
+        }
+    }
+
+    /// Checks the stat of the disk and returns it if the available space has just dropped below
+    /// the threshold.
+    pub fn check(&self, stat: DiskStat) -> Option<DiskStat> {
+        let is_low = stat.available < self.threshold;
+        let mut low = self.low.lock().unwrap();
+        let was_low = low.is_some();
+        *low = Some(stat.clone()).filter(|_| is_low);
+        Some(stat).filter(|_| is_low && !was_low)
+    }
+
+    /// Returns the stat of the last check if the space was low then.
+    pub fn low_stat(&self) -> Option<DiskStat> {
+        self.low.lock().unwrap().clone()
+    }
+}
+
+/// Parses a size such as `500MB`, `5GB` or `4GiB`, with the units of either `ByteUnits`.
+pub fn parse_byte_size(s: &str) -> Result<u64, String> {
+    const UNITS: [(&str, u64); 10] = [
+        ("PiB", 1 << 50),
+        ("TiB", 1 << 40),
+        ("GiB", 1 << 30),
+        ("MiB", 1 << 20),
+        ("KiB", 1 << 10),
+        ("PB", 1_000_000_000_000_000),
+        ("TB", 1_000_000_000_000),
+        ("GB", 1_000_000_000),
+        ("MB", 1_000_000),
+        ("KB", 1_000),
+    ];
+
+    let s = s.trim();
+    let (number, unit) = UNITS
+        .iter()
+        .find_map(|&(suffix, unit)| Some((s.strip_suffix(suffix)?, unit)))
+        .unwrap_or_else(|| (s.strip_suffix('B').unwrap_or(s), 1));
+    let number: f64 = number
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid size {:?} (expected e.g. 500MB or 5GB)", s))?;
+    if !number.is_finite() || number < 0.0 {
+        return Err(format!("invalid size {:?}", s));
+    }
+    Ok((number * unit as f64) as u64)
+}
+
+/// Which units sizes are shown in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ByteUnits {
+    /// Powers of 1000: KB, MB, GB, ...
+    #[default]
+    Decimal,
+    /// Powers of 1024: KiB, MiB, GiB, ..., as `df -h` and most file managers count.
+    Binary,
+}
+
+impl ByteUnits {
+    /// Formats a size for reading, e.g. `1.500GB` or `1.397GiB`.
+    pub fn humanize(self, size: u64) -> String {
+        let (base, units) = match self {
+            ByteUnits::Decimal => (1000_f64, ["B", "KB", "MB", "GB", "TB", "PB"]),
+            ByteUnits::Binary => (1024_f64, ["B", "KiB", "MiB", "GiB", "TiB", "PiB"]),
+        };
+
+        let size = size as f64;
+        // Zero has no logarithm.
+        let e = ((size.ln() / base.ln()).floor() as i32).clamp(0, (units.len() - 1) as i32);
+        format!("{:.3}{}", size / base.powi(e), units[e as usize])
+    }
+}
+
+impl FromStr for ByteUnits {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "decimal" => Ok(ByteUnits::Decimal),
+            "binary" => Ok(ByteUnits::Binary),
+            _ => Err(format!(
+                "unknown byte units {:?} (expected decimal or binary)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ByteUnits {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            ByteUnits::Decimal => "decimal",
+            ByteUnits::Binary => "binary",
         })
     }
 }
 
+/// Formats a size in decimal units, for logs and settings. What pages and notifications show
+/// follows `BYTE_UNITS` instead.
 pub fn humanize_byte_size(size: u64) -> String {
-    const UNITS: [&str; 6] = ["B", "KB", "MB", "GB", "TB", "PB"];
-
-    let size = size as f64;
-    let e = ((size.log10() / 3.0).floor() as i32).min((UNITS.len() - 1) as i32);
-    format!("{:.3}{}", size / 1000_f64.powi(e), UNITS[e as usize])
+    ByteUnits::Decimal.humanize(size)
 }
@@ -0,0 +1,83 @@
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, FixedOffset, Local, Utc};
+
+/// The time zone that pages show times in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DisplayTimezone {
+    #[default]
+    Utc,
+    /// The time zone of the machine the server runs on.
+    Local,
+    Fixed(FixedOffset),
+}
+
+impl DisplayTimezone {
+    /// The offset from UTC at `time`.
+    pub fn offset(&self, time: DateTime<Utc>) -> FixedOffset {
+        match self {
+            DisplayTimezone::Utc => FixedOffset::east_opt(0).unwrap(),
+            DisplayTimezone::Local => *time.with_timezone(&Local).offset(),
+            DisplayTimezone::Fixed(offset) => *offset,
+        }
+    }
+
+    /// Formats a time for reading, e.g. `2021-05-01 18:00 +09:00`.
+    pub fn format(&self, time: DateTime<Utc>) -> String {
+        const FORMAT: &str = "%Y-%m-%d %H:%M";
+        match self {
+            DisplayTimezone::Utc => format!("{} UTC", time.format(FORMAT)),
+            DisplayTimezone::Local => time
+                .with_timezone(&Local)
+                .format(&format!("{} %:z", FORMAT))
+                .to_string(),
+            DisplayTimezone::Fixed(offset) => time
+                .with_timezone(offset)
+                .format(&format!("{} %:z", FORMAT))
+                .to_string(),
+        }
+    }
+}
+
+impl FromStr for DisplayTimezone {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "unknown time zone {:?} (expected UTC, local, or an offset such as +09:00)",
+                s
+            )
+        };
+        match s {
+            "UTC" | "utc" | "Z" => return Ok(DisplayTimezone::Utc),
+            "local" => return Ok(DisplayTimezone::Local),
+            _ => {}
+        }
+        let sign = match s.chars().next() {
+            Some('+') => 1,
+            Some('-') => -1,
+            _ => return Err(invalid()),
+        };
+        let (hours, minutes) = s[1..].split_once(':').ok_or_else(invalid)?;
+        let hours: u32 = hours.parse().map_err(|_| invalid())?;
+        let minutes: u32 = minutes.parse().map_err(|_| invalid())?;
+        if hours >= 24 || minutes >= 60 {
+            return Err(invalid());
+        }
+        FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60) as i32)
+            .map(DisplayTimezone::Fixed)
+            .ok_or_else(invalid)
+    }
+}
+
+impl fmt::Display for DisplayTimezone {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DisplayTimezone::Utc => f.write_str("UTC"),
+            DisplayTimezone::Local => f.write_str("local"),
+            DisplayTimezone::Fixed(offset) => offset.fmt(f),
+        }
+    }
+}
@@ -0,0 +1,446 @@
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+
+use serde_json::{json, Value as Json};
+
+use crate::config::Config;
+use crate::disk_stat::{ByteUnits, DiskStatCache};
+use crate::platform;
+use crate::recorder::{self, Job, Recorder};
+
+/// Returns the version string reported by `command --version`.
+pub fn version(command: &str) -> io::Result<String> {
+    let output = Command::new(command).arg("--version").output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "{} --version exited with {}",
+            command, output.status
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
+
+/// Returns the executable `command` refers to, searching `PATH` unless it contains a directory.
+pub fn resolve(command: &str) -> Option<PathBuf> {
+    let command = Path::new(command);
+    if command.components().count() > 1 {
+        return Some(command.to_owned()).filter(|path| platform::is_executable(path));
+    }
+
+    let paths = env::var_os("PATH")?;
+    env::split_paths(&paths)
+        .map(|dir| dir.join(command))
+        .find(|path| platform::is_executable(path))
+}
+
+/// Checks a `-o` output template. It must name a file in the job dir, so separators, `..` and
+/// hidden names are rejected.
+pub fn validate_output_template(template: &str) -> Result<(), &'static str> {
+    if template.trim().is_empty() {
+        return Err("output template is empty");
+    }
+    if template.contains(['/', '\\', '\0', '\n']) {
+        return Err("output template must be a file name, not a path");
+    }
+    if template.starts_with('.') || template.starts_with('-') {
+        return Err("output template must not start with '.' or '-'");
+    }
+    Ok(())
+}
+
+/// Named args that the download form offers for a URL, e.g. `audio` for `-x --audio-format mp3`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Profile {
+    pub name: String,
+    pub args: Vec<String>,
+}
+
+impl Profile {
+    /// The profiles when `PROFILES` is not set: `default`, with the args the form started with
+    /// before there were profiles.
+    pub fn defaults() -> Vec<Profile> {
+        vec![Profile {
+            name: "default".to_owned(),
+            args: vec![
+                "--write-all-thumbnails".to_owned(),
+                "--write-info-json".to_owned(),
+            ],
+        }]
+    }
+
+    /// Parses profiles separated by `;`, each a name, `=`, and args separated by spaces, e.g.
+    /// `default=--write-info-json;audio=-x --audio-format mp3`.
+    pub fn parse_list(s: &str) -> Result<Vec<Profile>, String> {
+        let mut profiles: Vec<Profile> = vec![];
+        for profile in s.split(';').map(str::trim).filter(|s| !s.is_empty()) {
+            let (name, args) = profile
+                .split_once('=')
+                .ok_or_else(|| format!("profile {:?} must be name=args", profile))?;
+            let name = name.trim();
+            if name.is_empty() || profiles.iter().any(|profile| profile.name == name) {
+                return Err(format!("profile name {:?} is empty or repeated", name));
+            }
+            let args: Vec<&str> = args.split_whitespace().collect();
+            with_output_template(&args, None)
+                .map_err(|err| format!("profile {:?}: {}", name, err))?;
+            profiles.push(Profile {
+                name: name.to_owned(),
+                args: args.into_iter().map(str::to_owned).collect(),
+            });
+        }
+        if profiles.is_empty() {
+            return Err("no profiles".to_owned());
+        }
+        Ok(profiles)
+    }
+
+    /// Finds the profile that `args` were made with by `args_for`, with the URL.
+    pub fn find<'a, 'b>(
+        profiles: &'a [Profile],
+        args: &[&'b str],
+    ) -> Option<(&'a Profile, &'b str)> {
+        let (url, profile_args) = args.split_last()?;
+        profiles
+            .iter()
+            .find(|profile| profile.args == profile_args)
+            .map(|profile| (profile, *url))
+    }
+
+    /// The args that download `url` with the profile.
+    pub fn args_for<'a>(&'a self, url: &'a str) -> Vec<&'a str> {
+        let mut args: Vec<&str> = self.args.iter().map(String::as_str).collect();
+        args.push(url);
+        args
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.args.join(" "))
+    }
+}
+
+/// Returns `args` preceded by `-o template`. Output options in `args` are rejected so that the
+/// template cannot be bypassed, and so is `--cookies-from-browser`, which is only passed for the
+/// configured browser.
+pub fn with_output_template<'a>(
+    args: &[&'a str],
+    template: Option<&'a str>,
+) -> Result<Vec<&'a str>, &'static str> {
+    if args.iter().any(|arg| is_output_option(arg)) {
+        return Err("use the output template instead of -o or -P");
+    }
+    if args.iter().any(|arg| is_cookies_from_browser_option(arg)) {
+        return Err("use the browser cookies option instead of --cookies-from-browser");
+    }
+    let mut result = vec![];
+    if let Some(template) = template {
+        validate_output_template(template)?;
+        result.extend_from_slice(&["-o", template]);
+    }
+    result.extend_from_slice(args);
+    Ok(result)
+}
+
+/// Splits the output template off args built by `with_output_template`.
+pub fn split_output_template(args: &[String]) -> (Option<&str>, Vec<&str>) {
+    match args {
+        [option, template, rest @ ..] if is_output_option(option) => (
+            Some(template.as_str()),
+            rest.iter().map(String::as_str).collect(),
+        ),
+        _ => (None, args.iter().map(String::as_str).collect()),
+    }
+}
+
+/// Whether the arg sets where files are written, with `-o`/`--output` or `-P`/`--paths`. Also
+/// matches abbreviations such as `--outp`, which the downloader accepts, and the short options in
+/// a group such as `-wo`, up to the first one that takes a value.
+fn is_output_option(arg: &str) -> bool {
+    if let Some(name) = arg.strip_prefix("--") {
+        let name = name.split('=').next().unwrap_or(name);
+        return !name.is_empty()
+            && ["output", "paths"]
+                .iter()
+                .any(|long| long.starts_with(name) || name.starts_with(long));
+    }
+    // Short options that take a value, which is the rest of the group if it isn't empty.
+    const TAKES_VALUE: &str = "afrRSupNI";
+    match arg.strip_prefix('-') {
+        Some(group) => {
+            for c in group.chars() {
+                if c == 'o' || c == 'P' {
+                    return true;
+                }
+                if TAKES_VALUE.contains(c) {
+                    break;
+                }
+            }
+            false
+        }
+        None => false,
+    }
+}
+
+/// Also matches abbreviations such as `--cookies-from`, which the downloader accepts, but not
+/// `--cookies`.
+fn is_cookies_from_browser_option(arg: &str) -> bool {
+    let name = arg.split('=').next().unwrap_or(arg);
+    name.len() > "--cookies".len() && "--cookies-from-browser".starts_with(name)
+}
+
+/// Spawns a job running the configured update command.
+pub fn spawn_update(config: &Config, recorder: &Recorder) -> recorder::Result<Job> {
+    let command = config.downloader_update_command();
+    let (command, args) = command
+        .split_first()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "update command is empty"))?;
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    recorder.spawn_job(command, &args)
+}
+
+/// What to do with a download that is estimated not to fit in the free disk space.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SizeCheck {
+    /// Does not estimate sizes.
+    #[default]
+    Off,
+    /// Starts the download anyway, noting the estimate in the job's metadata.
+    Warn,
+    /// Refuses to start the download.
+    Reject,
+}
+
+impl FromStr for SizeCheck {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(SizeCheck::Off),
+            "warn" => Ok(SizeCheck::Warn),
+            "reject" => Ok(SizeCheck::Reject),
+            _ => Err(format!(
+                "unknown size check {:?} (expected off, warn, or reject)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for SizeCheck {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            SizeCheck::Off => "off",
+            SizeCheck::Warn => "warn",
+            SizeCheck::Reject => "reject",
+        })
+    }
+}
+
+pub struct SizeEstimate {
+    pub size: u64,
+    pub available: u64,
+    /// Units the sizes are shown in.
+    pub units: ByteUnits,
+}
+
+impl SizeEstimate {
+    pub fn fits(&self) -> bool {
+        self.size <= self.available
+    }
+
+    /// Returns the estimate as job metadata.
+    pub fn to_metadata(&self) -> Json {
+        let mut metadata = json!({
+            "estimated_size": self.units.humanize(self.size),
+            "available_space": self.units.humanize(self.available),
+        });
+        if !self.fits() {
+            metadata["size_warning"] = json!("the download may not fit in the free disk space");
+        }
+        metadata
+    }
+}
+
+impl fmt::Display for SizeEstimate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "estimated size {} exceeds the available {}",
+            self.units.humanize(self.size),
+            self.units.humanize(self.available)
+        )
+    }
+}
+
+/// Estimates the size of a download, and rejects it if it doesn't fit and `SIZE_CHECK` is
+/// `reject`. Estimation errors are logged and let the download through.
+pub fn check_size(
+    config: &Config,
+    disk_stats: &DiskStatCache,
+    args: &[&str],
+) -> Result<Option<SizeEstimate>, String> {
+    let reject = config.size_check == SizeCheck::Reject;
+    match estimate_size(config, disk_stats, args) {
+        Ok(Some(estimate)) if reject && !estimate.fits() => Err(estimate.to_string()),
+        Ok(estimate) => Ok(estimate),
+        Err(err) => {
+            println!("size estimation failed: {}", err);
+            Ok(None)
+        }
+    }
+}
+
+/// Estimates the size of a download by asking the downloader for its info JSON (`-J`), if
+/// `SIZE_CHECK` is enabled. Returns `None` if disabled or the downloader reports no sizes.
+/// Runs the downloader with the job sandbox and user, so it may take a while.
+pub fn estimate_size(
+    config: &Config,
+    disk_stats: &DiskStatCache,
+    args: &[&str],
+) -> io::Result<Option<SizeEstimate>> {
+    if config.size_check == SizeCheck::Off {
+        return Ok(None);
+    }
+    let available = match disk_stats.get() {
+        Some(stat) => stat.available,
+        None => return Ok(None),
+    };
+
+    let info = match info_json(config, args)? {
+        Some(info) => info,
+        None => return Ok(None),
+    };
+    Ok(info_size(&info).map(|size| SizeEstimate {
+        size,
+        available,
+        units: config.byte_units,
+    }))
+}
+
+/// An entry of a channel or playlist.
+pub struct PlaylistEntry {
+    /// Identifies the entry as a line of a youtube-dl download archive does, e.g.
+    /// `youtube dQw4w9WgXcQ`.
+    pub key: String,
+    pub url: String,
+    pub title: Option<String>,
+}
+
+/// Lists the entries of a channel or playlist without looking into each (`--flat-playlist`).
+pub fn playlist_entries(config: &Config, url: &str) -> io::Result<Vec<PlaylistEntry>> {
+    let info = info_json(config, &["--flat-playlist", url])?
+        .ok_or_else(|| io::Error::other(format!("{} could not list {}", config.downloader, url)))?;
+    let entries = match info["entries"].as_array() {
+        Some(entries) => entries,
+        None => return Ok(vec![]),
+    };
+    let extractor = info["extractor_key"].as_str().unwrap_or_default();
+    Ok(entries
+        .iter()
+        .filter_map(|entry| {
+            let url = entry["webpage_url"].as_str().or(entry["url"].as_str())?;
+            let key = match entry["id"].as_str() {
+                Some(id) => {
+                    let extractor = entry["ie_key"].as_str().unwrap_or(extractor);
+                    format!("{} {}", extractor.to_lowercase(), id)
+                }
+                None => url.to_owned(),
+            };
+            Some(PlaylistEntry {
+                key,
+                url: url.to_owned(),
+                title: entry["title"].as_str().map(str::to_owned),
+            })
+        })
+        .collect())
+}
+
+/// Asks the downloader for the info JSON of `args` (`-J`), with the job sandbox and user.
+/// Returns `None` if the downloader fails.
+fn info_json(config: &Config, args: &[&str]) -> io::Result<Option<Json>> {
+    let dir = config
+        .var_dir
+        .join("tmp")
+        .join(ulid::Ulid::new().to_string());
+    fs::create_dir_all(&dir)?;
+    let output = fs::canonicalize(&dir).and_then(|dir| {
+        let mut args = args.to_vec();
+        args.insert(0, "-J");
+        let mut command = config.sandbox.command(&dir, &config.downloader, &args);
+        platform::set_user(&mut command, &dir, config.job_uid, config.job_gid)?;
+        command.stderr(Stdio::null()).output()
+    });
+    fs::remove_dir_all(&dir).ok();
+    let output = output?;
+
+    if !output.status.success() {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_slice(&output.stdout)?))
+}
+
+/// Sums the sizes of the formats to be downloaded, and of the entries of a playlist.
+fn info_size(info: &Json) -> Option<u64> {
+    let size = |format: &Json| {
+        format["filesize"]
+            .as_u64()
+            .or_else(|| format["filesize_approx"].as_f64().map(|size| size as u64))
+    };
+
+    if let Some(entries) = info["entries"].as_array() {
+        return entries.iter().map(info_size).sum();
+    }
+    if let Some(formats) = info["requested_formats"].as_array() {
+        return formats.iter().map(size).sum();
+    }
+    size(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_options_are_rejected_however_they_are_written() {
+        for arg in &[
+            "-o",
+            "-ofoo.%(ext)s",
+            "--output",
+            "--output=foo",
+            "--outp",
+            "--out=foo",
+            "--output-na-placeholder",
+            "-P",
+            "-P/tmp",
+            "--paths",
+            "--pat=/tmp",
+            "-wo",
+            "-qP/tmp",
+        ] {
+            assert!(
+                with_output_template(&[arg, "https://example.com/"], None).is_err(),
+                "{}",
+                arg
+            );
+        }
+    }
+
+    #[test]
+    fn other_options_are_kept_after_the_template() {
+        let args = ["-x", "-fworst", "--print", "--part", "https://example.com/"];
+        let result = with_output_template(&args, Some("%(id)s.%(ext)s")).unwrap();
+        assert_eq!(result[..2], ["-o", "%(id)s.%(ext)s"]);
+        assert_eq!(result[2..], args);
+
+        let owned: Vec<String> = result.iter().map(|&arg| arg.to_owned()).collect();
+        let (template, rest) = split_output_template(&owned);
+        assert_eq!(template, Some("%(id)s.%(ext)s"));
+        assert_eq!(rest, args);
+    }
+}
@@ -0,0 +1,124 @@
+use std::fs;
+use std::str::FromStr;
+
+use serde::Serialize;
+
+use crate::recorder::{Job, Recorder};
+
+/// Formats the job list can be exported in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(ExportFormat::Csv),
+            "json" => Ok(ExportFormat::Json),
+            _ => Err(format!("unknown format {:?} (expected csv or json)", s)),
+        }
+    }
+}
+
+/// A line of the exported job list.
+#[derive(Debug, Serialize)]
+pub struct JobRow {
+    pub id: String,
+    pub date: Option<String>,
+    pub title: Option<String>,
+    pub url: Option<String>,
+    pub status: Option<String>,
+    /// Total bytes of the job's files.
+    pub size: u64,
+}
+
+const COLUMNS: &[&str] = &["id", "date", "title", "url", "status", "size"];
+
+impl JobRow {
+    fn new(job: &Job) -> JobRow {
+        let info = job.info();
+        let metadata = job.metadata();
+        let field = |key: &str| {
+            info.as_ref()
+                .and_then(|info| info[key].as_str())
+                .or_else(|| {
+                    metadata
+                        .as_ref()
+                        .and_then(|metadata| metadata[key].as_str())
+                })
+                .map(str::to_owned)
+        };
+        let url = field("webpage_url").or_else(|| {
+            let invocation = job.invocation()?;
+            invocation["args"]
+                .as_array()?
+                .iter()
+                .filter_map(|arg| arg.as_str())
+                .find(|arg| arg.starts_with("http://") || arg.starts_with("https://"))
+                .map(str::to_owned)
+        });
+        let size = job
+            .os_file_names()
+            .iter()
+            .filter_map(|name| fs::metadata(job.path().join(name)).ok())
+            .map(|metadata| metadata.len())
+            .sum();
+
+        JobRow {
+            id: job.id().to_string(),
+            date: job.created_at().map(|created_at| created_at.to_rfc3339()),
+            title: job.title(),
+            url,
+            status: job.state().map(|state| state.as_str().to_owned()),
+            size,
+        }
+    }
+
+    fn csv_fields(&self) -> Vec<String> {
+        vec![
+            self.id.clone(),
+            self.date.clone().unwrap_or_default(),
+            self.title.clone().unwrap_or_default(),
+            self.url.clone().unwrap_or_default(),
+            self.status.clone().unwrap_or_default(),
+            self.size.to_string(),
+        ]
+    }
+}
+
+/// Returns rows for all jobs, oldest first.
+pub fn job_rows(recorder: &Recorder) -> Vec<JobRow> {
+    let mut jobs = recorder.jobs();
+    jobs.sort_by(|a, b| a.id().cmp(b.id()));
+    jobs.iter().map(JobRow::new).collect()
+}
+
+/// Formats rows as CSV with a header line, as described in RFC 4180.
+pub fn to_csv(rows: &[JobRow]) -> String {
+    fn escape(field: &str) -> String {
+        // Titles come from the sites, so keep spreadsheets from evaluating them as formulas.
+        let field = if field.starts_with(['=', '+', '-', '@']) {
+            format!("'{}", field)
+        } else {
+            field.to_owned()
+        };
+        if field.contains([',', '"', '\r', '\n']) {
+            format!("\"{}\"", field.replace('"', "\"\""))
+        } else {
+            field
+        }
+    }
+
+    let mut csv = COLUMNS.join(",");
+    csv.push_str("\r\n");
+    for row in rows {
+        let fields: Vec<String> = row.csv_fields().iter().map(|field| escape(field)).collect();
+        csv.push_str(&fields.join(","));
+        csv.push_str("\r\n");
+    }
+    csv
+}
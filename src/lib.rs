@@ -0,0 +1,24 @@
+//! The job machinery behind the vrec server, for programs that manage downloads without running
+//! it, such as a TUI client or a batch importer. `Config::from_env` reads the same settings as the
+//! server, and `Config::recorder` opens its work dir.
+
+pub mod cli;
+pub mod config;
+mod digest;
+mod disk_stat;
+pub mod display_time;
+pub mod downloader;
+mod export;
+pub mod notify;
+mod platform;
+mod postprocess;
+pub mod recorder;
+mod stats;
+mod subscription;
+mod subtitles;
+mod telegram;
+pub mod web;
+pub mod worker;
+
+pub use crate::config::Config;
+pub use crate::recorder::{Job, JobId, JobState, Recorder};
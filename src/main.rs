@@ -1,13 +1,11 @@
-mod cli;
-mod disk_stat;
-mod recorder;
-mod web;
+use vrec::{cli, web, worker};
 
 #[actix_rt::main]
 async fn main() -> std::io::Result<()> {
-    if std::env::args().nth(1).map(|s| s == "--gc") == Some(true) {
-        cli::gc()
-    } else {
-        web::start().await
+    match std::env::args().nth(1).as_deref() {
+        Some("--gc") => cli::gc(),
+        Some("update-downloader") => cli::update_downloader(),
+        Some("worker") => worker::run(),
+        _ => web::start().await,
     }
 }
@@ -0,0 +1,23 @@
+//! Process and filesystem functions that differ between operating systems.
+
+#[cfg(unix)]
+pub use self::unix::*;
+#[cfg(windows)]
+pub use self::windows::*;
+
+#[cfg(unix)]
+mod unix;
+#[cfg(windows)]
+mod windows;
+
+pub struct DiskSpace {
+    pub available: u64,
+    pub total: u64,
+    /// Inodes, if the file system has a fixed number of them.
+    pub inodes: Option<Inodes>,
+}
+
+pub struct Inodes {
+    pub available: u64,
+    pub total: u64,
+}
@@ -0,0 +1,153 @@
+use std::borrow::Cow;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::os::unix::net::UnixDatagram;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::Path;
+use std::process::{Command, ExitStatus};
+
+use super::{DiskSpace, Inodes};
+
+pub fn is_running(pid: u32) -> bool {
+    unsafe { libc::kill(pid as libc::pid_t, 0) == 0 }
+}
+
+/// Sends SIGINT, on which youtube-dl stops and keeps what it has downloaded.
+pub fn interrupt(pid: u32) -> io::Result<()> {
+    if unsafe { libc::kill(pid as libc::pid_t, libc::SIGINT) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+pub fn disk_space(path: &Path) -> Option<DiskSpace> {
+    let path = std::ffi::CString::new(path.to_str()?).ok()?;
+    let mut stat = unsafe { std::mem::zeroed() };
+
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+
+    #[allow(clippy::useless_conversion)]
+    let available = u64::from(stat.f_bavail).checked_mul(stat.f_frsize)?;
+    #[allow(clippy::useless_conversion)]
+    let total = u64::from(stat.f_blocks).checked_mul(stat.f_frsize)?;
+
+    // Some file systems, such as btrfs, make inodes as needed and report none.
+    #[allow(clippy::useless_conversion)]
+    let inodes = Some(Inodes {
+        available: u64::from(stat.f_favail),
+        total: u64::from(stat.f_files),
+    })
+    .filter(|inodes| inodes.total > 0);
+
+    Some(DiskSpace {
+        available,
+        total,
+        inodes,
+    })
+}
+
+pub fn is_executable(path: &Path) -> bool {
+    path.metadata()
+        .map(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+/// Returns the device and inode numbers, which hard links to the same file share.
+pub fn file_id(metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    Some((metadata.dev(), metadata.ino()))
+}
+
+pub fn link_count(metadata: &fs::Metadata) -> u64 {
+    metadata.nlink()
+}
+
+pub fn is_superuser() -> bool {
+    unsafe { libc::geteuid() == 0 }
+}
+
+/// Sends `state`, e.g. `READY=1`, to the socket systemd passes in `NOTIFY_SOCKET`. Returns false
+/// if the process was not started by systemd with notifications enabled.
+pub fn notify_systemd(state: &str) -> io::Result<bool> {
+    let path = match std::env::var_os("NOTIFY_SOCKET") {
+        Some(path) => path,
+        None => return Ok(false),
+    };
+    let socket = UnixDatagram::unbound()?;
+    match path.as_bytes().strip_prefix(b"@") {
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            socket.send_to_addr(state.as_bytes(), &addr)?;
+        }
+        #[cfg(not(target_os = "linux"))]
+        Some(_) => return Err(io::ErrorKind::Unsupported.into()),
+        None => {
+            socket.send_to(state.as_bytes(), &path)?;
+        }
+    }
+    Ok(true)
+}
+
+/// Returns true if the process was killed by a signal rather than exiting on its own.
+pub fn is_signaled(status: &ExitStatus) -> bool {
+    status.signal().is_some()
+}
+
+/// Makes `command` run as the given user and group, and hands `dir` over to them.
+pub fn set_user(
+    command: &mut Command,
+    dir: &Path,
+    uid: Option<u32>,
+    gid: Option<u32>,
+) -> io::Result<()> {
+    if uid.is_some() || gid.is_some() {
+        std::os::unix::fs::chown(dir, uid, gid)?;
+    }
+    if let Some(uid) = uid {
+        command.uid(uid);
+    }
+    if let Some(gid) = gid {
+        command.gid(gid);
+    }
+    Ok(())
+}
+
+/// Writes a file that only its owner can read, which is the given user and group if set.
+pub fn write_private(
+    path: &Path,
+    contents: &[u8],
+    uid: Option<u32>,
+    gid: Option<u32>,
+) -> io::Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let mut f = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    // The mode only applies to new files.
+    f.set_permissions(fs::Permissions::from_mode(0o600))?;
+    if uid.is_some() || gid.is_some() {
+        std::os::unix::fs::chown(path, uid, gid)?;
+    }
+    f.write_all(contents)
+}
+
+/// The bytes of a file name, for putting it in a URL whether or not it is UTF-8.
+pub fn file_name_bytes(name: &OsStr) -> Cow<'_, [u8]> {
+    Cow::Borrowed(name.as_bytes())
+}
+
+/// The file name with the bytes given by `file_name_bytes`.
+pub fn file_name_from_bytes(bytes: Vec<u8>) -> OsString {
+    OsString::from_vec(bytes)
+}
@@ -0,0 +1,140 @@
+use std::borrow::Cow;
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::{Command, ExitStatus};
+
+use sysinfo::{DiskExt, Pid, PidExt, ProcessExt, ProcessRefreshKind, System, SystemExt};
+
+use super::DiskSpace;
+
+pub fn is_running(pid: u32) -> bool {
+    let mut system = System::new();
+    system.refresh_process_specifics(Pid::from_u32(pid), ProcessRefreshKind::new())
+}
+
+/// Terminates the process, as there is no SIGINT to let it stop on its own.
+pub fn interrupt(pid: u32) -> io::Result<()> {
+    let pid = Pid::from_u32(pid);
+    let mut system = System::new();
+    system.refresh_process_specifics(pid, ProcessRefreshKind::new());
+    match system.process(pid) {
+        Some(process) if process.kill() => Ok(()),
+        _ => Err(io::Error::other("could not terminate process")),
+    }
+}
+
+/// Returns the space of the disk whose mount point contains `path`.
+pub fn disk_space(path: &Path) -> Option<DiskSpace> {
+    // Not canonicalized, as that would add a `\\?\` prefix that mount points don't have.
+    let path = env::current_dir().ok()?.join(path);
+
+    let mut system = System::new();
+    system.refresh_disks_list();
+    system
+        .disks()
+        .iter()
+        .filter(|disk| path.starts_with(disk.mount_point()))
+        .max_by_key(|disk| disk.mount_point().as_os_str().len())
+        .map(|disk| DiskSpace {
+            available: disk.available_space(),
+            total: disk.total_space(),
+            // NTFS has no fixed number of inodes.
+            inodes: None,
+        })
+}
+
+pub fn is_executable(path: &Path) -> bool {
+    path.is_file() || path.with_extension("exe").is_file()
+}
+
+/// Returns `None`, as file indexes are not available on stable Rust.
+pub fn file_id(_metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// Returns 1, as link counts are not available on stable Rust.
+pub fn link_count(_metadata: &fs::Metadata) -> u64 {
+    1
+}
+
+pub fn is_superuser() -> bool {
+    false
+}
+
+/// Returns false, as there is no systemd.
+pub fn notify_systemd(_state: &str) -> io::Result<bool> {
+    Ok(false)
+}
+
+pub fn is_signaled(_status: &ExitStatus) -> bool {
+    false
+}
+
+pub fn set_user(
+    _command: &mut Command,
+    _dir: &Path,
+    uid: Option<u32>,
+    gid: Option<u32>,
+) -> io::Result<()> {
+    if uid.is_some() || gid.is_some() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "running jobs as another user is not supported on this platform",
+        ));
+    }
+    Ok(())
+}
+
+/// Writes a file that only the current user can read, by replacing its inherited ACL with one
+/// that grants the user alone full control. The ACL is set while the file is still empty.
+pub fn write_private(
+    path: &Path,
+    contents: &[u8],
+    uid: Option<u32>,
+    gid: Option<u32>,
+) -> io::Result<()> {
+    if uid.is_some() || gid.is_some() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "running jobs as another user is not supported on this platform",
+        ));
+    }
+    let user = match (env::var("USERDOMAIN"), env::var("USERNAME")) {
+        (Ok(domain), Ok(name)) => format!("{}\\{}", domain, name),
+        (Err(_), Ok(name)) => name,
+        _ => return Err(io::Error::other("USERNAME is not set")),
+    };
+    fs::write(path, b"")?;
+    let status = Command::new("icacls")
+        .arg(path)
+        .args(["/inheritance:r", "/grant:r"])
+        .arg(format!("{}:F", user))
+        .arg("/q")
+        .status()?;
+    if !status.success() {
+        fs::remove_file(path).ok();
+        return Err(io::Error::other(format!(
+            "icacls could not restrict {} to {}",
+            path.display(),
+            user
+        )));
+    }
+    fs::write(path, contents)
+}
+
+/// The bytes of a file name, for putting it in a URL. Names that are not valid UTF-16 can't be
+/// given exactly.
+pub fn file_name_bytes(name: &OsStr) -> Cow<'_, [u8]> {
+    match name.to_string_lossy() {
+        Cow::Borrowed(name) => Cow::Borrowed(name.as_bytes()),
+        Cow::Owned(name) => Cow::Owned(name.into_bytes()),
+    }
+}
+
+/// The file name with the bytes given by `file_name_bytes`.
+pub fn file_name_from_bytes(bytes: Vec<u8>) -> OsString {
+    String::from_utf8_lossy(&bytes).into_owned().into()
+}
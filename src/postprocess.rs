@@ -0,0 +1,261 @@
+use std::fmt;
+use std::fs;
+use std::io::{self, BufReader};
+use std::str::FromStr;
+
+use serde_json::Value as Json;
+
+use crate::recorder::{Job, PostStep};
+
+const FFMPEG: &str = "ffmpeg";
+const ATOMIC_PARSLEY: &str = "AtomicParsley";
+
+/// Optional processing of downloaded files, run after the downloader succeeds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PostProcessor {
+    /// Embeds the title, uploader, upload date and thumbnail from the `.info.json` file into the
+    /// media file. Needs `--write-info-json`, and `--write-thumbnail` for the thumbnail.
+    EmbedMetadata,
+    /// Splits the media file into one file per chapter listed in the `.info.json` file, keeping
+    /// the original.
+    SplitChapters,
+}
+
+impl PostProcessor {
+    pub const ALL: &'static [PostProcessor] =
+        &[PostProcessor::EmbedMetadata, PostProcessor::SplitChapters];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PostProcessor::EmbedMetadata => "embed_metadata",
+            PostProcessor::SplitChapters => "split_chapters",
+        }
+    }
+
+    pub fn step(self) -> PostStep {
+        let run = match self {
+            PostProcessor::EmbedMetadata => embed_metadata,
+            PostProcessor::SplitChapters => split_chapters,
+        };
+        PostStep {
+            name: self.as_str().to_owned(),
+            run: Box::new(run),
+        }
+    }
+}
+
+/// Rebuilds the post steps listed in a job's invocation.
+pub fn steps_from_invocation(invocation: &Json) -> Vec<PostStep> {
+    invocation["post"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|name| name.as_str()?.parse::<PostProcessor>().ok())
+        .map(PostProcessor::step)
+        .collect()
+}
+
+impl FromStr for PostProcessor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        PostProcessor::ALL
+            .iter()
+            .copied()
+            .find(|processor| processor.as_str() == s)
+            .ok_or_else(|| format!("unknown post-processor {:?}", s))
+    }
+}
+
+impl fmt::Display for PostProcessor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A downloaded media file along with the files youtube-dl wrote next to it.
+struct Download {
+    media: String,
+    thumbnail: Option<String>,
+    info: Json,
+}
+
+/// Finds downloads by their `<name>.info.json` files.
+fn downloads(job: &Job) -> io::Result<Vec<Download>> {
+    let mut file_names = job.file_names();
+    file_names.sort();
+
+    let mut downloads = vec![];
+    for info_name in &file_names {
+        let base = match info_name.strip_suffix(".info.json") {
+            Some(base) => base,
+            None => continue,
+        };
+        // `<name>.<ext>` for media, and also `<name>_<id>.<ext>` for thumbnails.
+        let related = |type_: mime::Name, is_suffix: fn(&str) -> bool| {
+            file_names.iter().find(|name| {
+                name.strip_prefix(base).is_some_and(is_suffix)
+                    && mime_guess::from_path(name)
+                        .first()
+                        .is_some_and(|mime| mime.type_() == type_)
+            })
+        };
+        let is_ext = |suffix: &str| suffix.starts_with('.') && !suffix[1..].contains('.');
+        let media = match related(mime::VIDEO, is_ext).or_else(|| related(mime::AUDIO, is_ext)) {
+            Some(media) => media.clone(),
+            None => continue,
+        };
+        let thumbnail = related(mime::IMAGE, |suffix| {
+            suffix.starts_with('.') || suffix.starts_with('_')
+        })
+        .cloned();
+        let f = fs::File::open(job.path().join(info_name))?;
+        let info = serde_json::from_reader(BufReader::new(f))?;
+        downloads.push(Download {
+            media,
+            thumbnail,
+            info,
+        });
+    }
+
+    if downloads.is_empty() {
+        return Err(io::Error::other(
+            "no media file with an .info.json file (download with --write-info-json)",
+        ));
+    }
+    Ok(downloads)
+}
+
+fn embed_metadata(job: &Job) -> io::Result<()> {
+    for download in downloads(job)? {
+        let Download {
+            media,
+            thumbnail,
+            info,
+        } = download;
+        let ext = media.rsplit('.').next().unwrap_or_default().to_lowercase();
+        // AtomicParsley and ffmpeg's MP4 muxer only take JPEG and PNG cover art.
+        let thumbnail = thumbnail.filter(|name| {
+            let name = name.to_lowercase();
+            name.ends_with(".jpg") || name.ends_with(".jpeg") || name.ends_with(".png")
+        });
+
+        let mut metadata = vec![];
+        let mut add = |key: &str, value: Option<String>| {
+            if let Some(value) = value {
+                metadata.push("-metadata".to_owned());
+                metadata.push(format!("{}={}", key, value));
+            }
+        };
+        let field = |key: &str| info[key].as_str().map(str::to_owned);
+        add("title", field("title"));
+        add("artist", field("uploader"));
+        add("comment", field("webpage_url"));
+        add(
+            "date",
+            field("upload_date")
+                .filter(|date| date.len() == 8)
+                .map(|date| format!("{}-{}-{}", &date[0..4], &date[4..6], &date[6..8])),
+        );
+
+        let mut args: Vec<String> = vec!["-loglevel", "error", "-y", "-i", &media]
+            .into_iter()
+            .map(str::to_owned)
+            .collect();
+        let mut artwork = None;
+        match (thumbnail, ext.as_str()) {
+            (Some(thumbnail), "mkv" | "webm" | "mka") => {
+                let mimetype = mime_guess::from_path(&thumbnail).first_or_octet_stream();
+                args.extend(["-map", "0", "-attach", &thumbnail].map(str::to_owned));
+                args.push("-metadata:s:t".to_owned());
+                args.push(format!("mimetype={}", mimetype));
+            }
+            (Some(thumbnail), "mp3" | "m4a") => {
+                args.extend(["-i", &thumbnail, "-map", "0:a", "-map", "1"].map(str::to_owned));
+                args.extend(["-disposition:v:0", "attached_pic"].map(str::to_owned));
+            }
+            (thumbnail, _) => {
+                args.extend(["-map", "0"].map(str::to_owned));
+                if ext == "mp4" || ext == "m4v" || ext == "mov" {
+                    artwork = thumbnail;
+                }
+            }
+        }
+        args.extend(["-c", "copy"].map(str::to_owned));
+        args.extend(metadata);
+
+        // Keep the output hidden until it replaces the original.
+        let tmp_name = format!(".embed-metadata.{}", ext);
+        args.push(tmp_name.clone());
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        job.run_command(FFMPEG, &args)?;
+        fs::rename(job.path().join(&tmp_name), job.path().join(&media))?;
+
+        if let Some(artwork) = artwork {
+            job.run_command(
+                ATOMIC_PARSLEY,
+                &[&media, "--artwork", &artwork, "--overWrite"],
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn split_chapters(job: &Job) -> io::Result<()> {
+    for Download { media, info, .. } in downloads(job)? {
+        let chapters = match info["chapters"].as_array() {
+            Some(chapters) => chapters,
+            None => continue,
+        };
+        let (stem, ext) = media.rsplit_once('.').unwrap_or((&media, ""));
+
+        for (i, chapter) in chapters.iter().enumerate() {
+            let (start, end) = match (chapter["start_time"].as_f64(), chapter["end_time"].as_f64())
+            {
+                (Some(start), Some(end)) if start < end => (start, end),
+                _ => continue,
+            };
+            let title = sanitize_file_name(chapter["title"].as_str().unwrap_or_default());
+            let file_name = if title.is_empty() {
+                format!("{} - {:03}.{}", stem, i + 1, ext)
+            } else {
+                format!("{} - {:03} {}.{}", stem, i + 1, title, ext)
+            };
+            let (start, end) = (start.to_string(), end.to_string());
+            job.run_command(
+                FFMPEG,
+                &[
+                    "-loglevel",
+                    "error",
+                    "-y",
+                    "-i",
+                    &media,
+                    "-ss",
+                    &start,
+                    "-to",
+                    &end,
+                    "-map",
+                    "0",
+                    "-c",
+                    "copy",
+                    &file_name,
+                ],
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Makes a chapter title usable in a file name.
+fn sanitize_file_name(title: &str) -> String {
+    title
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | '\0' | '\n' | '\r' => '_',
+            c => c,
+        })
+        .take(100)
+        .collect::<String>()
+        .trim()
+        .to_owned()
+}
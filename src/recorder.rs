@@ -1,67 +1,678 @@
-use std::ffi::OsStr;
+use std::cmp::Ordering;
+use std::ffi::{OsStr, OsString};
 use std::fs;
 use std::io::{self, BufReader, Read, Write};
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Child, Command};
+use std::str::FromStr;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 
+use chrono::{DateTime, Utc};
+use mime::Mime;
+use rayon::prelude::*;
+use serde::{de, Deserialize, Deserializer, Serialize};
 use serde_json::{json, Value as Json};
 
+use crate::disk_stat::humanize_byte_size;
+use crate::{downloader, platform};
+
+pub use self::credentials::{Credential, CredentialVault};
+pub use self::diagnostics::{Diagnostics, Runtime};
+pub use self::domain::{DomainDelay, DomainLimit};
+pub use self::error::{RecorderError, Result};
+pub use self::failure::FailureReason;
+pub use self::filter::JobFilter;
+pub use self::index::{FileSummary, JobSummary};
+pub use self::lock::WorkDirLock;
+pub use self::probe::MediaInfo;
+pub use self::proxy::{Proxy, ProxyDomain, ProxyPool};
+pub use self::queue::ClaimedJob;
+pub use self::range::TimeRange;
+pub use self::reconcile::ReconcileReport;
+pub use self::retry::RetryPolicy;
+pub use self::sandbox::Sandbox;
+pub use self::state::{JobState, StateRecord};
+
+use self::chain::Chain;
+use self::coalesce::{CoalesceKey, Coalescer};
+use self::diagnostics::SupervisorRecord;
+use self::index::JobIndex;
+use self::queue::Queue;
+use self::scheduler::Scheduler;
+
+mod chain;
+mod coalesce;
+mod compact;
+mod credentials;
+mod dedup;
+mod diagnostics;
+mod domain;
+mod error;
+mod failure;
+mod filter;
+mod index;
+mod lock;
+mod probe;
+mod proxy;
+mod queue;
+mod range;
+mod reconcile;
+mod retry;
+mod sandbox;
+mod scheduler;
+mod state;
+
+/// Called after a job has moved to a new state.
+pub type Listener = Arc<dyn Fn(&Job, JobState) + Send + Sync>;
+
+/// Work done in the job dir after the command succeeds, such as post-processing downloaded files.
+/// The job fails if a step fails, and the remaining steps are skipped.
+pub struct PostStep {
+    pub name: String,
+    pub run: PostStepFn,
+}
+
+pub type PostStepFn = Box<dyn FnOnce(&Job) -> io::Result<()> + Send>;
+
+/// Per-job settings besides the command line.
+#[derive(Default)]
+pub struct JobOptions {
+    pub post_steps: Vec<PostStep>,
+    /// Waits until then before running, e.g. for a live stream that has not started yet.
+    pub start_at: Option<DateTime<Utc>>,
+    /// Interrupts the command after this long, e.g. to record part of a live stream. The job
+    /// succeeds with whatever was written by then.
+    pub time_limit: Option<Duration>,
+    /// Identifies the request that submitted the job, for finding it in the logs.
+    pub request_id: Option<String>,
+    /// Identifies the submission to the client, so that a repeated one can be answered with the
+    /// job instead of a new one. See `Recorder::job_by_idempotency_key`.
+    pub idempotency_key: Option<String>,
+    /// Passes the cookies of the browser set with `Recorder::set_cookies_from_browser`.
+    pub browser_cookies: bool,
+    /// Waits until this job has succeeded before running, e.g. to download one thing after
+    /// another. The job is cancelled if that one fails or is cancelled instead.
+    pub after: Option<JobId>,
+}
+
+#[derive(Clone)]
 pub struct Recorder {
     work_dir: WorkDir,
+    trash_dir: Option<WorkDir>,
+    listener: Option<Listener>,
+    spawn_options: SpawnOptions,
+    queue: Queue,
+    index: Arc<JobIndex>,
+    /// Proxies for jobs that run the command.
+    proxy_pool: Option<Arc<(String, ProxyPool)>>,
+    /// The browser whose cookies jobs that run the command may use.
+    cookies_from_browser: Option<Arc<(String, String)>>,
+    /// Site credentials for jobs that run the command.
+    credential_vault: Option<Arc<(String, CredentialVault)>>,
+    coalescer: Option<Arc<Coalescer>>,
+    retry_policy: RetryPolicy,
+    scheduler: Scheduler,
+    chain: Chain,
+}
+
+/// How job commands are run.
+#[derive(Clone, Copy, Debug, Default)]
+struct SpawnOptions {
+    sandbox: Sandbox,
+    uid: Option<u32>,
+    gid: Option<u32>,
 }
 
 impl Recorder {
     pub fn new(path: PathBuf) -> Self {
         Recorder {
+            queue: Queue::new(&path),
+            chain: Chain::new(WorkDir::new(path.clone())),
             work_dir: WorkDir::new(path),
+            trash_dir: None,
+            listener: None,
+            spawn_options: SpawnOptions::default(),
+            index: Arc::default(),
+            proxy_pool: None,
+            cookies_from_browser: None,
+            credential_vault: None,
+            coalescer: None,
+            retry_policy: RetryPolicy::default(),
+            scheduler: Scheduler::default(),
         }
     }
 
-    pub fn spawn_job(&self, command: &str, args: &[&str]) -> io::Result<Job> {
+    pub fn set_listener(&mut self, listener: Listener) {
+        self.listener = Some(listener);
+    }
+
+    /// Makes `Job::safe_delete` move job dirs into `path` instead of removing them.
+    pub fn set_trash_dir(&mut self, path: PathBuf) {
+        self.trash_dir = Some(WorkDir::new(path));
+    }
+
+    pub fn set_sandbox(&mut self, sandbox: Sandbox) {
+        self.spawn_options.sandbox = sandbox;
+    }
+
+    /// Runs jobs as the given user and group. The job dir is chowned so that the job can write to it.
+    pub fn set_job_user(&mut self, uid: Option<u32>, gid: Option<u32>) {
+        self.spawn_options.uid = uid;
+        self.spawn_options.gid = gid;
+    }
+
+    /// Sends jobs that run `command` through the proxies of `pool`, set in the proxy environment
+    /// variables when they start. The name of the proxy is added to the job's metadata.
+    pub fn set_proxy_pool(&mut self, command: &str, pool: ProxyPool) {
+        self.proxy_pool = Some(Arc::new((command.to_owned(), pool)));
+    }
+
+    /// Lets jobs that run `command` use the cookies of `browser` (e.g. `firefox:default-release`)
+    /// with `JobOptions::browser_cookies`, passing `--cookies-from-browser` when they start.
+    pub fn set_cookies_from_browser(&mut self, command: &str, browser: &str) {
+        self.cookies_from_browser = Some(Arc::new((command.to_owned(), browser.to_owned())));
+    }
+
+    /// Gives jobs that run `command` the credentials in `vault` for their site, in a `.netrc`
+    /// file that exists in the job dir only while the command runs.
+    pub fn set_credential_vault(&mut self, command: &str, vault: CredentialVault) {
+        self.credential_vault = Some(Arc::new((command.to_owned(), vault)));
+    }
+
+    /// Runs jobs that fail with a network or server error again, as `policy` says.
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.retry_policy = policy;
+    }
+
+    pub fn credential_vault(&self) -> Option<&CredentialVault> {
+        self.credential_vault.as_ref().map(|vault| &vault.1)
+    }
+
+    pub fn spawn_job(&self, command: &str, args: &[&str]) -> Result<Job> {
+        self.spawn_job_with(command, args, JobOptions::default())
+    }
+
+    /// Makes `spawn_job_with` return the job spawned for the same URL within `window`, unless it
+    /// has failed or been cancelled, instead of spawning another. Only submissions with the same
+    /// command, other args, and options are attached; see `CoalesceKey`.
+    pub fn set_coalesce_window(&mut self, window: Duration) {
+        self.coalescer = Some(Arc::new(Coalescer::new(window)));
+    }
+
+    pub fn spawn_job_with(&self, command: &str, args: &[&str], options: JobOptions) -> Result<Job> {
+        self.spawn_or_attach_job(command, args, options)
+            .map(|(job, _)| job)
+    }
+
+    /// Like `spawn_job_with`, but also tells whether the job is new, or is the one spawned before
+    /// for the same URL that the submission was attached to.
+    pub fn spawn_or_attach_job(
+        &self,
+        command: &str,
+        args: &[&str],
+        options: JobOptions,
+    ) -> Result<(Job, bool)> {
+        let key = CoalesceKey::new(command, args, &options);
+        let (coalescer, key) = match (&self.coalescer, key) {
+            (Some(coalescer), Some(key)) => (coalescer, key),
+            _ => {
+                return self
+                    .spawn_new_job(command, args, options)
+                    .map(|job| (job, true))
+            }
+        };
+
+        let mut recent = coalescer.lock();
+        let attached = recent
+            .get(&key)
+            .and_then(|(job_id, _)| self.job(job_id))
+            .filter(|job| {
+                !matches!(
+                    job.state(),
+                    None | Some(JobState::Failed | JobState::Cancelled | JobState::Interrupted)
+                )
+            });
+        if let Some(job) = attached {
+            println!("submission of {} attached to job {}", key.url(), job.job_id);
+            return Ok((job, false));
+        }
+        let job = self.spawn_new_job(command, args, options)?;
+        recent.insert(key, (job.job_id.clone(), Instant::now()));
+        Ok((job, true))
+    }
+
+    fn spawn_new_job(&self, command: &str, args: &[&str], options: JobOptions) -> Result<Job> {
+        if let Some(after) = &options.after {
+            if self.job(after).is_none() {
+                return Err(RecorderError::JobNotFound(after.clone()));
+            }
+        }
         let job_id = JobId::new();
         let job_dir = self.work_dir.job_dir(&job_id);
-        let job = Job::new(job_id, job_dir);
-        job.spawn(command, args).map(|_| job)
+        let job = self.new_job(job_id, job_dir);
+        job.spawn(command, args, options).map(|_| job)
+    }
+
+    /// Resumes waiting for the jobs scheduled before a restart, and queues the jobs that were
+    /// queued but not started again. `post_steps` rebuilds the post steps of a job from its
+    /// invocation.
+    pub fn resume_pending_jobs<F>(&self, post_steps: F)
+    where
+        F: Fn(&Json) -> Vec<PostStep>,
+    {
+        for job in self.jobs() {
+            let after = job.invocation().and_then(|invocation| {
+                serde_json::from_value::<JobId>(invocation["after"].clone()).ok()
+            });
+            if let Some(after) = after {
+                self.chain.link(&after, &job.job_id);
+            }
+            let state = job.state();
+            if state == Some(JobState::Queued) && job.pid().is_none() {
+                let invocation = match job.invocation() {
+                    Some(invocation) => invocation,
+                    None => continue,
+                };
+                let parsed = (
+                    invocation["command"].as_str(),
+                    serde_json::from_value::<Vec<String>>(invocation["args"].clone()),
+                );
+                if let (Some(command), Ok(args)) = parsed {
+                    println!("job {} is queued again", job.job_id);
+                    let options = JobOptions {
+                        post_steps: post_steps(&invocation),
+                        start_at: None,
+                        time_limit: invocation["time_limit"].as_u64().map(Duration::from_secs),
+                        request_id: None,
+                        idempotency_key: None,
+                        browser_cookies: invocation["browser_cookies"] == true,
+                        after: None,
+                    };
+                    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+                    if let Err(err) = job.enqueue(command, &args, options) {
+                        println!("job {} could not start: {}", job.job_id, err);
+                    }
+                }
+                continue;
+            }
+            if state != Some(JobState::Waiting) {
+                continue;
+            }
+            let invocation = match job.invocation() {
+                Some(invocation) => invocation,
+                None => continue,
+            };
+            let parsed = (
+                invocation["command"].as_str(),
+                serde_json::from_value::<Vec<String>>(invocation["args"].clone()),
+            );
+            let (command, args) = match parsed {
+                (Some(command), Ok(args)) => (command.to_owned(), args),
+                _ => continue,
+            };
+            let start_at =
+                serde_json::from_value::<DateTime<Utc>>(invocation["start_at"].clone()).ok();
+            // A job that has run, e.g. one waiting to be retried, is done with its chain.
+            let after = serde_json::from_value::<JobId>(invocation["after"].clone())
+                .ok()
+                .filter(|_| job.retry_count() == 0);
+            let options = JobOptions {
+                post_steps: post_steps(&invocation),
+                start_at,
+                time_limit: invocation["time_limit"].as_u64().map(Duration::from_secs),
+                request_id: None,
+                idempotency_key: None,
+                browser_cookies: invocation["browser_cookies"] == true,
+                after: after.clone(),
+            };
+            match (after, start_at) {
+                (Some(after), _) => {
+                    println!("job {} resumes waiting for job {}", job.job_id, after);
+                    let chain = self.chain.clone();
+                    chain.add(after, job, command, args, options);
+                }
+                (None, Some(start_at)) => {
+                    println!("job {} resumes waiting until {}", job.job_id, start_at);
+                    job.wait(command, args, options);
+                }
+                (None, None) => {}
+            }
+        }
+    }
+
+    /// Creates a job that runs no command, for files obtained elsewhere. Store the files with
+    /// `Job::create_file` and move the job to `Succeeded` when done.
+    pub fn import_job(&self) -> Result<Job> {
+        let job_id = JobId::new();
+        let job_dir = self.work_dir.job_dir(&job_id);
+        let job = self.new_job(job_id, job_dir);
+        job.job_dir.create_dir("info")?;
+        job.transition(JobState::Created)?;
+        Ok(job)
     }
 
     pub fn job(&self, job_id: &JobId) -> Option<Job> {
+        if !job_id.is_valid() {
+            return None;
+        }
         let job_dir = self.work_dir.job_dir(job_id);
         if job_dir.path().is_dir() {
-            Some(Job::new(job_id.clone(), job_dir))
+            Some(self.new_job(job_id.clone(), job_dir))
         } else {
             None
         }
     }
 
+    /// The job submitted with `key` in `JobOptions::idempotency_key`, if it still exists.
+    pub fn job_by_idempotency_key(&self, key: &str) -> Option<Job> {
+        self.jobs().into_iter().find(|job| {
+            job.invocation()
+                .is_some_and(|invocation| invocation["idempotency_key"] == key)
+        })
+    }
+
     pub fn jobs(&self) -> Vec<Job> {
         self.work_dir
             .job_dirs()
-            .map(|(job_id, job_dir)| Job::new(job_id, job_dir))
+            .map(|(job_id, job_dir)| self.new_job(job_id, job_dir))
+            .collect()
+    }
+
+    /// The jobs with their state, files, title, and uploader, which are read from the job dirs
+    /// only for jobs that are still going or have changed since they were last read. Job dirs
+    /// are read in parallel, and each summary is kept as soon as it is read.
+    pub fn job_summaries(&self) -> Vec<(Job, JobSummary)> {
+        self.job_summaries_in(&TimeRange::default())
+    }
+
+    /// The jobs chained with `job` through `JobOptions::after`, first to last and including
+    /// `job`: those it runs after, then those that run after it.
+    pub fn job_chain(&self, job: &Job) -> Vec<Job> {
+        fn after_of(job: &Job) -> Option<JobId> {
+            job.invocation()
+                .and_then(|invocation| serde_json::from_value(invocation["after"].clone()).ok())
+        }
+
+        let mut chain = vec![job.clone()];
+        while let Some(after) = after_of(&chain[0]).and_then(|after| self.job(&after)) {
+            if chain.iter().any(|job| job.job_id == after.job_id) {
+                break;
+            }
+            chain.insert(0, after);
+        }
+
+        let mut pending = vec![job.job_id.clone()];
+        while let Some(job_id) = pending.pop() {
+            let next: Vec<Job> = self
+                .chain
+                .next(&job_id)
+                .iter()
+                .filter(|next| chain.iter().all(|job| job.job_id != **next))
+                .filter_map(|next| self.job(next))
+                .collect();
+            pending.extend(next.iter().rev().map(|job| job.job_id.clone()));
+            chain.extend(next);
+        }
+        chain
+    }
+
+    /// The jobs waiting for their `start_at`, soonest first, with when they start.
+    pub fn scheduled_jobs(&self) -> Vec<(DateTime<Utc>, Job)> {
+        self.scheduler
+            .jobs()
+            .into_iter()
+            .filter(|(_, job)| job.state() == Some(JobState::Waiting))
             .collect()
     }
 
-    pub fn prune_job_dirs(&self) -> io::Result<()> {
+    /// The jobs created within `range` with their summaries. Jobs outside it are left out by
+    /// their ids, without reading their dirs.
+    pub fn job_summaries_in(&self, range: &TimeRange) -> Vec<(Job, JobSummary)> {
+        let job_ids = self.index.ids(self.work_dir.path(), || {
+            self.work_dir.job_dirs().map(|(job_id, _)| job_id).collect()
+        });
+        job_ids
+            .into_par_iter()
+            .filter(|job_id| range.contains(job_id))
+            .map(|job_id| {
+                let job = self.new_job(job_id.clone(), self.work_dir.job_dir(&job_id));
+                let summary = self.index.summary(&job_id, || job.summary());
+                (job, summary)
+            })
+            .collect()
+    }
+
+    fn new_job(&self, job_id: JobId, job_dir: JobDir) -> Job {
+        Job {
+            job_id,
+            job_dir,
+            listener: self.listener.clone(),
+            spawn_options: self.spawn_options,
+            trash_dir: self.trash_dir.clone(),
+            queue: self.queue.clone(),
+            index: self.index.clone(),
+            proxy_pool: self.proxy_pool.clone(),
+            cookies_from_browser: self.cookies_from_browser.clone(),
+            credential_vault: self.credential_vault.clone(),
+            retry_policy: self.retry_policy,
+            scheduler: self.scheduler.clone(),
+            chain: self.chain.clone(),
+        }
+    }
+
+    /// Reads the summaries of all jobs in the background, e.g. at startup, so that the first
+    /// request for the jobs list does not wait for a large work dir to be read.
+    pub fn load_index(&self) {
+        let recorder = self.clone();
+        std::thread::spawn(move || {
+            let started_at = Instant::now();
+            let count = recorder.job_summaries().len();
+            println!(
+                "indexed {} jobs in {}ms",
+                count,
+                started_at.elapsed().as_millis()
+            );
+        });
+    }
+
+    /// Returns the ids of the jobs in the trash.
+    pub fn trashed_job_ids(&self) -> Vec<JobId> {
+        match &self.trash_dir {
+            Some(trash_dir) => trash_dir.job_dirs().map(|(job_id, _)| job_id).collect(),
+            None => vec![],
+        }
+    }
+
+    /// Moves a job out of the trash.
+    pub fn restore_job(&self, job_id: &JobId) -> Result<Job> {
+        let trashed_dir = self
+            .trash_dir
+            .as_ref()
+            .filter(|_| job_id.is_valid())
+            .map(|trash_dir| trash_dir.job_dir(job_id))
+            .filter(|trashed_dir| trashed_dir.path().is_dir())
+            .ok_or_else(|| RecorderError::JobNotFound(job_id.clone()))?;
+        let job_dir = self.work_dir.job_dir(job_id);
+        if job_dir.path().exists() {
+            return Err(RecorderError::JobExists(job_id.clone()));
+        }
+
+        println!("restoring dir {:?}", trashed_dir.path());
+        fs::rename(trashed_dir.path(), job_dir.path())?;
+        fs::remove_file(job_dir.path().join(TRASHED_AT_FILE)).ok();
+        self.index.invalidate(job_id);
+        Ok(self.new_job(job_id.clone(), job_dir))
+    }
+
+    /// Removes the jobs that have been in the trash longer than `max_age` and returns how many
+    /// were removed.
+    pub fn purge_trash(&self, max_age: Duration) -> Result<usize> {
+        let trash_dir = match &self.trash_dir {
+            Some(trash_dir) => trash_dir,
+            None => return Ok(0),
+        };
+
+        let mut count = 0;
+        for (_, job_dir) in trash_dir.job_dirs() {
+            // Dirs trashed without the file, e.g. moved there by hand or by a move cut short, are
+            // aged by the time the dir was last modified instead.
+            let trashed_at = fs::metadata(job_dir.path().join(TRASHED_AT_FILE))
+                .or_else(|_| fs::metadata(job_dir.path()))
+                .and_then(|metadata| metadata.modified());
+            let expired = trashed_at
+                .ok()
+                .and_then(|trashed_at| trashed_at.elapsed().ok())
+                .is_some_and(|elapsed| elapsed > max_age);
+            if expired {
+                println!("purging dir {:?}", job_dir.path());
+                fs::remove_dir_all(job_dir.path())?;
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// Removes the dirs of finished jobs that produced no files and returns how many were removed.
+    pub fn prune_job_dirs(&self) -> Result<usize> {
+        let mut count = 0;
         for job in self.jobs() {
-            if !job.is_running() && job.file_names().is_empty() {
+            let is_waiting = job.state() == Some(JobState::Waiting);
+            if !job.is_running() && !is_waiting && job.os_file_names().is_empty() {
                 println!("removing dir {:?}", &job.job_dir.path);
                 fs::remove_dir_all(&job.job_dir.path)?;
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// Deletes the jobs matching `filter`, or only their media files if `slim` is true, and
+    /// returns how many were deleted.
+    pub fn delete_jobs(&self, filter: &JobFilter, slim: bool) -> usize {
+        println!("deleting jobs matching {} (slim: {})", filter, slim);
+        self.jobs()
+            .into_iter()
+            .filter(|job| filter.matches(job))
+            .filter(|job| {
+                if slim {
+                    job.clone().slim_delete()
+                } else {
+                    job.clone().safe_delete()
+                }
+            })
+            .count()
+    }
+
+    /// Marks the recorder read-only, e.g. before host maintenance, so that submissions of new
+    /// jobs are refused. Existing jobs are left alone. The mode survives restarts.
+    pub fn set_read_only(&self, read_only: bool) -> Result<()> {
+        let path = self.work_dir.path().join(READ_ONLY_FILE);
+        if read_only {
+            fs::write(path, b"")?;
+        } else {
+            match fs::remove_file(path) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err.into()),
+                _ => {}
             }
         }
         Ok(())
     }
 
+    pub fn is_read_only(&self) -> bool {
+        self.work_dir.path().join(READ_ONLY_FILE).exists()
+    }
+
     pub fn work_dir_path(&self) -> &Path {
         self.work_dir.path()
     }
 }
 
-#[derive(Clone, Debug)]
+/// Returns true for the temporary files the downloader writes while downloading or merging, such
+/// as `.part`, `.ytdl`, and `.temp.mp4` files. They are replaced by the finished files.
+pub fn is_partial_file_name(name: &str) -> bool {
+    name.ends_with(".part")
+        || name.ends_with(".ytdl")
+        || name.contains(".part-Frag")
+        || name.contains(".temp.")
+}
+
+/// Orders file names as people number files, comparing runs of digits by their value, so that
+/// `part2.mp4` comes before `part10.mp4`. Names that differ only in leading zeros, or that are
+/// otherwise equal, are ordered as strings.
+pub fn compare_file_names(a: &str, b: &str) -> Ordering {
+    fn chunks(s: &str) -> impl Iterator<Item = &str> {
+        let mut rest = s;
+        std::iter::from_fn(move || {
+            let first = rest.chars().next()?;
+            let is_digit = first.is_ascii_digit();
+            let len = rest
+                .find(|c: char| c.is_ascii_digit() != is_digit)
+                .unwrap_or(rest.len());
+            let (chunk, next) = rest.split_at(len);
+            rest = next;
+            Some(chunk)
+        })
+    }
+
+    let (mut a_chunks, mut b_chunks) = (chunks(a), chunks(b));
+    loop {
+        let ordering = match (a_chunks.next(), b_chunks.next()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(a), Some(b))
+                if a.as_bytes()[0].is_ascii_digit() && b.as_bytes()[0].is_ascii_digit() =>
+            {
+                let (a, b) = (a.trim_start_matches('0'), b.trim_start_matches('0'));
+                a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+            }
+            (Some(a), Some(b)) => a.cmp(b),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+/// Bytes read from the start of a file to tell its type by its contents.
+const SNIFF_LEN: u64 = 8192;
+
+/// The MIME type of the file at `path` by its extension, or else by its first bytes, as some
+/// extractors write files without an extension.
+pub fn guess_mime(path: &Path) -> Mime {
+    if let Some(mime) = mime_guess::from_path(path).first() {
+        return mime;
+    }
+    let mut head = vec![];
+    fs::File::open(path)
+        .and_then(|f| f.take(SNIFF_LEN).read_to_end(&mut head))
+        .ok()
+        .and_then(|_| infer::get(&head))
+        .and_then(|kind| kind.mime_type().parse().ok())
+        .unwrap_or(mime::APPLICATION_OCTET_STREAM)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
 pub struct JobId(String);
 
 impl JobId {
     pub fn new() -> Self {
         JobId(ulid::Ulid::new().to_string())
     }
+
+    /// Returns true if the id can name a job dir, i.e. it can't point outside the work dir.
+    fn is_valid(&self) -> bool {
+        !self.0.is_empty() && self.0.chars().all(|c| c.is_ascii_alphanumeric())
+    }
+}
+
+impl Default for JobId {
+    fn default() -> Self {
+        JobId::new()
+    }
 }
 
 impl std::fmt::Display for JobId {
@@ -70,22 +681,51 @@ impl std::fmt::Display for JobId {
     }
 }
 
+/// Takes an id as it is, for ids vrec made itself, e.g. the names of job dirs. Ids from outside are
+/// parsed instead.
 impl From<String> for JobId {
     fn from(string: String) -> JobId {
         JobId(string)
     }
 }
 
+/// Parses an id from outside, which must be a ULID in the canonical form job ids are made in.
+impl FromStr for JobId {
+    type Err = RecorderError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match ulid::Ulid::from_string(s) {
+            Ok(ulid) if ulid.to_string() == s => Ok(JobId(s.to_owned())),
+            _ => Err(RecorderError::InvalidJobId(s.to_owned())),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for JobId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
+#[derive(Clone)]
 pub struct Job {
     job_id: JobId,
     job_dir: JobDir,
+    listener: Option<Listener>,
+    spawn_options: SpawnOptions,
+    trash_dir: Option<WorkDir>,
+    queue: Queue,
+    index: Arc<JobIndex>,
+    proxy_pool: Option<Arc<(String, ProxyPool)>>,
+    cookies_from_browser: Option<Arc<(String, String)>>,
+    credential_vault: Option<Arc<(String, CredentialVault)>>,
+    retry_policy: RetryPolicy,
+    scheduler: Scheduler,
+    chain: Chain,
 }
 
 impl Job {
-    fn new(job_id: JobId, job_dir: JobDir) -> Self {
-        Job { job_id, job_dir }
-    }
-
     pub fn id(&self) -> &JobId {
         &self.job_id
     }
@@ -94,66 +734,672 @@ impl Job {
         self.job_dir.path()
     }
 
+    /// Returns when the job was created, as recorded in its id.
+    pub fn created_at(&self) -> Option<DateTime<Utc>> {
+        ulid::Ulid::from_string(&self.job_id.0)
+            .ok()
+            .map(|ulid| ulid.datetime())
+    }
+
     pub fn invocation(&self) -> Option<Json> {
         let f = self.job_dir.open_file("info/invocation.json").ok()?;
         serde_json::from_reader(BufReader::new(f)).ok()
     }
 
+    /// The names of the job's files that are UTF-8, which are all of them unless the downloader
+    /// was given a name that is not.
     pub fn file_names(&self) -> Vec<String> {
         self.job_dir.file_names()
     }
 
+    /// The names of all of the job's files.
+    pub fn os_file_names(&self) -> Vec<OsString> {
+        self.job_dir.os_file_names()
+    }
+
+    /// Creates a file in the job dir. `file_name` must be a plain, non-hidden file name.
+    pub fn create_file(&self, file_name: &str) -> Result<fs::File> {
+        let is_plain = !file_name.is_empty()
+            && !file_name.starts_with('.')
+            && !file_name.contains(['/', '\\', '\0']);
+        if !is_plain {
+            return Err(RecorderError::InvalidFileName(file_name.to_owned()));
+        }
+        let f = self.job_dir.create_file(file_name)?;
+        self.index.invalidate(&self.job_id);
+        Ok(f)
+    }
+
+    /// The first `.info.json` file the downloader wrote, if any.
+    pub fn info(&self) -> Option<Json> {
+        let mut file_names = self.file_names();
+        file_names.sort();
+        let name = file_names
+            .into_iter()
+            .find(|name| name.ends_with(".info.json"))?;
+        let f = self.job_dir.open_file(name).ok()?;
+        serde_json::from_reader(BufReader::new(f)).ok()
+    }
+
+    /// The image that best shows the job: the largest of those named like its first media file,
+    /// as the downloader names thumbnails, or else the largest image.
+    pub fn thumbnail_file_name(&self) -> Option<String> {
+        let mut file_names = self.file_names();
+        file_names.sort_by(|a, b| compare_file_names(a, b));
+        let is_type = |name: &str, types: &[mime::Name]| {
+            !is_partial_file_name(name)
+                && types.contains(&guess_mime(&self.job_dir.path().join(name)).type_())
+        };
+        let media_stem = file_names
+            .iter()
+            .find(|name| is_type(name, &[mime::VIDEO, mime::AUDIO]))
+            .and_then(|name| Path::new(name).file_stem())
+            .and_then(OsStr::to_str);
+        let images: Vec<(&String, u64)> = file_names
+            .iter()
+            .filter(|name| is_type(name, &[mime::IMAGE]))
+            .filter_map(|name| {
+                let metadata = fs::metadata(self.job_dir.path().join(name)).ok()?;
+                Some((name, metadata.len()))
+            })
+            .collect();
+        // `<name>.<ext>`, or `<name>_<id>.<ext>` with `--write-all-thumbnails`.
+        let is_named_like_media = |name: &str| {
+            media_stem
+                .and_then(|stem| name.strip_prefix(stem))
+                .is_some_and(|rest| rest.starts_with('.') || rest.starts_with('_'))
+        };
+        let largest = |named_like_media: bool| {
+            images
+                .iter()
+                .filter(|(name, _)| !named_like_media || is_named_like_media(name))
+                .max_by_key(|(_, size)| *size)
+                .map(|(name, _)| (*name).clone())
+        };
+        largest(true).or_else(|| largest(false))
+    }
+
+    /// The video title from the `.info.json` file, or else the title the job was submitted or
+    /// imported with (e.g. an email subject).
+    pub fn title(&self) -> Option<String> {
+        let from = |json: Json| json["title"].as_str().map(str::to_owned);
+        self.info()
+            .and_then(from)
+            .or_else(|| self.metadata().and_then(from))
+            .filter(|title| !title.is_empty())
+    }
+
+    /// The uploader or channel, from the `.info.json` file or the metadata the job was imported
+    /// with.
+    pub fn uploader(&self) -> Option<String> {
+        let from = |json: Json| {
+            ["uploader", "channel"]
+                .iter()
+                .find_map(|key| json[key].as_str().map(str::to_owned))
+        };
+        self.info()
+            .and_then(from)
+            .or_else(|| self.metadata().and_then(from))
+            .filter(|uploader| !uploader.is_empty())
+    }
+
+    /// User-provided metadata of the job, such as the title of an imported job.
+    pub fn metadata(&self) -> Option<Json> {
+        let f = self.job_dir.open_file("info/metadata.json").ok()?;
+        serde_json::from_reader(BufReader::new(f)).ok()
+    }
+
+    /// Adds the keys of `metadata`, an object, to the job's metadata, replacing those already set.
+    pub fn set_metadata(&self, metadata: &Json) -> Result<()> {
+        let metadata = match (self.metadata(), metadata) {
+            (Some(Json::Object(mut merged)), Json::Object(metadata)) => {
+                merged.extend(metadata.clone());
+                Json::Object(merged)
+            }
+            _ => metadata.clone(),
+        };
+        let json = serde_json::to_vec(&metadata).map_err(io::Error::from)?;
+        self.job_dir
+            .write_file_atomically("info/metadata.json", &json)?;
+        self.index.invalidate(&self.job_id);
+        Ok(())
+    }
+
+    fn summary(&self) -> JobSummary {
+        let file_names = self.file_names();
+        let files = file_names
+            .iter()
+            .map(|name| {
+                let path = self.job_dir.path().join(name);
+                let metadata = fs::metadata(&path).ok();
+                FileSummary {
+                    name: name.clone(),
+                    size: metadata.as_ref().map_or(0, |metadata| metadata.len()),
+                    modified_at: metadata
+                        .and_then(|metadata| metadata.modified().ok())
+                        .map(DateTime::<Utc>::from),
+                    mime_type: guess_mime(&path).to_string(),
+                }
+            })
+            .collect();
+        JobSummary {
+            state: self.state(),
+            file_names,
+            title: self.title(),
+            uploader: self.uploader(),
+            runtime: self.runtime(),
+            thumbnail: self.thumbnail_file_name(),
+            files,
+        }
+    }
+
+    /// The output of the job's downloader, `stdout` or `stderr`, whether or not it has been
+    /// gzipped; only the last `tail` lines if given.
+    pub fn log(&self, stream: &str, tail: Option<usize>) -> io::Result<String> {
+        let name = match stream {
+            "stdout" | "stderr" => format!("info/{}.txt", stream),
+            _ => return Err(io::ErrorKind::NotFound.into()),
+        };
+        match tail {
+            Some(lines) => self.read_log_tail(&name, lines),
+            None => self.read_log(&name),
+        }
+    }
+
+    pub fn last_stderr_line(&self) -> Option<String> {
+        self.read_log("info/stderr.txt")
+            .ok()?
+            .lines()
+            .rev()
+            .find(|line| !line.trim().is_empty())
+            .map(str::to_owned)
+    }
+
+    pub fn state(&self) -> Option<JobState> {
+        self.state_record().and_then(|record| record.state())
+    }
+
+    pub fn state_record(&self) -> Option<StateRecord> {
+        self.job_dir.state_record()
+    }
+
+    /// Moves the job to `next` and persists the transition, failing if it is not allowed from the
+    /// current state. A `state.json` that can't be parsed is reported rather than overwritten.
+    pub fn transition(&self, next: JobState) -> Result<()> {
+        self.transition_with_note(next, None)
+    }
+
+    /// Like `transition`, recording why the job moved to `next`.
+    pub fn transition_with_note(&self, next: JobState, note: Option<&str>) -> Result<()> {
+        {
+            // Held until the new state is written, so that a transition made meanwhile, e.g. a
+            // cancel as the job finishes, is checked against this one rather than overwritten.
+            let _lock = self.job_dir.lock_file("info/state.lock")?;
+            let mut record: StateRecord = match self.job_dir.open_file("info/state.json") {
+                Ok(f) => serde_json::from_reader(BufReader::new(f)).map_err(|source| {
+                    RecorderError::CorruptMetadata {
+                        job_id: self.job_id.clone(),
+                        file: "info/state.json",
+                        source,
+                    }
+                })?,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => StateRecord::default(),
+                Err(err) => return Err(err.into()),
+            };
+            record.push(next, note.map(str::to_owned))?;
+            let json = serde_json::to_vec(&record).map_err(io::Error::from)?;
+            self.job_dir
+                .write_file_atomically("info/state.json", &json)?;
+        }
+        self.index.invalidate(&self.job_id);
+        if next == JobState::Failed {
+            self.record_failure();
+        }
+
+        if let Some(listener) = &self.listener {
+            listener(self, next);
+        }
+        self.chain.finished(&self.job_id, next);
+
+        Ok(())
+    }
+
     pub fn is_running(&self) -> bool {
         match self.pid() {
-            Ok(pid) => unsafe { libc::kill(pid, 0) == 0 },
-            _ => false,
+            Some(pid) => platform::is_running(pid),
+            None => false,
         }
     }
 
-    fn spawn(&self, command: &str, args: &[&str]) -> io::Result<()> {
+    fn spawn(&self, command: &str, args: &[&str], options: JobOptions) -> Result<()> {
         self.job_dir.create_dir("info")?;
 
         {
             let f = self.job_dir.create_file("info/invocation.json")?;
-            let json = json!({ "command": command, "args": &args });
+            let mut json = json!({ "command": command, "args": &args });
+            if !options.post_steps.is_empty() {
+                let names: Vec<&str> = options
+                    .post_steps
+                    .iter()
+                    .map(|step| step.name.as_str())
+                    .collect();
+                json["post"] = json!(names);
+            }
+            if let Some(start_at) = options.start_at {
+                json["start_at"] = json!(start_at);
+            }
+            if let Some(time_limit) = options.time_limit {
+                json["time_limit"] = json!(time_limit.as_secs());
+            }
+            if let Some(request_id) = &options.request_id {
+                json["request_id"] = json!(request_id);
+            }
+            if let Some(key) = &options.idempotency_key {
+                json["idempotency_key"] = json!(key);
+            }
+            if options.browser_cookies {
+                json["browser_cookies"] = json!(true);
+            }
+            if let Some(after) = &options.after {
+                json["after"] = json!(after);
+            }
             writeln!(&f, "{}", json)?;
         }
 
+        if let Some(request_id) = &options.request_id {
+            println!("[{}] spawning job {}", request_id, self.job_id);
+        }
+
+        self.transition(JobState::Created)?;
+
+        if let Some(after) = options.after.clone() {
+            self.transition(JobState::Waiting)?;
+            let args = args.iter().map(|&arg| arg.to_owned()).collect();
+            self.chain
+                .add(after, self.clone(), command.to_owned(), args, options);
+            Ok(())
+        } else if options.start_at.is_some() {
+            self.transition(JobState::Waiting)?;
+            let args = args.iter().map(|&arg| arg.to_owned()).collect();
+            self.clone().wait(command.to_owned(), args, options);
+            Ok(())
+        } else {
+            self.run(command, args, options)
+        }
+    }
+
+    /// Runs the job once `options.start_at` has come, unless it has been cancelled or deleted
+    /// meanwhile.
+    fn wait(self, command: String, args: Vec<String>, options: JobOptions) {
+        let scheduler = self.scheduler.clone();
+        scheduler.add(self, command, args, options);
+    }
+
+    fn run(&self, command: &str, args: &[&str], options: JobOptions) -> Result<()> {
+        self.transition(JobState::Queued)?;
+        self.enqueue(command, args, options)
+    }
+
+    /// Starts the queued job, or leaves it to `Recorder::resume_queue` if the queue is paused.
+    fn enqueue(&self, command: &str, args: &[&str], options: JobOptions) -> Result<()> {
+        match self.queue.hold(self, command, args, options) {
+            Some(options) => self.start_queued(command, args, options),
+            None => Ok(()),
+        }
+    }
+
+    fn start_queued(&self, command: &str, args: &[&str], options: JobOptions) -> Result<()> {
+        let proxy = self
+            .proxy_pool
+            .as_ref()
+            .filter(|pool| pool.0 == command)
+            .and_then(|pool| pool.1.assign(args))
+            .cloned();
+        let mut extra_args = vec![];
+        // In the environment rather than the args, which any user can see in the process list
+        // while the URL may hold the proxy's password.
+        let envs: Vec<(&str, &str)> = match &proxy {
+            Some(proxy) => PROXY_ENV_VARS
+                .iter()
+                .map(|&name| (name, proxy.url.as_str()))
+                .collect(),
+            None => vec![],
+        };
+        if options.browser_cookies {
+            match self
+                .cookies_from_browser
+                .as_ref()
+                .filter(|cookies| cookies.0 == command)
+            {
+                Some(cookies) => extra_args.extend(["--cookies-from-browser", cookies.1.as_str()]),
+                None => println!(
+                    "job {} runs without browser cookies, which are not set up",
+                    self.job_id
+                ),
+            }
+        }
+
+        if self.write_netrc(command, args) {
+            extra_args.extend(["--netrc", "--netrc-location", NETRC_FILE_NAME]);
+        }
+
+        let child = match self.start(command, &[&extra_args[..], args].concat(), &envs) {
+            Ok(child) => child,
+            Err(source) => {
+                self.remove_netrc();
+                self.queue.release(&self.job_id);
+                self.transition(JobState::Failed)?;
+                return Err(RecorderError::Spawn {
+                    command: command.to_owned(),
+                    source,
+                });
+            }
+        };
+
+        self.write_pid(&child)?;
+        if let Some(proxy) = &proxy {
+            self.set_metadata(&json!({ "proxy": proxy.name }))?;
+        }
+
+        self.transition(JobState::Running)?;
+        self.clone().supervise(command.to_owned(), child, options);
+
+        Ok(())
+    }
+
+    /// Writes the credentials for the job's site, if there are any, and returns whether it did.
+    /// Failing to is logged rather than failing the job, which may not need them.
+    fn write_netrc(&self, command: &str, args: &[&str]) -> bool {
+        let vault = match self
+            .credential_vault
+            .as_ref()
+            .filter(|vault| vault.0 == command)
+        {
+            Some(vault) => &vault.1,
+            None => return false,
+        };
+        let written = vault.netrc_for(args).and_then(|netrc| match netrc {
+            Some(netrc) => {
+                let options = &self.spawn_options;
+                let path = self.job_dir.path().join(NETRC_FILE_NAME);
+                platform::write_private(&path, netrc.as_bytes(), options.uid, options.gid)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        });
+        written.unwrap_or_else(|err| {
+            println!("job {} runs without credentials: {}", self.job_id, err);
+            false
+        })
+    }
+
+    fn remove_netrc(&self) {
+        let path = self.job_dir.path().join(NETRC_FILE_NAME);
+        if let Err(err) = fs::remove_file(path) {
+            if err.kind() != io::ErrorKind::NotFound {
+                println!("job {} could not remove credentials: {}", self.job_id, err);
+            }
+        }
+    }
+
+    fn start(&self, command: &str, args: &[&str], envs: &[(&str, &str)]) -> io::Result<Child> {
         let stdout = self.job_dir.create_file("info/stdout.txt")?;
         let stderr = self.job_dir.create_file("info/stderr.txt")?;
 
-        let child = Command::new(command)
-            .args(args)
-            .current_dir(&self.job_dir.path())
+        self.command(command, args)?
+            .envs(envs.iter().copied())
             .stdout(stdout)
             .stderr(stderr)
-            .spawn()?;
+            .spawn()
+    }
+
+    /// Runs a command in the job dir the way the job's command was run, appending to its logs.
+    /// Meant for post steps.
+    pub fn run_command(&self, command: &str, args: &[&str]) -> io::Result<()> {
+        let stdout = self.job_dir.append_file("info/stdout.txt")?;
+        let stderr = self.job_dir.append_file("info/stderr.txt")?;
 
-        let pid_file = self.job_dir.create_file("info/pid.txt")?;
-        writeln!(&pid_file, "{}", child.id())?;
+        let mut child = self
+            .command(command, args)?
+            .stdout(stdout)
+            .stderr(stderr)
+            .spawn()?;
+        self.write_pid(&child)?;
 
+        let status = child.wait()?;
+        if !status.success() {
+            return Err(io::Error::other(format!(
+                "{} exited with {}",
+                command, status
+            )));
+        }
         Ok(())
     }
 
-    fn pid(&self) -> Result<i32, &'static str> {
-        let mut f = self
-            .job_dir
-            .open_file("info/pid.txt")
-            .map_err(|_| "could not open file")?;
+    fn command(&self, command: &str, args: &[&str]) -> io::Result<Command> {
+        let options = &self.spawn_options;
+        let dir = fs::canonicalize(self.job_dir.path())?;
+        let mut command = options.sandbox.command(&dir, command, args);
+        platform::set_user(&mut command, &dir, options.uid, options.gid)?;
+        Ok(command)
+    }
+
+    /// Records the pid of the running process so that `is_running` can find it.
+    fn write_pid(&self, child: &Child) -> io::Result<()> {
+        let contents = format!("{}\n", child.id());
+        self.job_dir
+            .write_file_atomically("info/pid.txt", contents.as_bytes())
+    }
+
+    /// Waits for the child in a background thread, runs the post steps if it succeeded, and
+    /// records how the job ended.
+    fn supervise(self, command: String, mut child: Child, options: JobOptions) {
+        std::thread::spawn(move || {
+            let timer = options
+                .time_limit
+                .map(|limit| self.limit_time(child.id(), limit));
+            let mut record = SupervisorRecord {
+                started_at: Some(Utc::now()),
+                // Asked while the download runs, so that the version is the one that ran.
+                downloader_version: downloader::version(&command).ok(),
+                ..SupervisorRecord::default()
+            };
+            self.write_supervisor_record(&record);
+            let status = child.wait();
+            self.remove_netrc();
+            record.finished_at = Some(Utc::now());
+            record.exit_code = status.as_ref().ok().and_then(|status| status.code());
+            self.write_supervisor_record(&record);
+            let timed_out = timer.is_some_and(|(done, handle)| {
+                drop(done);
+                handle.join().unwrap_or(false)
+            });
+            // Cancelled while it ran; the process was stopped for that, not interrupted.
+            if self.state() == Some(JobState::Cancelled) {
+                self.queue.release(&self.job_id);
+                return;
+            }
+
+            let next = match status {
+                _ if timed_out => self.run_post_steps(options.post_steps),
+                Ok(status) if status.success() => self.run_post_steps(options.post_steps),
+                Ok(status) if platform::is_signaled(&status) => JobState::Interrupted,
+                Ok(_) if self.retry_later(&command, options) => {
+                    self.queue.release(&self.job_id);
+                    return;
+                }
+                Ok(_) => JobState::Failed,
+                Err(_) => JobState::Interrupted,
+            };
+            if let Err(err) = self.transition(next) {
+                println!("job {} transition failed: {}", self.job_id, err);
+            }
+            self.queue.release(&self.job_id);
+        });
+    }
+
+    /// Runs the post steps of a job whose command succeeded, and returns the state it ends in.
+    fn run_post_steps(&self, post_steps: Vec<PostStep>) -> JobState {
+        for step in post_steps {
+            if let Err(err) = (step.run)(self) {
+                println!(
+                    "job {} post step {} failed: {}",
+                    self.job_id, step.name, err
+                );
+                if let Ok(f) = self.job_dir.append_file("info/stderr.txt") {
+                    writeln!(&f, "post step {} failed: {}", step.name, err).ok();
+                }
+                return JobState::Failed;
+            }
+        }
+        JobState::Succeeded
+    }
+
+    /// Interrupts the process after `limit` unless the returned sender is dropped first. The
+    /// thread returns whether it interrupted the process.
+    fn limit_time(&self, pid: u32, limit: Duration) -> (Sender<()>, JoinHandle<bool>) {
+        let (done, wait_done) = mpsc::channel::<()>();
+        let job = self.clone();
+        let handle = std::thread::spawn(move || {
+            if wait_done.recv_timeout(limit) != Err(RecvTimeoutError::Timeout) {
+                return false;
+            }
+            if let Ok(f) = job.job_dir.append_file("info/stderr.txt") {
+                writeln!(&f, "stopping after the time limit of {}s", limit.as_secs()).ok();
+            }
+            if let Err(err) = platform::interrupt(pid) {
+                println!("job {} could not be interrupted: {}", job.job_id, err);
+            }
+            true
+        });
+        (done, handle)
+    }
+
+    fn pid(&self) -> Option<u32> {
+        let mut f = self.job_dir.open_file("info/pid.txt").ok()?;
         let mut pid = String::new();
-        f.read_to_string(&mut pid).map_err(|_| "read failed")?;
-        pid.trim_end().parse().map_err(|_| "parse failed")
+        f.read_to_string(&mut pid).ok()?;
+        pid.trim_end().parse().ok()
     }
 
+    /// Deletes the job unless it is running, moving it to the trash if there is one.
     pub fn safe_delete(self) -> bool {
-        if !self.is_running() {
-            println!("removing dir {:?}", &self.job_dir.path);
-            return fs::remove_dir_all(&self.job_dir.path).is_ok();
+        if self.is_running() {
+            return false;
+        }
+        match &self.trash_dir {
+            Some(trash_dir) => {
+                println!("moving dir {:?} to trash", &self.job_dir.path);
+                self.move_to(trash_dir).is_ok()
+            }
+            None => {
+                println!("removing dir {:?}", &self.job_dir.path);
+                fs::remove_dir_all(&self.job_dir.path).is_ok()
+            }
+        }
+    }
+
+    /// Removes the audio and video files of the job unless it is running, keeping the info
+    /// JSON, thumbnails, and logs. The removed files are listed in the metadata.
+    pub fn slim_delete(self) -> bool {
+        if self.is_running() {
+            return false;
+        }
+        match self.remove_media_files() {
+            Ok(()) => true,
+            Err(err) => {
+                println!("could not slim dir {:?}: {}", &self.job_dir.path, err);
+                false
+            }
+        }
+    }
+
+    fn remove_media_files(&self) -> Result<()> {
+        let mut file_names = self.os_file_names();
+        file_names.sort();
+
+        let mut removed = vec![];
+        let mut freed = 0;
+        for name in file_names {
+            let path = self.job_dir.path.join(&name);
+            // Partial downloads are media too.
+            let is_media = is_partial_file_name(&name.to_string_lossy())
+                || [mime::VIDEO, mime::AUDIO].contains(&guess_mime(&path).type_());
+            if is_media {
+                let file_metadata = fs::metadata(&path)?;
+                // Files deduplicated with other jobs stay on disk through their other links.
+                if platform::link_count(&file_metadata) == 1 {
+                    freed += file_metadata.len();
+                }
+                println!("removing file {:?}", &path);
+                fs::remove_file(&path)?;
+                self.index.invalidate(&self.job_id);
+                removed.push(name.to_string_lossy().into_owned());
+            }
         }
-        false
+        if removed.is_empty() {
+            return Ok(());
+        }
+
+        let mut metadata = match self.metadata() {
+            Some(Json::Object(metadata)) => metadata,
+            _ => Default::default(),
+        };
+        let mut removed_files = metadata
+            .get("removed_files")
+            .and_then(Json::as_str)
+            .map(|names| vec![names.to_owned()])
+            .unwrap_or_default();
+        removed_files.extend(removed);
+        metadata.insert("removed_files".to_owned(), json!(removed_files.join(", ")));
+        metadata.insert("slimmed_at".to_owned(), json!(Utc::now().to_rfc3339()));
+        metadata.insert("freed_size".to_owned(), json!(humanize_byte_size(freed)));
+        self.set_metadata(&Json::Object(metadata))
+    }
+
+    /// Removes the partial downloads left by a job that did not finish, keeping the files that
+    /// were completed. Returns the number of bytes freed.
+    pub fn remove_partial_files(&self) -> Result<u64> {
+        if self.is_running() {
+            return Err(RecorderError::JobRunning(self.job_id.clone()));
+        }
+
+        let mut freed = 0;
+        for name in self.os_file_names() {
+            if !is_partial_file_name(&name.to_string_lossy()) {
+                continue;
+            }
+            let path = self.job_dir.path.join(&name);
+            freed += fs::metadata(&path)?.len();
+            println!("removing file {:?}", &path);
+            fs::remove_file(&path)?;
+            self.index.invalidate(&self.job_id);
+        }
+        Ok(freed)
+    }
+
+    fn move_to(&self, trash_dir: &WorkDir) -> io::Result<()> {
+        fs::create_dir_all(trash_dir.path())?;
+        self.job_dir.create_dir("info")?;
+        // Its modification time tells when the job was trashed.
+        self.job_dir
+            .write_file_atomically(TRASHED_AT_FILE, Utc::now().to_rfc3339().as_bytes())?;
+        fs::rename(self.job_dir.path(), trash_dir.job_dir(&self.job_id).path())
     }
 }
 
+const TRASHED_AT_FILE: &str = "info/trashed_at.txt";
+
+/// Site credentials for the running command. Hidden so that it is not listed or served.
+const NETRC_FILE_NAME: &str = ".netrc";
+
+/// Set to the URL of the job's proxy, which the downloader and the tools it runs read.
+const PROXY_ENV_VARS: [&str; 3] = ["http_proxy", "https_proxy", "all_proxy"];
+
+/// Present in the work dir while the recorder is read-only.
+const READ_ONLY_FILE: &str = ".read-only";
+
+#[derive(Clone)]
 struct WorkDir {
     path: PathBuf,
 }
@@ -194,6 +1440,7 @@ impl WorkDir {
     }
 }
 
+#[derive(Clone)]
 struct JobDir {
     path: PathBuf,
 }
@@ -216,45 +1463,73 @@ impl JobDir {
         fs::File::open(self.path.join(path))
     }
 
+    fn state_record(&self) -> Option<StateRecord> {
+        let f = self.open_file("info/state.json").ok()?;
+        serde_json::from_reader(BufReader::new(f)).ok()
+    }
+
+    fn append_file<P: AsRef<Path>>(&self, path: P) -> io::Result<fs::File> {
+        fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.path.join(path))
+    }
+
+    /// Writes to a temporary file and renames it over `path` so readers never see a partial file.
+    /// Each write has its own temporary file, so that writes at the same time don't mix.
+    fn write_file_atomically<P: AsRef<Path>>(&self, path: P, contents: &[u8]) -> io::Result<()> {
+        let path = self.path.join(path);
+        let tmp_path = path.with_extension(format!("{}.tmp", ulid::Ulid::new()));
+        let written = fs::File::create(&tmp_path).and_then(|mut f| {
+            f.write_all(contents)?;
+            f.sync_all()
+        });
+        if let Err(err) = written.and_then(|_| fs::rename(&tmp_path, path)) {
+            fs::remove_file(&tmp_path).ok();
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Opens `path`, creating it if needed, and locks it until the returned file is dropped,
+    /// waiting for the lock if another thread or process holds it.
+    fn lock_file<P: AsRef<Path>>(&self, path: P) -> io::Result<fs::File> {
+        let f = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(self.path.join(path))?;
+        f.lock()?;
+        Ok(f)
+    }
+
     fn path(&self) -> &Path {
         self.path.as_path()
     }
 
-    /// Returns non-hidden file names.
-    fn file_names(&self) -> Vec<String> {
+    /// Returns non-hidden file names, including those of partial downloads (see
+    /// `is_partial_file_name`) and those that are not UTF-8.
+    fn os_file_names(&self) -> Vec<OsString> {
         if let Ok(iter) = self.path.read_dir() {
             iter.flatten()
                 .map(|entry| entry.path())
                 .filter(|path| path.is_file())
                 .filter_map(|path| {
-                    path.file_name().and_then(OsStr::to_str).and_then(|name| {
-                        if !name.starts_with('.') {
-                            Some(name.to_owned())
-                        } else {
-                            None
-                        }
-                    })
+                    path.file_name()
+                        .filter(|name| !name.to_string_lossy().starts_with('.'))
+                        .map(OsStr::to_owned)
                 })
                 .collect()
         } else {
             vec![]
         }
     }
-}
 
-/// Starts a thread that cleans up exited child processes.
-pub fn start_child_reaper() {
-    let signals = signal_hook::iterator::Signals::new(&[signal_hook::SIGCHLD])
-        .expect("SIGCHLD handler must be registered");
-
-    std::thread::spawn(move || {
-        for _ in signals.forever() {
-            loop {
-                let pid = unsafe { libc::waitpid(-1, std::ptr::null_mut(), libc::WNOHANG) };
-                if pid <= 0 {
-                    break;
-                }
-            }
-        }
-    });
+    /// Returns the non-hidden file names that are UTF-8.
+    fn file_names(&self) -> Vec<String> {
+        self.os_file_names()
+            .into_iter()
+            .filter_map(|name| name.into_string().ok())
+            .collect()
+    }
 }
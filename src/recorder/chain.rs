@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use super::{Job, JobId, JobOptions, JobState, WorkDir};
+
+/// Holds jobs that start once another job has succeeded, e.g. a download after the one it
+/// builds on, and cancels them if it fails or is cancelled instead.
+///
+/// Like the schedule, the chains are kept in the job dirs, as the `Waiting` state of each job and
+/// the `after` of its invocation, so that `Recorder::resume_pending_jobs` can fill them in again
+/// after a restart.
+#[derive(Clone)]
+pub(super) struct Chain {
+    work_dir: WorkDir,
+    /// The jobs waiting for each job.
+    waiting: Arc<Mutex<HashMap<JobId, Vec<ChainedJob>>>>,
+    /// The jobs chained after each job, kept after they start so that `Recorder::job_chain` can
+    /// follow them without reading every invocation.
+    next: Arc<Mutex<HashMap<JobId, Vec<JobId>>>>,
+}
+
+struct ChainedJob {
+    job: Job,
+    command: String,
+    args: Vec<String>,
+    options: JobOptions,
+}
+
+impl Chain {
+    pub(super) fn new(work_dir: WorkDir) -> Self {
+        Chain {
+            work_dir,
+            waiting: Arc::default(),
+            next: Arc::default(),
+        }
+    }
+
+    /// Records that `job_id` is chained after the job `after`.
+    pub(super) fn link(&self, after: &JobId, job_id: &JobId) {
+        let mut next = self.next.lock().unwrap();
+        let next = next.entry(after.clone()).or_default();
+        if !next.contains(job_id) {
+            next.push(job_id.clone());
+        }
+    }
+
+    /// The jobs chained after `job_id`, oldest first.
+    pub(super) fn next(&self, job_id: &JobId) -> Vec<JobId> {
+        let mut next = self
+            .next
+            .lock()
+            .unwrap()
+            .get(job_id)
+            .cloned()
+            .unwrap_or_default();
+        next.sort();
+        next
+    }
+
+    /// Holds the waiting job until the job `after` succeeds, starting it now if that one already
+    /// has and cancelling it if that one has failed, been cancelled, or does not exist.
+    pub(super) fn add(
+        &self,
+        after: JobId,
+        job: Job,
+        command: String,
+        args: Vec<String>,
+        options: JobOptions,
+    ) {
+        self.link(&after, &job.job_id);
+        let chained = ChainedJob {
+            job,
+            command,
+            args,
+            options,
+        };
+        let mut waiting = self.waiting.lock().unwrap();
+        // Read while holding the lock, which `finished` takes only after the state is written, so
+        // that the job can't finish unnoticed in between.
+        let state = self
+            .work_dir
+            .job_dir(&after)
+            .state_record()
+            .and_then(|record| record.state());
+        match state {
+            Some(JobState::Succeeded) => {
+                drop(waiting);
+                chained.start();
+            }
+            None | Some(JobState::Failed | JobState::Cancelled) => {
+                drop(waiting);
+                chained.cancel(&after, state);
+            }
+            Some(_) => waiting.entry(after).or_default().push(chained),
+        }
+    }
+
+    /// Starts or cancels the jobs waiting for the job that has just moved to `state`. Jobs that
+    /// are interrupted or retried later keep them waiting.
+    pub(super) fn finished(&self, job_id: &JobId, state: JobState) {
+        if !matches!(
+            state,
+            JobState::Succeeded | JobState::Failed | JobState::Cancelled
+        ) {
+            return;
+        }
+        let chained = self.waiting.lock().unwrap().remove(job_id);
+        for chained in chained.unwrap_or_default() {
+            if state == JobState::Succeeded {
+                chained.start();
+            } else {
+                chained.cancel(job_id, Some(state));
+            }
+        }
+    }
+}
+
+impl ChainedJob {
+    /// Queues the job, or leaves it to the scheduler if it has a start time, unless it has been
+    /// deleted while it waited.
+    fn start(self) {
+        let job = self.job;
+        if job.state() != Some(JobState::Waiting) {
+            return;
+        }
+        println!("job {} starts after the job it waited for", job.job_id);
+        if self.options.start_at.is_some() {
+            job.wait(self.command, self.args, self.options);
+            return;
+        }
+        let args: Vec<&str> = self.args.iter().map(String::as_str).collect();
+        if let Err(err) = job.run(&self.command, &args, self.options) {
+            println!("job {} could not start: {}", job.job_id, err);
+        }
+    }
+
+    fn cancel(self, after: &JobId, state: Option<JobState>) {
+        let job = self.job;
+        if job.state() != Some(JobState::Waiting) {
+            return;
+        }
+        let note = match state {
+            Some(state) => format!("job {} it was to run after {}", after, state),
+            None => format!("job {} it was to run after does not exist", after),
+        };
+        println!("job {} is cancelled: {}", job.job_id, note);
+        if let Err(err) = job.transition_with_note(JobState::Cancelled, Some(&note)) {
+            println!("job {} transition failed: {}", job.job_id, err);
+        }
+    }
+}
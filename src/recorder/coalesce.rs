@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use url::Url;
+
+use super::{JobId, JobOptions};
+
+/// Query parameters that only track where a link was shared from.
+const TRACKING_PARAMS: &[&str] = &["si", "feature", "fbclid", "gclid", "igshid", "ref"];
+
+/// The jobs spawned within the window, by what they download.
+pub(super) type RecentJobs = HashMap<CoalesceKey, (JobId, Instant)>;
+
+/// What makes two submissions the same download: the command, the URL, the other args, and the
+/// options that change what is downloaded or when. Submissions of the same URL in another format
+/// or to another output template get jobs of their own.
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub(super) struct CoalesceKey {
+    command: String,
+    /// As normalized by `normalized_url_of_args`.
+    url: String,
+    other_args: Vec<String>,
+    start_at: Option<DateTime<Utc>>,
+    time_limit: Option<Duration>,
+    browser_cookies: bool,
+    after: Option<JobId>,
+    post_steps: Vec<String>,
+}
+
+impl CoalesceKey {
+    /// The key of a job running `command` with `args`, or `None` if the args have no URL.
+    pub fn new(command: &str, args: &[&str], options: &JobOptions) -> Option<Self> {
+        let index = args.iter().position(|arg| url_with_host(arg).is_some())?;
+        let url = normalized_url_of_args(&args[index..])?;
+        let other_args = args
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| i != index)
+            .map(|(_, &arg)| arg.to_owned())
+            .collect();
+        Some(CoalesceKey {
+            command: command.to_owned(),
+            url,
+            other_args,
+            start_at: options.start_at,
+            time_limit: options.time_limit,
+            browser_cookies: options.browser_cookies,
+            after: options.after.clone(),
+            post_steps: options
+                .post_steps
+                .iter()
+                .map(|step| step.name.clone())
+                .collect(),
+        })
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+}
+
+/// Remembers the jobs recently spawned for each URL, so that a submission repeated within the
+/// window can attach to the first job instead of downloading the same video into another dir.
+pub(super) struct Coalescer {
+    window: Duration,
+    recent: Mutex<RecentJobs>,
+}
+
+impl Coalescer {
+    pub fn new(window: Duration) -> Self {
+        Coalescer {
+            window,
+            recent: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Locks the jobs spawned within the window. Checking for a job and spawning one are done
+    /// under the lock so that concurrent submissions don't both spawn.
+    pub fn lock(&self) -> MutexGuard<'_, RecentJobs> {
+        let mut recent = self.recent.lock().unwrap();
+        recent.retain(|_, (_, spawned_at)| spawned_at.elapsed() < self.window);
+        recent
+    }
+}
+
+/// The first URL in `args` in a form shared by links to the same video, e.g. without `www.`,
+/// the fragment, or tracking parameters, and with `youtu.be` links expanded.
+pub(super) fn normalized_url_of_args(args: &[&str]) -> Option<String> {
+    let mut url = args.iter().find_map(|arg| url_with_host(arg))?;
+
+    let host = url.host_str()?.to_lowercase();
+    let host = host
+        .strip_prefix("www.")
+        .or_else(|| host.strip_prefix("m."))
+        .unwrap_or(&host)
+        .to_owned();
+    if host == "youtu.be" {
+        let id = url.path().trim_start_matches('/').to_owned();
+        url = Url::parse(&format!("https://youtube.com/watch?v={}", id)).ok()?;
+    } else {
+        url.set_host(Some(&host)).ok()?;
+    }
+
+    let mut pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(name, _)| !name.starts_with("utm_") && !TRACKING_PARAMS.contains(&name.as_ref()))
+        .map(|(name, value)| (name.into_owned(), value.into_owned()))
+        .collect();
+    pairs.sort();
+    if pairs.is_empty() {
+        url.set_query(None);
+    } else {
+        url.query_pairs_mut().clear().extend_pairs(pairs);
+    }
+    url.set_fragment(None);
+    if url.path().len() > 1 && url.path().ends_with('/') {
+        let path = url.path().trim_end_matches('/').to_owned();
+        url.set_path(&path);
+    }
+    // Sites are served over both schemes.
+    url.set_scheme("https").ok();
+
+    Some(url.into())
+}
+
+fn url_with_host(arg: &str) -> Option<Url> {
+    Url::parse(arg).ok().filter(|url| url.host_str().is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(args: &[&str]) -> CoalesceKey {
+        CoalesceKey::new("yt-dlp", args, &JobOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn links_to_the_same_video_are_normalized_alike() {
+        let expected = Some("https://youtube.com/watch?v=x".to_owned());
+        for url in &[
+            "https://www.youtube.com/watch?v=x",
+            "http://m.youtube.com/watch?v=x&si=abc&utm_source=share",
+            "https://youtu.be/x",
+            "https://youtube.com/watch?v=x#t=10",
+        ] {
+            assert_eq!(normalized_url_of_args(&[url]), expected, "{}", url);
+        }
+        assert_eq!(normalized_url_of_args(&["-f", "best"]), None);
+    }
+
+    #[test]
+    fn the_same_url_with_the_same_args_has_the_same_key() {
+        assert_eq!(
+            key(&[
+                "-f",
+                "bestaudio",
+                "https://www.example.com/video?utm_source=x"
+            ]),
+            key(&["-f", "bestaudio", "https://example.com/video"]),
+        );
+    }
+
+    #[test]
+    fn other_args_or_options_make_another_key() {
+        let url = "https://example.com/video";
+        let plain = key(&[url]);
+        assert_ne!(key(&["-f", "bestaudio", url]), plain);
+        assert_ne!(key(&["-o", "%(id)s.%(ext)s", url]), plain);
+
+        let options = JobOptions {
+            time_limit: Some(Duration::from_secs(60)),
+            ..JobOptions::default()
+        };
+        assert_ne!(CoalesceKey::new("yt-dlp", &[url], &options).unwrap(), plain);
+        let options = JobOptions {
+            browser_cookies: true,
+            ..JobOptions::default()
+        };
+        assert_ne!(CoalesceKey::new("yt-dlp", &[url], &options).unwrap(), plain);
+        assert_ne!(
+            CoalesceKey::new("other", &[url], &JobOptions::default()).unwrap(),
+            plain
+        );
+    }
+
+    #[test]
+    fn args_without_a_url_have_no_key() {
+        assert!(CoalesceKey::new("yt-dlp", &["--version"], &JobOptions::default()).is_none());
+    }
+}
@@ -0,0 +1,264 @@
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::time::Duration;
+
+use chrono::Utc;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+use super::{guess_mime, Job, JobState, Recorder, Result};
+
+const LOG_FILES: &[&str] = &["info/stdout.txt", "info/stderr.txt"];
+
+/// The archive small auxiliary files are repacked into.
+const AUXILIARY_ARCHIVE: &str = "auxiliary.tar.gz";
+
+/// Files at most this large are repacked.
+const MAX_AUXILIARY_SIZE: u64 = 64 * 1024;
+
+impl Recorder {
+    /// Gzips the logs of finished jobs created longer ago than `max_age`, and repacks their small
+    /// auxiliary files into one archive if `repack` is true. Returns how many jobs were compacted.
+    pub fn compact_old_jobs(&self, max_age: Duration, repack: bool) -> Result<usize> {
+        let mut count = 0;
+        for job in self.jobs() {
+            let is_old = job
+                .created_at()
+                .and_then(|created_at| (Utc::now() - created_at).to_std().ok())
+                .is_some_and(|elapsed| elapsed > max_age);
+            let is_finished = !job.is_running()
+                && !matches!(job.state(), Some(JobState::Waiting | JobState::Queued));
+            if !is_old || !is_finished {
+                continue;
+            }
+
+            let mut compacted = job.compress_logs()?;
+            if repack {
+                compacted |= job.repack_auxiliary_files()?;
+            }
+            if compacted {
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+}
+
+impl Job {
+    /// Reads a log file, whether or not it has been gzipped.
+    pub(super) fn read_log(&self, name: &str) -> io::Result<String> {
+        let mut log = String::new();
+        match self.job_dir.open_file(name) {
+            Ok(mut f) => f.read_to_string(&mut log)?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                let f = self.job_dir.open_file(format!("{}.gz", name))?;
+                GzDecoder::new(f).read_to_string(&mut log)?
+            }
+            Err(err) => return Err(err),
+        };
+        Ok(log)
+    }
+
+    /// Reads the last `lines` lines of a log file. A plain log is read from its end; a gzipped
+    /// one has to be decompressed from the start, but only the last lines are kept.
+    pub(super) fn read_log_tail(&self, name: &str, lines: usize) -> io::Result<String> {
+        let tail = match self.job_dir.open_file(name) {
+            Ok(mut f) => tail(&mut f, lines)?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                let f = self.job_dir.open_file(format!("{}.gz", name))?;
+                let mut reader = BufReader::new(GzDecoder::new(f));
+                let mut kept = VecDeque::with_capacity(lines.min(1024) + 1);
+                loop {
+                    let mut line = Vec::new();
+                    if reader.read_until(b'\n', &mut line)? == 0 {
+                        break;
+                    }
+                    kept.push_back(line);
+                    if kept.len() > lines {
+                        kept.pop_front();
+                    }
+                }
+                kept.into_iter().flatten().collect()
+            }
+            Err(err) => return Err(err),
+        };
+        Ok(String::from_utf8_lossy(&tail).into_owned())
+    }
+
+    fn compress_logs(&self) -> io::Result<bool> {
+        let mut compressed = false;
+        for name in LOG_FILES {
+            let path = self.job_dir.path().join(name);
+            let mut f = match fs::File::open(&path) {
+                Ok(f) => f,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(err),
+            };
+            println!("compressing file {:?}", &path);
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+            io::copy(&mut f, &mut encoder)?;
+            self.job_dir
+                .write_file_atomically(format!("{}.gz", name), &encoder.finish()?)?;
+            fs::remove_file(&path)?;
+            compressed = true;
+        }
+        Ok(compressed)
+    }
+
+    /// Packs small files other than media, images, and info JSON into one archive.
+    fn repack_auxiliary_files(&self) -> io::Result<bool> {
+        if self.job_dir.path().join(AUXILIARY_ARCHIVE).exists() {
+            return Ok(false);
+        }
+
+        let mut file_names = self.file_names();
+        file_names.sort();
+        let mut auxiliary = vec![];
+        for name in file_names {
+            let mime = guess_mime(&self.job_dir.path().join(&name));
+            let is_kept = [mime::AUDIO, mime::VIDEO, mime::IMAGE].contains(&mime.type_())
+                || name.ends_with(".info.json")
+                || name.ends_with(".part");
+            let size = fs::metadata(self.job_dir.path().join(&name))?.len();
+            if !is_kept && size <= MAX_AUXILIARY_SIZE {
+                auxiliary.push(name);
+            }
+        }
+        // Packing a single file saves nothing.
+        if auxiliary.len() < 2 {
+            return Ok(false);
+        }
+
+        println!("repacking {} files in {:?}", auxiliary.len(), self.path());
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::best()));
+        for name in &auxiliary {
+            builder.append_path_with_name(self.job_dir.path().join(name), name)?;
+        }
+        let archive = builder.into_inner()?.finish()?;
+        self.job_dir
+            .write_file_atomically(AUXILIARY_ARCHIVE, &archive)?;
+        for name in &auxiliary {
+            fs::remove_file(self.job_dir.path().join(name))?;
+            self.index.invalidate(&self.job_id);
+        }
+        Ok(true)
+    }
+}
+
+/// The last `lines` lines of a file, read backwards from its end a chunk at a time.
+fn tail(f: &mut File, lines: usize) -> io::Result<Vec<u8>> {
+    const CHUNK_SIZE: u64 = 8 * 1024;
+
+    let len = f.seek(SeekFrom::End(0))?;
+    let mut tail = Vec::new();
+    let mut start = len;
+    let mut newlines = 0;
+    while start > 0 && newlines < lines {
+        let size = CHUNK_SIZE.min(start);
+        start -= size;
+        let mut chunk = vec![0; size as usize];
+        f.seek(SeekFrom::Start(start))?;
+        f.read_exact(&mut chunk)?;
+        // A newline ending the file doesn't start another line.
+        let end = if start + size == len && chunk.ends_with(b"\n") {
+            chunk.len() - 1
+        } else {
+            chunk.len()
+        };
+        let line_start = chunk[..end]
+            .iter()
+            .rposition(|&b| {
+                newlines += (b == b'\n') as usize;
+                newlines == lines
+            })
+            .map_or(0, |i| i + 1);
+        chunk.drain(..line_start);
+        chunk.append(&mut tail);
+        tail = chunk;
+    }
+    Ok(tail)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// A file in the temp dir with `contents`, removed when dropped.
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(contents: &[u8]) -> Self {
+            let path = env::temp_dir().join(format!("vrec-tail-{}", ulid::Ulid::new()));
+            fs::write(&path, contents).unwrap();
+            TempFile(path)
+        }
+
+        fn tail(&self, lines: usize) -> String {
+            let mut f = File::open(&self.0).unwrap();
+            String::from_utf8(tail(&mut f, lines).unwrap()).unwrap()
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            fs::remove_file(&self.0).ok();
+        }
+    }
+
+    #[test]
+    fn a_trailing_newline_does_not_count_as_a_line() {
+        let file = TempFile::new(b"one\ntwo\nthree\n");
+        assert_eq!(file.tail(1), "three\n");
+        assert_eq!(file.tail(2), "two\nthree\n");
+
+        let file = TempFile::new(b"one\ntwo\nthree");
+        assert_eq!(file.tail(1), "three");
+        assert_eq!(file.tail(2), "two\nthree");
+    }
+
+    #[test]
+    fn lines_crossing_a_chunk_boundary_are_kept_whole() {
+        let long_line = "x".repeat(10 * 1024);
+        let contents = format!("first\n{}\nlast\n", long_line);
+        let file = TempFile::new(contents.as_bytes());
+        assert_eq!(file.tail(2), format!("{}\nlast\n", long_line));
+        assert_eq!(file.tail(3), contents);
+
+        // Many lines, so that the chunks end mid-line.
+        let lines: Vec<String> = (0..5000).map(|i| format!("line {}", i)).collect();
+        let file = TempFile::new(format!("{}\n", lines.join("\n")).as_bytes());
+        assert_eq!(file.tail(3000), format!("{}\n", lines[2000..].join("\n")));
+    }
+
+    #[test]
+    fn a_file_shorter_than_asked_for_is_read_whole() {
+        let file = TempFile::new(b"one\ntwo\n");
+        assert_eq!(file.tail(10), "one\ntwo\n");
+        assert_eq!(TempFile::new(b"").tail(10), "");
+        assert_eq!(file.tail(0), "");
+    }
+
+    #[test]
+    fn gzipped_logs_are_tailed_like_plain_ones() {
+        let dir = env::temp_dir().join(format!("vrec-compact-{}", ulid::Ulid::new()));
+        let recorder = Recorder::new(dir.clone());
+        let job = recorder.import_job().unwrap();
+        let log: String = (0..100).map(|i| format!("line {}\n", i)).collect();
+        let mut f = job.job_dir.create_file("info/stderr.txt").unwrap();
+        f.write_all(log.as_bytes()).unwrap();
+
+        let plain = job.read_log_tail("info/stderr.txt", 3).unwrap();
+        assert_eq!(plain, "line 97\nline 98\nline 99\n");
+        assert!(job.compress_logs().unwrap());
+        assert!(!job.path().join("info/stderr.txt").exists());
+        assert_eq!(job.read_log_tail("info/stderr.txt", 3).unwrap(), plain);
+        assert_eq!(job.read_log("info/stderr.txt").unwrap(), log);
+        fs::remove_dir_all(&dir).ok();
+    }
+}
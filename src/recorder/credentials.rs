@@ -0,0 +1,158 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use aes_gcm::aead::{Aead, NewAead};
+use aes_gcm::Aes256Gcm;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::domain::{host_of_args, matches_domain};
+use super::{RecorderError, Result};
+use crate::platform;
+
+const NONCE_SIZE: usize = 12;
+
+/// A login to a site, e.g. for members-only videos.
+#[derive(Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Credential {
+    /// The name the downloader looks the login up by in `.netrc`, e.g. `niconico`.
+    pub machine: String,
+    /// Jobs from this domain and its subdomains are given the login, e.g. `nicovideo.jp`.
+    pub domain: String,
+    pub username: String,
+    pub password: String,
+}
+
+impl fmt::Debug for Credential {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("Credential")
+            .field("machine", &self.machine)
+            .field("domain", &self.domain)
+            .field("username", &self.username)
+            .finish()
+    }
+}
+
+impl Credential {
+    /// Fails if a field is empty or can't be written to `.netrc`, which splits on whitespace.
+    fn validate(&self) -> Result<()> {
+        let fields = [&self.machine, &self.domain, &self.username, &self.password];
+        if fields.iter().any(|field| field.is_empty()) {
+            return Err(RecorderError::InvalidCredential("fields must not be empty"));
+        }
+        if fields
+            .iter()
+            .any(|field| field.contains(char::is_whitespace) || field.contains('"'))
+        {
+            return Err(RecorderError::InvalidCredential(
+                "fields must not contain whitespace or quotes",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Logins to sites, kept encrypted with a key from the environment so that the var dir, or a
+/// backup of it, does not give them away.
+pub struct CredentialVault {
+    path: PathBuf,
+    cipher: Aes256Gcm,
+    /// Serializes changes to the file.
+    lock: Mutex<()>,
+}
+
+impl CredentialVault {
+    /// Opens the vault at `path`, which is created when a login is first added.
+    pub fn new(path: PathBuf, key: &str) -> Self {
+        let key = Sha256::digest(key.as_bytes());
+        CredentialVault {
+            path,
+            cipher: Aes256Gcm::new(&key),
+            lock: Mutex::new(()),
+        }
+    }
+
+    pub fn credentials(&self) -> Result<Vec<Credential>> {
+        let data = match fs::read(&self.path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(err.into()),
+        };
+        let (nonce, ciphertext) = data
+            .split_first_chunk::<NONCE_SIZE>()
+            .ok_or(RecorderError::CredentialsUnreadable)?;
+        let plaintext = self
+            .cipher
+            .decrypt(&(*nonce).into(), ciphertext)
+            .map_err(|_| RecorderError::CredentialsUnreadable)?;
+        serde_json::from_slice(&plaintext).map_err(|_| RecorderError::CredentialsUnreadable)
+    }
+
+    /// Adds a login, replacing the one for the same machine and domain.
+    pub fn add(&self, mut credential: Credential) -> Result<()> {
+        credential.validate()?;
+        credential.domain = credential.domain.to_lowercase();
+        let _lock = self.lock.lock().unwrap();
+        let mut credentials = self.credentials()?;
+        credentials.retain(|c| !(c.machine == credential.machine && c.domain == credential.domain));
+        credentials.push(credential);
+        self.save(&credentials)
+    }
+
+    /// Removes the login for `machine` and `domain`, returning whether there was one.
+    pub fn remove(&self, machine: &str, domain: &str) -> Result<bool> {
+        let _lock = self.lock.lock().unwrap();
+        let mut credentials = self.credentials()?;
+        let count = credentials.len();
+        credentials.retain(|c| !(c.machine == machine && c.domain == domain));
+        if credentials.len() == count {
+            return Ok(false);
+        }
+        self.save(&credentials)?;
+        Ok(true)
+    }
+
+    fn save(&self, credentials: &[Credential]) -> Result<()> {
+        let plaintext = serde_json::to_vec(credentials).map_err(io::Error::from)?;
+        let nonce: [u8; NONCE_SIZE] = rand::random();
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce.into(), plaintext.as_slice())
+            .map_err(|_| io::Error::other("could not encrypt credentials"))?;
+
+        let tmp_path = self
+            .path
+            .with_extension(format!("{}.tmp", ulid::Ulid::new()));
+        let contents = [&nonce[..], &ciphertext].concat();
+        let written = platform::write_private(&tmp_path, &contents, None, None)
+            .and_then(|_| fs::rename(&tmp_path, &self.path));
+        if let Err(err) = written {
+            fs::remove_file(&tmp_path).ok();
+            return Err(err.into());
+        }
+        Ok(())
+    }
+
+    /// `.netrc` contents with the logins for the site of a job with `args`, if there are any.
+    pub fn netrc_for(&self, args: &[&str]) -> Result<Option<String>> {
+        let host = match host_of_args(args) {
+            Some(host) => host,
+            None => return Ok(None),
+        };
+        let netrc: String = self
+            .credentials()?
+            .iter()
+            .filter(|credential| matches_domain(&credential.domain, &host))
+            .map(|credential| {
+                format!(
+                    "machine {} login {} password {}\n",
+                    credential.machine, credential.username, credential.password
+                )
+            })
+            .collect();
+        Ok(Some(netrc).filter(|netrc| !netrc.is_empty()))
+    }
+}
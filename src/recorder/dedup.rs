@@ -0,0 +1,163 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::{JobState, Recorder, Result};
+use crate::platform;
+
+/// Content hashes of job files, kept in the work dir so unchanged files aren't hashed again.
+const INDEX_FILE: &str = ".dedup-index.json";
+
+/// Smaller files aren't worth linking.
+const MIN_FILE_SIZE: u64 = 1024 * 1024;
+
+#[derive(Default, Serialize, Deserialize)]
+struct Index {
+    /// Keyed by `<job id>/<file name>`.
+    files: BTreeMap<String, IndexEntry>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct IndexEntry {
+    size: u64,
+    modified: u64,
+    sha256: String,
+}
+
+/// The outcome of deduplicating job files.
+#[derive(Debug, Default)]
+pub struct DedupReport {
+    /// Files replaced by a hard link to an identical file in another job.
+    pub linked: usize,
+    /// Bytes freed by linking.
+    pub reclaimed: u64,
+}
+
+impl Recorder {
+    /// Replaces files of finished jobs with hard links to identical files in older jobs.
+    pub fn deduplicate(&self) -> Result<DedupReport> {
+        let index_path = self.work_dir.path().join(INDEX_FILE);
+        let old_index: Index = fs::File::open(&index_path)
+            .ok()
+            .and_then(|f| serde_json::from_reader(BufReader::new(f)).ok())
+            .unwrap_or_default();
+
+        let mut jobs = self.jobs();
+        jobs.sort_by(|a, b| a.id().cmp(b.id()));
+
+        let mut index = Index::default();
+        let mut paths_by_hash: BTreeMap<String, Vec<(String, PathBuf)>> = BTreeMap::new();
+        for job in jobs {
+            let is_finished = !job.is_running()
+                && !matches!(job.state(), Some(JobState::Waiting | JobState::Queued));
+            if !is_finished {
+                continue;
+            }
+            let mut file_names = job.file_names();
+            file_names.sort();
+            for name in file_names {
+                let path = job.path().join(&name);
+                let metadata = fs::metadata(&path)?;
+                if metadata.len() < MIN_FILE_SIZE {
+                    continue;
+                }
+                let modified = metadata
+                    .modified()?
+                    .duration_since(UNIX_EPOCH)
+                    .map(|duration| duration.as_secs())
+                    .unwrap_or(0);
+                let key = format!("{}/{}", job.id(), name);
+                let entry = match old_index.files.get(&key) {
+                    Some(entry) if entry.size == metadata.len() && entry.modified == modified => {
+                        entry.clone()
+                    }
+                    _ => IndexEntry {
+                        size: metadata.len(),
+                        modified,
+                        sha256: hash_file(&path)?,
+                    },
+                };
+                paths_by_hash
+                    .entry(entry.sha256.clone())
+                    .or_default()
+                    .push((key.clone(), path));
+                index.files.insert(key, entry);
+            }
+        }
+
+        let mut report = DedupReport::default();
+        for paths in paths_by_hash.values() {
+            let ((original_key, original), copies) = paths.split_first().unwrap();
+            let original_metadata = fs::metadata(original)?;
+            for (key, copy) in copies {
+                let copy_metadata = fs::metadata(copy)?;
+                let file_id = platform::file_id(&copy_metadata);
+                if file_id.is_some() && file_id == platform::file_id(&original_metadata) {
+                    continue;
+                }
+                // Don't trust the hash alone with deleting data.
+                if copy_metadata.len() != original_metadata.len() || !same_contents(original, copy)?
+                {
+                    continue;
+                }
+                println!("linking {:?} to {:?}", copy, original);
+                // The copy's space is freed only if no other link keeps it.
+                if platform::link_count(&copy_metadata) == 1 {
+                    report.reclaimed += copy_metadata.len();
+                }
+                link_over(original, copy)?;
+                report.linked += 1;
+                // The link has the mtime of the original.
+                let original_entry = index.files[original_key].clone();
+                index.files.insert(key.clone(), original_entry);
+            }
+        }
+
+        let json = serde_json::to_vec(&index).map_err(io::Error::from)?;
+        let tmp_path = index_path.with_extension(format!("{}.tmp", ulid::Ulid::new()));
+        if let Err(err) = fs::write(&tmp_path, json).and_then(|_| fs::rename(&tmp_path, index_path))
+        {
+            fs::remove_file(&tmp_path).ok();
+            return Err(err.into());
+        }
+
+        Ok(report)
+    }
+}
+
+fn hash_file(path: &Path) -> io::Result<String> {
+    let mut f = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut f, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn same_contents(a: &Path, b: &Path) -> io::Result<bool> {
+    let mut a = BufReader::new(fs::File::open(a)?);
+    let mut b = BufReader::new(fs::File::open(b)?);
+    let mut buf_a = vec![0; 64 * 1024];
+    let mut buf_b = vec![0; 64 * 1024];
+    loop {
+        let n = a.read(&mut buf_a)?;
+        if n == 0 {
+            return Ok(b.read(&mut buf_b)? == 0);
+        }
+        b.read_exact(&mut buf_b[..n])?;
+        if buf_a[..n] != buf_b[..n] {
+            return Ok(false);
+        }
+    }
+}
+
+/// Replaces `path` with a hard link to `original`.
+fn link_over(original: &Path, path: &Path) -> io::Result<()> {
+    let tmp_path = path.with_file_name(".dedup-link");
+    let _ = fs::remove_file(&tmp_path);
+    fs::hard_link(original, &tmp_path)?;
+    fs::rename(&tmp_path, path)
+}
@@ -0,0 +1,148 @@
+use std::io::{self, BufReader};
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::failure::STDERR_TAIL_LINES;
+use super::{Job, JobState};
+
+/// Contents of `info/supervisor.json`, written by the thread that waits for the downloader
+/// when the downloader starts and again when it exits.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub(super) struct SupervisorRecord {
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    /// `None` if the process was killed by a signal or could not be waited for.
+    pub exit_code: Option<i32>,
+    pub downloader_version: Option<String>,
+}
+
+/// When a job's last run started and ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Runtime {
+    pub started_at: DateTime<Utc>,
+    /// `None` if the run is still going.
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+impl Runtime {
+    /// How long the run took, or has taken so far.
+    pub fn duration(&self) -> Duration {
+        self.finished_at.unwrap_or_else(Utc::now) - self.started_at
+    }
+}
+
+/// What went on in a job's last run, for working out why it failed or was slow.
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Diagnostics {
+    pub exit_code: Option<i32>,
+    pub started_at: Option<DateTime<Utc>>,
+    /// When the run ended, or `None` if it is still going.
+    pub finished_at: Option<DateTime<Utc>>,
+    /// Seconds from the start of the run to its end, or to now if it is still going.
+    pub runtime_seconds: Option<i64>,
+    /// The fastest download speed the downloader reported, in bytes per second.
+    pub peak_speed: Option<u64>,
+    pub downloader_version: Option<String>,
+    pub stderr_tail: Vec<String>,
+}
+
+impl Job {
+    pub(super) fn write_supervisor_record(&self, record: &SupervisorRecord) {
+        let written = serde_json::to_vec(record)
+            .map_err(io::Error::from)
+            .and_then(|json| {
+                self.job_dir
+                    .write_file_atomically("info/supervisor.json", &json)
+            });
+        if let Err(err) = written {
+            println!("job {} could not record its exit: {}", self.job_id, err);
+        }
+    }
+
+    fn supervisor_record(&self) -> Option<SupervisorRecord> {
+        let f = self.job_dir.open_file("info/supervisor.json").ok()?;
+        serde_json::from_reader(BufReader::new(f)).ok()
+    }
+
+    /// When the job's last run started and ended, as the supervisor recorded them. Jobs it didn't
+    /// supervise, such as those run by workers, and runs it didn't see the end of, such as those
+    /// interrupted by a restart, are timed by their state transitions instead.
+    pub fn runtime(&self) -> Option<Runtime> {
+        self.runtime_with(self.supervisor_record().unwrap_or_default())
+    }
+
+    fn runtime_with(&self, supervisor: SupervisorRecord) -> Option<Runtime> {
+        let transitions = self
+            .state_record()
+            .map(|record| record.transitions)
+            .unwrap_or_default();
+        // A resumed job runs again; only its last run counts.
+        let run = transitions
+            .iter()
+            .rposition(|transition| transition.state == JobState::Running);
+        let started_at = supervisor
+            .started_at
+            .or_else(|| run.map(|i| transitions[i].at))?;
+        let finished_at = supervisor.finished_at.or_else(|| {
+            run.and_then(|i| transitions.get(i + 1))
+                .map(|transition| transition.at)
+        });
+        Some(Runtime {
+            started_at,
+            finished_at,
+        })
+    }
+
+    /// Assembles the diagnostics of the job's last run from its state transitions, what its
+    /// supervisor recorded, and its logs.
+    pub fn diagnostics(&self) -> Diagnostics {
+        let supervisor = self.supervisor_record().unwrap_or_default();
+        let runtime = self.runtime_with(supervisor.clone());
+
+        let peak_speed = self.read_log("info/stdout.txt").ok().and_then(|log| {
+            log.split(['\r', '\n'])
+                .filter_map(reported_speed)
+                .fold(None, |peak: Option<f64>, speed| {
+                    Some(peak.map_or(speed, |peak| peak.max(speed)))
+                })
+                .map(|speed| speed as u64)
+        });
+
+        let stderr_tail = self
+            .read_log_tail("info/stderr.txt", STDERR_TAIL_LINES)
+            .map(|tail| tail.lines().map(str::to_owned).collect())
+            .unwrap_or_default();
+
+        Diagnostics {
+            exit_code: supervisor.exit_code,
+            started_at: runtime.map(|runtime| runtime.started_at),
+            finished_at: runtime.and_then(|runtime| runtime.finished_at),
+            runtime_seconds: runtime.map(|runtime| runtime.duration().num_seconds()),
+            peak_speed,
+            downloader_version: supervisor.downloader_version,
+            stderr_tail,
+        }
+    }
+}
+
+/// The speed in bytes per second of a progress line such as
+/// `[download]  45.3% of 10.00MiB at  1.23MiB/s ETA 00:05`.
+fn reported_speed(line: &str) -> Option<f64> {
+    let (_, rest) = line.split_once(" at ")?;
+    let speed = rest.split_whitespace().next()?.strip_suffix("/s")?;
+    let split = speed.find(|c: char| c.is_ascii_alphabetic())?;
+    let (number, unit) = speed.split_at(split);
+    let multiplier = match unit {
+        "B" => 1.0,
+        "KiB" => 1024.0,
+        "MiB" => 1024.0 * 1024.0,
+        "GiB" => 1024.0 * 1024.0 * 1024.0,
+        "KB" | "kB" => 1e3,
+        "MB" => 1e6,
+        "GB" => 1e9,
+        _ => return None,
+    };
+    number.parse::<f64>().ok().map(|number| number * multiplier)
+}
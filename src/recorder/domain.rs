@@ -0,0 +1,103 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use url::Url;
+
+use super::filter::parse_age;
+
+/// Caps the jobs running at once for a site, e.g. to avoid being throttled or banned.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DomainLimit {
+    /// A domain, which also matches its subdomains, e.g. `youtube.com` or `*.youtube.com`.
+    pub pattern: String,
+    pub max_running: usize,
+}
+
+impl DomainLimit {
+    pub fn matches(&self, host: &str) -> bool {
+        matches_domain(&self.pattern, host)
+    }
+}
+
+impl FromStr for DomainLimit {
+    type Err = String;
+
+    /// Parses a limit such as `youtube.com=1`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid domain limit {:?} (expected e.g. youtube.com=1)", s);
+        let (pattern, max_running) = s.split_once('=').ok_or_else(invalid)?;
+        let pattern = pattern.trim().to_lowercase();
+        let max_running = max_running.trim().parse().map_err(|_| invalid())?;
+        if pattern.is_empty() || max_running == 0 {
+            return Err(invalid());
+        }
+        Ok(DomainLimit {
+            pattern,
+            max_running,
+        })
+    }
+}
+
+impl fmt::Display for DomainLimit {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}={}", self.pattern, self.max_running)
+    }
+}
+
+/// Spaces out the starts of jobs for a site, e.g. one that rate-limits bulk downloads.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DomainDelay {
+    /// A domain, which also matches its subdomains, e.g. `nicovideo.jp` or `*.nicovideo.jp`.
+    pub pattern: String,
+    /// The least time between the starts of two jobs.
+    pub delay: Duration,
+}
+
+impl DomainDelay {
+    pub fn matches(&self, host: &str) -> bool {
+        matches_domain(&self.pattern, host)
+    }
+}
+
+impl FromStr for DomainDelay {
+    type Err = String;
+
+    /// Parses a delay such as `nicovideo.jp=30s`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "invalid domain delay {:?} (expected e.g. nicovideo.jp=30s)",
+                s
+            )
+        };
+        let (pattern, delay) = s.split_once('=').ok_or_else(invalid)?;
+        let pattern = pattern.trim().to_lowercase();
+        let delay = parse_age(delay.trim()).map_err(|_| invalid())?;
+        if pattern.is_empty() {
+            return Err(invalid());
+        }
+        Ok(DomainDelay { pattern, delay })
+    }
+}
+
+impl fmt::Display for DomainDelay {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}={}s", self.pattern, self.delay.as_secs())
+    }
+}
+
+pub(super) fn matches_domain(pattern: &str, host: &str) -> bool {
+    let pattern = pattern.strip_prefix("*.").unwrap_or(pattern);
+    host == pattern
+        || host
+            .strip_suffix(pattern)
+            .is_some_and(|prefix| prefix.ends_with('.'))
+}
+
+/// The host of the first URL in `args`, which is taken as the site the job downloads from.
+pub fn host_of_args(args: &[&str]) -> Option<String> {
+    args.iter()
+        .filter_map(|arg| Url::parse(arg).ok())
+        .find_map(|url| url.host_str().map(str::to_lowercase))
+}
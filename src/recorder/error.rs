@@ -0,0 +1,63 @@
+use std::io;
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+use super::state::InvalidTransition;
+use super::JobId;
+
+pub type Result<T, E = RecorderError> = std::result::Result<T, E>;
+
+/// What went wrong with a job or the work dir.
+#[derive(Debug, Error)]
+pub enum RecorderError {
+    /// The job's command could not be started.
+    #[error("could not spawn {command}: {source}")]
+    Spawn { command: String, source: io::Error },
+    #[error("job {0} not found")]
+    JobNotFound(JobId),
+    #[error("job {0} already exists")]
+    JobExists(JobId),
+    #[error("job {0} is running")]
+    JobRunning(JobId),
+    /// The job is not being run by a worker, e.g. because the coordinator restarted meanwhile.
+    #[error("job {0} is not claimed by a worker")]
+    NotClaimed(JobId),
+    /// Another vrec process holds the lock file of the work dir.
+    #[error("{0:?} is locked by another vrec process")]
+    WorkDirLocked(PathBuf),
+    /// A JSON file that the recorder keeps in the job dir could not be parsed.
+    #[error("corrupt {file} in job {job_id}: {source}")]
+    CorruptMetadata {
+        job_id: JobId,
+        file: &'static str,
+        source: serde_json::Error,
+    },
+    #[error(transparent)]
+    InvalidTransition(#[from] InvalidTransition),
+    /// An id from outside, e.g. a request, that is not a ULID as job ids are.
+    #[error("invalid job id {0:?}")]
+    InvalidJobId(String),
+    #[error("invalid file name {0:?}")]
+    InvalidFileName(String),
+    #[error("invalid credential: {0}")]
+    InvalidCredential(&'static str),
+    /// The credentials file can't be decrypted, e.g. because `CREDENTIALS_KEY` has changed.
+    #[error("credentials can't be decrypted (is CREDENTIALS_KEY right?)")]
+    CredentialsUnreadable,
+    /// The work dir or a job dir can't be accessed, e.g. because of `JOB_UID`.
+    #[error("permission denied: {0}")]
+    PermissionDenied(io::Error),
+    #[error(transparent)]
+    Io(io::Error),
+}
+
+impl From<io::Error> for RecorderError {
+    fn from(err: io::Error) -> Self {
+        if err.kind() == io::ErrorKind::PermissionDenied {
+            RecorderError::PermissionDenied(err)
+        } else {
+            RecorderError::Io(err)
+        }
+    }
+}
@@ -0,0 +1,287 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde_json::json;
+
+use super::Job;
+
+/// Lines at the end of stderr that are looked through for the error, which diagnostics also show
+/// and retries classify.
+pub(super) const STDERR_TAIL_LINES: usize = 50;
+
+/// Why a download failed, as told by the errors youtube-dl and yt-dlp print.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FailureReason {
+    GeoBlocked,
+    Forbidden,
+    Private,
+    ExtractorBroken,
+    /// The site answered 404 Not Found or 410 Gone.
+    NotFound,
+    /// The site answered with another 4xx status.
+    ClientError,
+    Network,
+    /// The site answered with a 5xx status.
+    ServerError,
+}
+
+/// Phrases of the errors of each reason, lowercased. Reasons are tried in order, as a line may
+/// match more than one, e.g. `Unable to download webpage: HTTP Error 403: Forbidden`. Statuses
+/// come before `Network`, whose phrases the downloader also prints for an answer it didn't want.
+const PATTERNS: &[(FailureReason, &[&str])] = &[
+    (
+        FailureReason::GeoBlocked,
+        &[
+            "not available in your country",
+            "not available from your location",
+            "blocked it in your country",
+            "geo restriction",
+            "geo-restrict",
+        ],
+    ),
+    (
+        FailureReason::Private,
+        &["private video", "video is private", "members-only"],
+    ),
+    (
+        FailureReason::Forbidden,
+        &["http error 403", "403: forbidden"],
+    ),
+    (
+        FailureReason::NotFound,
+        &[
+            "http error 404",
+            "http error 410",
+            "404: not found",
+            "410: gone",
+        ],
+    ),
+    (FailureReason::ClientError, &["http error 4"]),
+    (
+        FailureReason::ServerError,
+        &[
+            "http error 50",
+            "internal server error",
+            "service unavailable",
+            "bad gateway",
+        ],
+    ),
+    (
+        FailureReason::Network,
+        &[
+            "unable to download webpage",
+            "urlopen error",
+            "connection refused",
+            "connection reset",
+            "timed out",
+            "name resolution",
+            "network is unreachable",
+            "getaddrinfo failed",
+        ],
+    ),
+    (
+        FailureReason::ExtractorBroken,
+        &["unable to extract", "report this issue", "extractorerror"],
+    ),
+];
+
+impl FailureReason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FailureReason::GeoBlocked => "geo_blocked",
+            FailureReason::Forbidden => "forbidden",
+            FailureReason::Private => "private",
+            FailureReason::ExtractorBroken => "extractor_broken",
+            FailureReason::NotFound => "not_found",
+            FailureReason::ClientError => "client_error",
+            FailureReason::Network => "network",
+            FailureReason::ServerError => "server_error",
+        }
+    }
+
+    /// Whether the error may pass by itself, so that the download is worth retrying as is.
+    pub fn is_transient(self) -> bool {
+        matches!(self, FailureReason::Network | FailureReason::ServerError)
+    }
+
+    /// What to try before running the download again.
+    pub fn suggestion(self) -> &'static str {
+        match self {
+            FailureReason::GeoBlocked => {
+                "The video is blocked where vrec runs. Try a proxy in a country where it is \
+                 available (PROXIES)."
+            }
+            FailureReason::Forbidden => {
+                "The site refused the download. Update the downloader; if that doesn't help, the \
+                 site may need browser cookies or credentials."
+            }
+            FailureReason::Private => {
+                "The video is private or for members only. Add credentials or browser cookies \
+                 for the site, or check that the video is still public."
+            }
+            FailureReason::ExtractorBroken => {
+                "The downloader could not read the site, which has probably changed. Update the \
+                 downloader."
+            }
+            FailureReason::NotFound => {
+                "The site has no such page. Check the URL, or whether the video was taken down."
+            }
+            FailureReason::ClientError => {
+                "The site refused the request. Check the URL and the args, and wait a while if \
+                 the site limits requests."
+            }
+            FailureReason::Network => "The site could not be reached. Check the network and retry.",
+            FailureReason::ServerError => "The site had an error. Retry later.",
+        }
+    }
+
+    /// Finds the reason of the last error in `stderr` that is known, with the line telling it.
+    pub fn classify(stderr: &str) -> Option<(FailureReason, &str)> {
+        stderr.lines().rev().find_map(|line| {
+            let lowercased = line.to_lowercase();
+            PATTERNS
+                .iter()
+                .find(|(_, phrases)| phrases.iter().any(|phrase| lowercased.contains(phrase)))
+                .map(|(reason, _)| (*reason, line.trim()))
+        })
+    }
+}
+
+impl FromStr for FailureReason {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        PATTERNS
+            .iter()
+            .map(|(reason, _)| *reason)
+            .find(|reason| reason.as_str() == s)
+            .ok_or_else(|| format!("unknown failure reason {:?}", s))
+    }
+}
+
+impl fmt::Display for FailureReason {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        self.as_str().fmt(fmt)
+    }
+}
+
+impl Job {
+    /// Records why the job failed in its metadata, as `failure_reason` and `failure_message`, if
+    /// its stderr tells.
+    pub(super) fn record_failure(&self) {
+        let stderr = match self.read_log_tail("info/stderr.txt", STDERR_TAIL_LINES) {
+            Ok(stderr) => stderr,
+            Err(_) => return,
+        };
+        let (reason, message) = match FailureReason::classify(&stderr) {
+            Some(failure) => failure,
+            None => return,
+        };
+        let metadata = json!({
+            "failure_reason": reason.as_str(),
+            "failure_message": message,
+        });
+        if let Err(err) = self.set_metadata(&metadata) {
+            println!("job {} could not record its failure: {}", self.job_id, err);
+        }
+    }
+
+    /// Why the job failed, if it was recorded.
+    pub fn failure_reason(&self) -> Option<FailureReason> {
+        self.metadata()?
+            .get("failure_reason")?
+            .as_str()?
+            .parse()
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reason_of(stderr: &str) -> Option<FailureReason> {
+        FailureReason::classify(stderr).map(|(reason, _)| reason)
+    }
+
+    #[test]
+    fn errors_are_told_by_their_phrases() {
+        for (line, reason) in &[
+            (
+                "ERROR: [youtube] x: This video is not available in your country",
+                FailureReason::GeoBlocked,
+            ),
+            ("ERROR: [youtube] x: Private video", FailureReason::Private),
+            (
+                "ERROR: [generic] Unable to extract title; please report this issue",
+                FailureReason::ExtractorBroken,
+            ),
+            (
+                "ERROR: Unable to download webpage: <urlopen error [Errno -2] Name or service not known>",
+                FailureReason::Network,
+            ),
+            (
+                "ERROR: unable to download video data: HTTP Error 503: Service Unavailable",
+                FailureReason::ServerError,
+            ),
+        ] {
+            assert_eq!(reason_of(line), Some(*reason), "{}", line);
+        }
+        assert_eq!(reason_of("ERROR: something else"), None);
+    }
+
+    #[test]
+    fn statuses_are_matched_before_network_errors() {
+        for (line, reason) in &[
+            (
+                "ERROR: [generic] Unable to download webpage: HTTP Error 403: Forbidden",
+                FailureReason::Forbidden,
+            ),
+            (
+                "ERROR: [generic] Unable to download webpage: HTTP Error 404: Not Found",
+                FailureReason::NotFound,
+            ),
+            (
+                "ERROR: [generic] Unable to download webpage: HTTP Error 410: Gone",
+                FailureReason::NotFound,
+            ),
+            (
+                "ERROR: [generic] Unable to download webpage: HTTP Error 429: Too Many Requests",
+                FailureReason::ClientError,
+            ),
+            (
+                "ERROR: [generic] Unable to download webpage: HTTP Error 502: Bad Gateway",
+                FailureReason::ServerError,
+            ),
+        ] {
+            assert_eq!(reason_of(line), Some(*reason), "{}", line);
+        }
+        assert!(!FailureReason::NotFound.is_transient());
+        assert!(!FailureReason::ClientError.is_transient());
+    }
+
+    #[test]
+    fn a_geo_block_is_told_before_the_status_it_came_with() {
+        let line = "ERROR: HTTP Error 403: Forbidden: this video is not available in your country";
+        assert_eq!(reason_of(line), Some(FailureReason::GeoBlocked));
+    }
+
+    #[test]
+    fn the_last_known_error_is_the_reason() {
+        let stderr = "WARNING: HTTP Error 503: Service Unavailable; retrying\n\
+                      ERROR: [youtube] x: Private video\n\
+                      some trailing output\n";
+        assert_eq!(
+            FailureReason::classify(stderr),
+            Some((FailureReason::Private, "ERROR: [youtube] x: Private video"))
+        );
+    }
+
+    #[test]
+    fn each_reason_is_parsed_from_its_name() {
+        for (reason, _) in PATTERNS {
+            assert_eq!(reason.as_str().parse(), Ok(*reason));
+        }
+        assert!("unknown".parse::<FailureReason>().is_err());
+    }
+}
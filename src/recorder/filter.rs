@@ -0,0 +1,89 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use chrono::Utc;
+
+use super::{Job, JobState};
+
+/// Selects finished jobs for bulk cleanup. Running and waiting jobs never match.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum JobFilter {
+    /// Jobs that failed.
+    Failed,
+    /// Jobs that produced no files.
+    Empty,
+    /// Jobs created longer ago than the duration.
+    OlderThan(Duration),
+    /// Jobs whose uploader or channel is the string.
+    Uploader(String),
+}
+
+impl JobFilter {
+    pub fn matches(&self, job: &Job) -> bool {
+        let state = job.state();
+        if job.is_running() || state == Some(JobState::Waiting) {
+            return false;
+        }
+        match self {
+            JobFilter::Failed => state == Some(JobState::Failed),
+            JobFilter::Empty => job.os_file_names().is_empty(),
+            JobFilter::OlderThan(age) => job
+                .created_at()
+                .and_then(|created_at| (Utc::now() - created_at).to_std().ok())
+                .is_some_and(|elapsed| elapsed > *age),
+            JobFilter::Uploader(uploader) => job.uploader().as_ref() == Some(uploader),
+        }
+    }
+}
+
+impl FromStr for JobFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "failed" => Ok(JobFilter::Failed),
+            "empty" => Ok(JobFilter::Empty),
+            _ => {
+                if let Some(age) = s.strip_prefix("older_than=") {
+                    parse_age(age).map(JobFilter::OlderThan)
+                } else if let Some(uploader) = s.strip_prefix("uploader=") {
+                    Ok(JobFilter::Uploader(uploader.to_owned()))
+                } else {
+                    Err(format!(
+                        "unknown filter {:?} (expected failed, empty, older_than=<age>, or uploader=<name>)",
+                        s
+                    ))
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for JobFilter {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            JobFilter::Failed => "failed".fmt(fmt),
+            JobFilter::Empty => "empty".fmt(fmt),
+            JobFilter::OlderThan(age) => write!(fmt, "older_than={}s", age.as_secs()),
+            JobFilter::Uploader(uploader) => write!(fmt, "uploader={}", uploader),
+        }
+    }
+}
+
+/// Parses an age such as `30d`, `12h`, `90m`, or `45s`.
+pub(super) fn parse_age(s: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid age {:?} (expected e.g. 30d, 12h, or 90m)", s);
+    let unit = match s.chars().last() {
+        Some('d') => 24 * 60 * 60,
+        Some('h') => 60 * 60,
+        Some('m') => 60,
+        Some('s') => 1,
+        _ => return Err(invalid()),
+    };
+    let count: u64 = s[..s.len() - 1].parse().map_err(|_| invalid())?;
+    count
+        .checked_mul(unit)
+        .map(Duration::from_secs)
+        .ok_or_else(invalid)
+}
@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use chrono::{DateTime, Utc};
+
+use super::{JobId, JobState, Runtime};
+
+/// What the jobs list shows of a job.
+#[derive(Clone, Debug)]
+pub struct JobSummary {
+    pub state: Option<JobState>,
+    pub file_names: Vec<String>,
+    pub title: Option<String>,
+    pub uploader: Option<String>,
+    pub runtime: Option<Runtime>,
+    /// See `Job::thumbnail_file_name`.
+    pub thumbnail: Option<String>,
+    /// The files named in `file_names`, in the same order.
+    pub files: Vec<FileSummary>,
+}
+
+/// What the jobs API tells of a job's file, so that clients need not ask for each.
+#[derive(Clone, Debug)]
+pub struct FileSummary {
+    pub name: String,
+    pub size: u64,
+    pub modified_at: Option<DateTime<Utc>>,
+    /// See `guess_mime`.
+    pub mime_type: String,
+}
+
+/// Summaries of finished jobs kept in memory, so that listing jobs reads only the jobs that
+/// changed rather than every file of every job.
+///
+/// The ids are read again when the work dir's modification time changes, i.e. when job dirs are
+/// added or removed. A summary is dropped when the recorder changes the job, through
+/// `invalidate`; summaries of jobs that are still going are not kept, as their files change
+/// without the recorder knowing.
+#[derive(Default)]
+pub(super) struct JobIndex {
+    ids: Mutex<Option<(SystemTime, Vec<JobId>)>>,
+    summaries: Mutex<HashMap<JobId, JobSummary>>,
+    /// Counts invalidations, so that a summary made while its job changed is not kept.
+    generation: AtomicU64,
+}
+
+impl JobIndex {
+    /// The ids of the jobs in the work dir, read with `read_ids` if the dir has changed since
+    /// last time.
+    pub fn ids(&self, work_dir: &Path, read_ids: impl FnOnce() -> Vec<JobId>) -> Vec<JobId> {
+        let modified = fs::metadata(work_dir).and_then(|metadata| metadata.modified());
+        let modified = match modified {
+            Ok(modified) => modified,
+            Err(_) => return read_ids(),
+        };
+
+        let mut ids = self.ids.lock().unwrap();
+        match &*ids {
+            Some((read_at, ids)) if *read_at == modified => ids.clone(),
+            _ => {
+                let read = read_ids();
+                self.summaries
+                    .lock()
+                    .unwrap()
+                    .retain(|job_id, _| read.contains(job_id));
+                *ids = Some((modified, read.clone()));
+                read
+            }
+        }
+    }
+
+    /// The summary of a job, made with `summarize` unless one is kept.
+    pub fn summary(&self, job_id: &JobId, summarize: impl FnOnce() -> JobSummary) -> JobSummary {
+        if let Some(summary) = self.summaries.lock().unwrap().get(job_id) {
+            return summary.clone();
+        }
+        let generation = self.generation.load(Ordering::SeqCst);
+        let summary = summarize();
+        let is_finished = matches!(
+            summary.state,
+            Some(
+                JobState::Succeeded
+                    | JobState::Failed
+                    | JobState::Cancelled
+                    | JobState::Interrupted
+            )
+        );
+        let mut summaries = self.summaries.lock().unwrap();
+        if is_finished && self.generation.load(Ordering::SeqCst) == generation {
+            summaries.insert(job_id.clone(), summary.clone());
+        }
+        summary
+    }
+
+    /// Drops the summary of a job that has changed.
+    pub fn invalidate(&self, job_id: &JobId) {
+        let mut summaries = self.summaries.lock().unwrap();
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        summaries.remove(job_id);
+    }
+}
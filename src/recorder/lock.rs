@@ -0,0 +1,37 @@
+use std::fs::{self, File, TryLockError};
+use std::io::Write;
+
+use super::{Recorder, RecorderError, Result};
+
+/// Present in the work dir, and locked while a vrec process uses it.
+const LOCK_FILE: &str = ".lock";
+
+/// Keeps other vrec processes out of the work dir until dropped.
+pub struct WorkDirLock {
+    _file: File,
+}
+
+impl Recorder {
+    /// Locks the work dir so that no other vrec process (e.g. a second server, or `--gc` while
+    /// a server is running) changes jobs at the same time. Fails with
+    /// `RecorderError::WorkDirLocked` if another process holds the lock.
+    pub fn lock(&self) -> Result<WorkDirLock> {
+        fs::create_dir_all(self.work_dir.path())?;
+        let path = self.work_dir.path().join(LOCK_FILE);
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => return Err(RecorderError::WorkDirLocked(path)),
+            Err(TryLockError::Error(err)) => return Err(err.into()),
+        }
+
+        // For finding the holder.
+        file.set_len(0)?;
+        writeln!(file, "{}", std::process::id())?;
+        Ok(WorkDirLock { _file: file })
+    }
+}
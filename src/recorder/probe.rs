@@ -0,0 +1,251 @@
+use std::collections::HashMap;
+use std::io::BufReader;
+use std::process::Stdio;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value as Json;
+
+use super::{guess_mime, is_partial_file_name, Job};
+use crate::platform;
+
+const FFPROBE: &str = "ffprobe";
+
+/// How long ffprobe may take on a file. It reads little more than the headers, so a file it is
+/// still reading after this is one made to keep it busy.
+const PROBE_TIME_LIMIT: Duration = Duration::from_secs(30);
+
+/// What ffprobe tells of a media file, to tell e.g. a 4K original from a 360p fallback.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MediaInfo {
+    pub duration_seconds: Option<f64>,
+    pub width: Option<u64>,
+    pub height: Option<u64>,
+    pub video_codec: Option<String>,
+    pub audio_codec: Option<String>,
+}
+
+/// A file as it was probed, kept in `info/media.json` by file name so that each file is probed
+/// only once unless it changes.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct ProbedFile {
+    size: u64,
+    /// Seconds since the epoch.
+    modified: u64,
+    /// `None` if ffprobe could not read the file, which is then not probed again either.
+    info: Option<MediaInfo>,
+}
+
+impl Job {
+    /// The media info of the job's finished video and audio files by name, probing those that
+    /// are new or have changed since they were last probed.
+    pub fn media_info(&self) -> HashMap<String, MediaInfo> {
+        let mut cache: HashMap<String, ProbedFile> = self
+            .job_dir
+            .open_file("info/media.json")
+            .ok()
+            .and_then(|f| serde_json::from_reader(BufReader::new(f)).ok())
+            .unwrap_or_default();
+        let mut is_changed = false;
+        let mut media_info = HashMap::new();
+
+        for name in self.file_names() {
+            let path = self.job_dir.path().join(&name);
+            let is_media = !is_partial_file_name(&name)
+                && [mime::VIDEO, mime::AUDIO].contains(&guess_mime(&path).type_());
+            let metadata = match path.metadata() {
+                Ok(metadata) if is_media => metadata,
+                _ => continue,
+            };
+            let size = metadata.len();
+            let modified = metadata
+                .modified()
+                .ok()
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |modified| modified.as_secs());
+
+            let is_fresh = cache
+                .get(&name)
+                .is_some_and(|probed| probed.size == size && probed.modified == modified);
+            if !is_fresh {
+                let info = self.probe(&name);
+                if info.is_none() {
+                    println!("job {} could not probe {}", self.job_id, name);
+                }
+                let probed = ProbedFile {
+                    size,
+                    modified,
+                    info,
+                };
+                cache.insert(name.clone(), probed);
+                is_changed = true;
+            }
+            if let Some(info) = cache.get(&name).and_then(|probed| probed.info.clone()) {
+                media_info.insert(name, info);
+            }
+        }
+
+        if is_changed {
+            // Files that have gone are dropped from the cache.
+            cache.retain(|name, _| self.job_dir.path().join(name).is_file());
+            let written = serde_json::to_vec(&cache)
+                .map_err(Into::into)
+                .and_then(|json| self.job_dir.write_file_atomically("info/media.json", &json));
+            if let Err(err) = written {
+                println!("job {} could not save media info: {}", self.job_id, err);
+            }
+        }
+        media_info
+    }
+}
+
+impl Job {
+    /// Runs ffprobe on one of the job's files in the job sandbox, as the user jobs run as, since
+    /// the file is as untrusted as the download that made it, and stops it after
+    /// `PROBE_TIME_LIMIT`.
+    fn probe(&self, file_name: &str) -> Option<MediaInfo> {
+        // Relative to the job dir, which ffprobe runs in, and never taken for an option.
+        let path = format!("./{}", file_name);
+        let args = [
+            "-v",
+            "error",
+            "-print_format",
+            "json",
+            "-show_format",
+            "-show_streams",
+            &path,
+        ];
+        let child = self
+            .command(FFPROBE, &args)
+            .and_then(|mut command| {
+                command
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::null())
+                    .spawn()
+            })
+            .ok()?;
+
+        let (done, wait_done) = mpsc::channel::<()>();
+        let (pid, job_id) = (child.id(), self.job_id.clone());
+        std::thread::spawn(move || {
+            if wait_done.recv_timeout(PROBE_TIME_LIMIT) == Err(RecvTimeoutError::Timeout) {
+                println!(
+                    "job {} stopping {} after the time limit of {}s",
+                    job_id,
+                    FFPROBE,
+                    PROBE_TIME_LIMIT.as_secs()
+                );
+                platform::interrupt(pid).ok();
+            }
+        });
+        let output = child.wait_with_output();
+        done.send(()).ok();
+
+        let output = output.ok().filter(|output| output.status.success())?;
+        let json: Json = serde_json::from_slice(&output.stdout).ok()?;
+        Some(parse_probe(&json))
+    }
+}
+
+/// Takes the duration from the container and the size and codec from the first video stream and
+/// the first audio stream of ffprobe's JSON. Cover art, which ffprobe lists as a video stream, is
+/// not taken for the video.
+fn parse_probe(json: &Json) -> MediaInfo {
+    let streams = json["streams"].as_array().cloned().unwrap_or_default();
+    let stream_of = |codec_type: &str| {
+        streams.iter().find(|stream| {
+            stream["codec_type"] == codec_type && stream["disposition"]["attached_pic"] != 1
+        })
+    };
+    let video = stream_of("video");
+    let audio = stream_of("audio");
+    MediaInfo {
+        duration_seconds: json["format"]["duration"]
+            .as_str()
+            .and_then(|duration| duration.parse().ok()),
+        width: video.and_then(|video| video["width"].as_u64()),
+        height: video.and_then(|video| video["height"].as_u64()),
+        video_codec: video.and_then(|video| video["codec_name"].as_str().map(str::to_owned)),
+        audio_codec: audio.and_then(|audio| audio["codec_name"].as_str().map(str::to_owned)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn video_is_taken_from_the_first_stream_that_is_not_cover_art() {
+        let json = json!({
+            "streams": [
+                {
+                    "codec_type": "video",
+                    "codec_name": "mjpeg",
+                    "width": 600,
+                    "height": 600,
+                    "disposition": { "attached_pic": 1 }
+                },
+                {
+                    "codec_type": "video",
+                    "codec_name": "h264",
+                    "width": 1920,
+                    "height": 1080,
+                    "disposition": { "attached_pic": 0 }
+                },
+                { "codec_type": "audio", "codec_name": "aac" },
+                { "codec_type": "audio", "codec_name": "opus" }
+            ],
+            "format": { "duration": "63.500000" }
+        });
+        assert_eq!(
+            parse_probe(&json),
+            MediaInfo {
+                duration_seconds: Some(63.5),
+                width: Some(1920),
+                height: Some(1080),
+                video_codec: Some("h264".to_owned()),
+                audio_codec: Some("aac".to_owned()),
+            }
+        );
+    }
+
+    #[test]
+    fn audio_with_only_cover_art_has_no_video() {
+        let json = json!({
+            "streams": [
+                { "codec_type": "audio", "codec_name": "mp3" },
+                {
+                    "codec_type": "video",
+                    "codec_name": "png",
+                    "width": 500,
+                    "height": 500,
+                    "disposition": { "attached_pic": 1 }
+                }
+            ],
+            "format": { "duration": "180.0" }
+        });
+        assert_eq!(
+            parse_probe(&json),
+            MediaInfo {
+                duration_seconds: Some(180.0),
+                audio_codec: Some("mp3".to_owned()),
+                ..MediaInfo::default()
+            }
+        );
+    }
+
+    #[test]
+    fn missing_and_malformed_fields_are_left_out() {
+        assert_eq!(parse_probe(&json!({})), MediaInfo::default());
+        let json = json!({
+            "streams": [{ "codec_type": "video", "width": "wide" }],
+            "format": { "duration": "N/A" }
+        });
+        assert_eq!(parse_probe(&json), MediaInfo::default());
+    }
+}
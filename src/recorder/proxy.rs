@@ -0,0 +1,197 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::domain::{host_of_args, matches_domain};
+
+/// A named proxy that jobs can be sent through, e.g. `tokyo=socks5://127.0.0.1:1080`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Proxy {
+    pub name: String,
+    pub url: String,
+}
+
+impl FromStr for Proxy {
+    type Err = String;
+
+    /// Parses a proxy such as `tokyo=socks5://127.0.0.1:1080`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "invalid proxy {:?} (expected e.g. tokyo=socks5://127.0.0.1:1080)",
+                s
+            )
+        };
+        let (name, url) = s.split_once('=').ok_or_else(invalid)?;
+        let (name, url) = (name.trim(), url.trim());
+        if name.is_empty() || url.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Proxy {
+            name: name.to_owned(),
+            url: url.to_owned(),
+        })
+    }
+}
+
+/// Sends the jobs of a site through a proxy of the pool, e.g. `youtube.com=tokyo`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProxyDomain {
+    /// A domain, which also matches its subdomains.
+    pub pattern: String,
+    /// The name of the proxy.
+    pub proxy: String,
+}
+
+impl FromStr for ProxyDomain {
+    type Err = String;
+
+    /// Parses a rule such as `youtube.com=tokyo`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "invalid proxy domain {:?} (expected e.g. youtube.com=tokyo)",
+                s
+            )
+        };
+        let (pattern, proxy) = s.split_once('=').ok_or_else(invalid)?;
+        let (pattern, proxy) = (pattern.trim().to_lowercase(), proxy.trim());
+        if pattern.is_empty() || proxy.is_empty() {
+            return Err(invalid());
+        }
+        Ok(ProxyDomain {
+            pattern,
+            proxy: proxy.to_owned(),
+        })
+    }
+}
+
+impl fmt::Display for ProxyDomain {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}={}", self.pattern, self.proxy)
+    }
+}
+
+/// Proxies that jobs are assigned to, by the rule of their site if there is one and in turn
+/// otherwise, so that bulk downloads don't all leave from one address.
+#[derive(Debug, Default)]
+pub struct ProxyPool {
+    proxies: Vec<Proxy>,
+    domains: Vec<ProxyDomain>,
+    next: AtomicUsize,
+}
+
+impl ProxyPool {
+    /// Fails if a rule in `domains` names a proxy that is not in `proxies`.
+    pub fn new(proxies: Vec<Proxy>, domains: Vec<ProxyDomain>) -> Result<Self, String> {
+        if let Some(domain) = domains
+            .iter()
+            .find(|domain| !proxies.iter().any(|proxy| proxy.name == domain.proxy))
+        {
+            return Err(format!(
+                "unknown proxy {:?} for {}",
+                domain.proxy, domain.pattern
+            ));
+        }
+        Ok(ProxyPool {
+            proxies,
+            domains,
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    pub fn proxies(&self) -> &[Proxy] {
+        &self.proxies
+    }
+
+    pub fn domains(&self) -> &[ProxyDomain] {
+        &self.domains
+    }
+
+    /// The proxy for a job with `args`, or `None` if the pool is empty or the args already
+    /// choose a proxy.
+    pub fn assign(&self, args: &[&str]) -> Option<&Proxy> {
+        if self.proxies.is_empty() || args.iter().any(|arg| is_proxy_option(arg)) {
+            return None;
+        }
+        let by_domain = host_of_args(args).and_then(|host| {
+            let domain = self
+                .domains
+                .iter()
+                .find(|domain| matches_domain(&domain.pattern, &host))?;
+            self.proxies.iter().find(|proxy| proxy.name == domain.proxy)
+        });
+        by_domain.or_else(|| {
+            let index = self.next.fetch_add(1, Ordering::Relaxed) % self.proxies.len();
+            self.proxies.get(index)
+        })
+    }
+}
+
+/// Whether the arg is `--proxy`, also as `--proxy=URL` or an abbreviation such as `--prox`, which
+/// the downloader accepts.
+fn is_proxy_option(arg: &str) -> bool {
+    let name = arg.split('=').next().unwrap_or(arg);
+    name.len() > "--pro".len() && "--proxy".starts_with(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool() -> ProxyPool {
+        let proxies = vec![
+            "tokyo=socks5://127.0.0.1:1080".parse().unwrap(),
+            "osaka=http://proxy.example.com:3128".parse().unwrap(),
+        ];
+        let domains = vec!["youtube.com=osaka".parse().unwrap()];
+        ProxyPool::new(proxies, domains).unwrap()
+    }
+
+    fn assigned(pool: &ProxyPool, args: &[&str]) -> Option<String> {
+        pool.assign(args).map(|proxy| proxy.name.clone())
+    }
+
+    #[test]
+    fn sites_with_a_rule_always_get_their_proxy() {
+        let pool = pool();
+        for _ in 0..3 {
+            let args = ["https://www.youtube.com/watch?v=x"];
+            assert_eq!(assigned(&pool, &args).as_deref(), Some("osaka"));
+        }
+    }
+
+    #[test]
+    fn other_sites_take_the_proxies_in_turn() {
+        let pool = pool();
+        let args = ["https://example.com/video"];
+        let names: Vec<_> = (0..3).filter_map(|_| assigned(&pool, &args)).collect();
+        assert_eq!(names, ["tokyo", "osaka", "tokyo"]);
+    }
+
+    #[test]
+    fn args_that_choose_a_proxy_get_none() {
+        let pool = pool();
+        for args in &[
+            ["--proxy", "http://other:3128"],
+            ["--proxy=http://other:3128", "https://example.com/video"],
+            ["--prox", "http://other:3128"],
+            ["--proxy=", "https://example.com/video"],
+        ] {
+            assert_eq!(assigned(&pool, args), None, "{:?}", args);
+        }
+        for args in &[
+            ["--progress", "https://example.com/video"],
+            ["--geo-verification-proxy", "http://other:3128"],
+        ] {
+            assert!(assigned(&pool, args).is_some(), "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn rules_must_name_a_proxy_of_the_pool() {
+        let domains = vec!["youtube.com=kyoto".parse().unwrap()];
+        let proxies = vec!["tokyo=socks5://127.0.0.1:1080".parse().unwrap()];
+        assert!(ProxyPool::new(proxies, domains).is_err());
+    }
+}
@@ -0,0 +1,470 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::domain::{host_of_args, DomainDelay, DomainLimit};
+use super::{platform, Job, JobId, JobOptions, JobState, Recorder, RecorderError, Result};
+
+/// Present in the work dir while the queue is paused.
+const PAUSED_FILE: &str = ".queue-paused";
+
+/// How long a worker holds a job it claimed without reporting on it, by default.
+pub const DEFAULT_WORKER_LEASE: Duration = Duration::from_secs(10 * 60);
+
+/// Holds back queued jobs while paused, e.g. during backups or peak network hours, while their
+/// site has as many jobs running as its limit allows or started one too recently, and jobs that
+/// are left to remote workers.
+#[derive(Clone)]
+pub(super) struct Queue {
+    paused_path: PathBuf,
+    /// Jobs running this command are run by workers instead of locally.
+    remote_command: Option<String>,
+    domain_limits: Arc<Vec<DomainLimit>>,
+    domain_delays: Arc<Vec<DomainDelay>>,
+    /// Jobs that were queued while paused, held for their domain, or for a worker, in order.
+    held: Arc<Mutex<Vec<HeldJob>>>,
+    domains: Arc<Mutex<DomainState>>,
+    /// Jobs that workers are running.
+    claimed: Arc<Mutex<HashMap<JobId, Claim>>>,
+    /// How long a claim lasts unless the worker renews it.
+    worker_lease: Duration,
+}
+
+/// A job that a worker is running, which goes back to the queue if the worker stops renewing
+/// the claim, e.g. because it died.
+struct Claim {
+    worker: String,
+    held_job: HeldJob,
+    expires_at: Instant,
+}
+
+/// What the domain limits and delays are checked against.
+#[derive(Default)]
+struct DomainState {
+    /// Running jobs that count against a domain limit, with the pattern of the limit.
+    running: HashMap<JobId, String>,
+    /// When a job last started, by the pattern of its domain delay.
+    last_started: HashMap<String, Instant>,
+    /// Patterns of domain delays after which held jobs are tried again.
+    wakeups: HashSet<String>,
+}
+
+/// Why a job can't start yet.
+enum Wait<'a> {
+    Limit(&'a DomainLimit),
+    Delay(&'a DomainDelay, Duration),
+}
+
+struct HeldJob {
+    job: Job,
+    command: String,
+    args: Vec<String>,
+    options: JobOptions,
+}
+
+/// A job handed to a worker.
+pub struct ClaimedJob {
+    pub job: Job,
+    pub args: Vec<String>,
+    pub time_limit: Option<Duration>,
+    /// Whether the worker should pass the cookies of its browser.
+    pub browser_cookies: bool,
+    /// How long the claim lasts unless the worker renews it.
+    pub lease: Duration,
+}
+
+impl Queue {
+    pub(super) fn new(work_dir: &Path) -> Self {
+        Queue {
+            paused_path: work_dir.join(PAUSED_FILE),
+            remote_command: None,
+            domain_limits: Arc::new(vec![]),
+            domain_delays: Arc::new(vec![]),
+            held: Arc::new(Mutex::new(vec![])),
+            domains: Arc::new(Mutex::new(DomainState::default())),
+            claimed: Arc::new(Mutex::new(HashMap::new())),
+            worker_lease: DEFAULT_WORKER_LEASE,
+        }
+    }
+
+    fn is_paused(&self) -> bool {
+        self.paused_path.exists()
+    }
+
+    fn is_remote(&self, command: &str) -> bool {
+        self.remote_command.as_deref() == Some(command)
+    }
+
+    /// Holds the job if the queue is paused, its site is at its limit or within its delay, or
+    /// the job is left to a worker, or returns the job's options to start it now.
+    pub(super) fn hold(
+        &self,
+        job: &Job,
+        command: &str,
+        args: &[&str],
+        options: JobOptions,
+    ) -> Option<JobOptions> {
+        let mut held = self.held.lock().unwrap();
+        if self.is_remote(command) {
+            println!("job {} waits for a worker", job.job_id);
+        } else if self.is_paused() {
+            println!("job {} is held while the queue is paused", job.job_id);
+        } else if let Err(wait) = self.take_slot(job, args) {
+            match wait {
+                Wait::Limit(limit) => println!(
+                    "job {} is held while {} is at its limit of {} running job(s)",
+                    job.job_id, limit.pattern, limit.max_running
+                ),
+                Wait::Delay(delay, remaining) => println!(
+                    "job {} is held for {}s to space out jobs of {}",
+                    job.job_id,
+                    remaining.as_secs(),
+                    delay.pattern
+                ),
+            }
+        } else {
+            return Some(options);
+        }
+        held.push(HeldJob {
+            job: job.clone(),
+            command: command.to_owned(),
+            args: args.iter().map(|&arg| arg.to_owned()).collect(),
+            options,
+        });
+        None
+    }
+
+    /// Counts the job against the limit and delay of its site, or returns why it has to wait.
+    /// Jobs waiting for the delay are tried again once it has passed.
+    fn take_slot(&self, job: &Job, args: &[&str]) -> std::result::Result<(), Wait<'_>> {
+        let host = match host_of_args(args) {
+            Some(host) => host,
+            None => return Ok(()),
+        };
+        let limit = self.domain_limits.iter().find(|limit| limit.matches(&host));
+        let delay = self.domain_delays.iter().find(|delay| delay.matches(&host));
+
+        let mut domains = self.domains.lock().unwrap();
+        if let Some(limit) = limit {
+            let running = domains
+                .running
+                .values()
+                .filter(|pattern| **pattern == limit.pattern)
+                .count();
+            if running >= limit.max_running {
+                return Err(Wait::Limit(limit));
+            }
+        }
+        if let Some(delay) = delay {
+            let elapsed = domains
+                .last_started
+                .get(&delay.pattern)
+                .map(Instant::elapsed);
+            if let Some(remaining) = elapsed.and_then(|elapsed| delay.delay.checked_sub(elapsed)) {
+                if domains.wakeups.insert(delay.pattern.clone()) {
+                    self.wake_after(delay.pattern.clone(), remaining);
+                }
+                return Err(Wait::Delay(delay, remaining));
+            }
+            domains
+                .last_started
+                .insert(delay.pattern.clone(), Instant::now());
+        }
+        if let Some(limit) = limit {
+            domains
+                .running
+                .insert(job.job_id.clone(), limit.pattern.clone());
+        }
+        Ok(())
+    }
+
+    /// Tries the held jobs again after a domain delay has passed.
+    fn wake_after(&self, pattern: String, after: Duration) {
+        let queue = self.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(after);
+            queue.domains.lock().unwrap().wakeups.remove(&pattern);
+            queue.start_held();
+        });
+    }
+
+    /// Frees the slot of a job that has ended and starts the jobs that were waiting for it.
+    pub(super) fn release(&self, job_id: &JobId) {
+        let released = self.domains.lock().unwrap().running.remove(job_id);
+        if released.is_some() {
+            self.start_held();
+        }
+    }
+
+    /// Starts the held jobs that may run now, in order, and returns how many were started.
+    fn start_held(&self) -> usize {
+        let startable: Vec<HeldJob> = {
+            let mut held = self.held.lock().unwrap();
+            if self.is_paused() {
+                return 0;
+            }
+            let mut startable = vec![];
+            let mut index = 0;
+            while index < held.len() {
+                let held_job = &held[index];
+                if self.is_remote(&held_job.command) {
+                    index += 1;
+                    continue;
+                }
+                // The job may have been deleted or cancelled meanwhile.
+                if held_job.job.state() != Some(JobState::Queued) {
+                    held.remove(index);
+                    continue;
+                }
+                let args: Vec<&str> = held_job.args.iter().map(String::as_str).collect();
+                if self.take_slot(&held_job.job, &args).is_ok() {
+                    startable.push(held.remove(index));
+                } else {
+                    index += 1;
+                }
+            }
+            startable
+        };
+
+        let mut count = 0;
+        for HeldJob {
+            job,
+            command,
+            args,
+            options,
+        } in startable
+        {
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+            match job.start_queued(&command, &args, options) {
+                Ok(()) => count += 1,
+                Err(err) => println!("job {} could not start: {}", job.job_id, err),
+            }
+        }
+        count
+    }
+}
+
+impl Recorder {
+    /// Stops starting queued jobs until `resume_queue` is called. Running jobs are left alone.
+    /// The pause survives restarts.
+    pub fn pause_queue(&self) -> Result<()> {
+        let _held = self.queue.held.lock().unwrap();
+        fs::write(&self.queue.paused_path, b"")?;
+        Ok(())
+    }
+
+    /// Starts the jobs queued while paused, in order, and returns how many were started. Jobs
+    /// over a domain limit stay queued until a slot frees up, and jobs left to workers until
+    /// claimed.
+    pub fn resume_queue(&self) -> Result<usize> {
+        {
+            let _held = self.queue.held.lock().unwrap();
+            match fs::remove_file(&self.queue.paused_path) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err.into()),
+                _ => {}
+            }
+        }
+        Ok(self.queue.start_held())
+    }
+
+    pub fn is_queue_paused(&self) -> bool {
+        self.queue.is_paused()
+    }
+
+    /// Limits how many jobs run at once for the sites that `limits` match. The first matching
+    /// limit applies to a job.
+    pub fn set_domain_limits(&mut self, limits: Vec<DomainLimit>) {
+        self.queue.domain_limits = Arc::new(limits);
+    }
+
+    /// Spaces out the starts of jobs for the sites that `delays` match, holding jobs in the
+    /// queue as needed. The first matching delay applies to a job.
+    pub fn set_domain_delays(&mut self, delays: Vec<DomainDelay>) {
+        self.queue.domain_delays = Arc::new(delays);
+    }
+
+    /// Leaves jobs that run `command` to workers, which take them with `claim_job`. Jobs
+    /// running other commands, such as downloader updates, still run locally.
+    pub fn dispatch_to_workers(&mut self, command: &str) {
+        self.queue.remote_command = Some(command.to_owned());
+    }
+
+    /// Sets how long a worker holds a job it claimed without renewing the claim.
+    pub fn set_worker_lease(&mut self, lease: Duration) {
+        self.queue.worker_lease = lease;
+    }
+
+    /// How long a worker holds a job it claimed without renewing the claim.
+    pub fn worker_lease(&self) -> Duration {
+        self.queue.worker_lease
+    }
+
+    /// Hands the oldest job waiting for a worker to `worker` and marks it running, or returns
+    /// `None` if there is none or the queue is paused. Jobs whose claims have expired are
+    /// handed out again.
+    pub fn claim_job(&self, worker: &str) -> Option<ClaimedJob> {
+        self.expire_worker_claims();
+        let mut held = self.queue.held.lock().unwrap();
+        if self.queue.is_paused() {
+            return None;
+        }
+        while let Some(index) = held
+            .iter()
+            .position(|held_job| self.queue.is_remote(&held_job.command))
+        {
+            let held_job = held.remove(index);
+            let job = held_job.job.clone();
+            // The job may have been deleted or cancelled meanwhile.
+            if job.state() != Some(JobState::Queued) {
+                continue;
+            }
+            let note = format!("claimed by worker {}", worker);
+            if let Err(err) = job.transition_with_note(JobState::Running, Some(&note)) {
+                println!("job {} transition failed: {}", job.job_id, err);
+                continue;
+            }
+            println!("job {} is claimed by worker {}", job.job_id, worker);
+            let claimed = ClaimedJob {
+                job: job.clone(),
+                args: held_job.args.clone(),
+                time_limit: held_job.options.time_limit,
+                browser_cookies: held_job.options.browser_cookies,
+                lease: self.queue.worker_lease,
+            };
+            let claim = Claim {
+                worker: worker.to_owned(),
+                held_job,
+                expires_at: Instant::now() + self.queue.worker_lease,
+            };
+            self.queue
+                .claimed
+                .lock()
+                .unwrap()
+                .insert(job.job_id.clone(), claim);
+            return Some(claimed);
+        }
+        None
+    }
+
+    /// The job with `job_id` if a worker is running it, renewing the worker's claim. A claim on
+    /// a job that has been cancelled or deleted meanwhile is dropped, so that the worker stops.
+    pub fn claimed_job(&self, job_id: &JobId) -> Result<Job> {
+        let mut claimed = self.queue.claimed.lock().unwrap();
+        let claim = claimed
+            .get_mut(job_id)
+            .ok_or_else(|| RecorderError::NotClaimed(job_id.clone()))?;
+        let job = match self.job(job_id) {
+            Some(job) if job.state() == Some(JobState::Running) => job,
+            job => {
+                claimed.remove(job_id);
+                return Err(match job {
+                    Some(_) => RecorderError::NotClaimed(job_id.clone()),
+                    None => RecorderError::JobNotFound(job_id.clone()),
+                });
+            }
+        };
+        claim.expires_at = Instant::now() + self.queue.worker_lease;
+        Ok(job)
+    }
+
+    /// Puts the jobs whose workers have not renewed their claims in time back in the queue for
+    /// other workers, and returns how many there were.
+    pub fn expire_worker_claims(&self) -> usize {
+        let expired: Vec<Claim> = {
+            let mut claimed = self.queue.claimed.lock().unwrap();
+            let now = Instant::now();
+            let job_ids: Vec<JobId> = claimed
+                .iter()
+                .filter(|(_, claim)| claim.expires_at <= now)
+                .map(|(job_id, _)| job_id.clone())
+                .collect();
+            job_ids
+                .iter()
+                .filter_map(|job_id| claimed.remove(job_id))
+                .collect()
+        };
+
+        let mut count = 0;
+        for Claim {
+            worker, held_job, ..
+        } in expired
+        {
+            let job = &held_job.job;
+            let note = format!("worker {} stopped renewing its claim", worker);
+            let requeued = job
+                .transition_with_note(JobState::Interrupted, Some(&note))
+                .and_then(|_| {
+                    job.transition_with_note(JobState::Queued, Some("waiting for another worker"))
+                });
+            if let Err(err) = requeued {
+                println!("job {} could not be requeued: {}", job.job_id, err);
+                continue;
+            }
+            println!("job {} is requeued: {}", job.job_id, note);
+            // Ahead of the jobs queued since it was claimed.
+            self.queue.held.lock().unwrap().insert(0, held_job);
+            count += 1;
+        }
+        count
+    }
+
+    /// Cancels a job that has not finished. A job a worker is running is dropped from its claim,
+    /// which the worker learns of when it next renews the claim, and a job running here is
+    /// interrupted.
+    pub fn cancel_job(&self, job_id: &JobId) -> Result<Job> {
+        let job = self
+            .job(job_id)
+            .ok_or_else(|| RecorderError::JobNotFound(job_id.clone()))?;
+        let claim = self.queue.claimed.lock().unwrap().remove(job_id);
+        let note = match &claim {
+            Some(claim) => format!("cancelled while worker {} ran it", claim.worker),
+            None => "cancelled".to_owned(),
+        };
+        // Marked first, so that the process ending is not taken as an interruption.
+        job.transition_with_note(JobState::Cancelled, Some(&note))?;
+        println!("job {} is {}", job.job_id, note);
+        if claim.is_none() {
+            if let Some(pid) = job.pid().filter(|_| job.is_running()) {
+                platform::interrupt(pid)?;
+            }
+        }
+        Ok(job)
+    }
+
+    /// Records how a worker's run of a job ended, with the output of its command. If it
+    /// succeeded, the post steps are run here on the files the worker sent.
+    pub fn finish_remote_job(
+        &self,
+        job_id: &JobId,
+        state: JobState,
+        stdout: &str,
+        stderr: &str,
+    ) -> Result<Job> {
+        let job = self.claimed_job(job_id)?;
+        let post_steps = self
+            .queue
+            .claimed
+            .lock()
+            .unwrap()
+            .remove(job_id)
+            .ok_or_else(|| RecorderError::NotClaimed(job_id.clone()))?
+            .held_job
+            .options
+            .post_steps;
+
+        job.job_dir
+            .write_file_atomically("info/stdout.txt", stdout.as_bytes())?;
+        job.job_dir
+            .write_file_atomically("info/stderr.txt", stderr.as_bytes())?;
+
+        let next = match state {
+            JobState::Succeeded => job.run_post_steps(post_steps),
+            state => state,
+        };
+        job.transition(next)?;
+        Ok(job)
+    }
+}
@@ -0,0 +1,75 @@
+use chrono::{DateTime, Duration, NaiveDate, TimeZone, Utc};
+use ulid::Ulid;
+
+use super::JobId;
+
+/// The bits of a ULID after its 48-bit timestamp.
+const RANDOM_BITS: u32 = 80;
+
+/// Jobs created between two times, both included.
+///
+/// Job ids are ULIDs, which sort by the time they were made, so the range is kept as the lowest
+/// and highest ids made within it and jobs are picked by comparing ids, without reading their
+/// dirs.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TimeRange {
+    min: Option<String>,
+    max: Option<String>,
+}
+
+impl TimeRange {
+    /// Parses the bounds of a range, each an RFC 3339 time such as `2021-05-01T09:00:00+09:00` or
+    /// a date such as `2021-05-01`. A date is taken as the whole day in UTC, so
+    /// `from=2021-05-01&to=2021-05-02` covers two days.
+    pub fn parse(from: Option<&str>, to: Option<&str>) -> Result<Self, String> {
+        let from = from
+            .filter(|s| !s.is_empty())
+            .map(|s| parse_time(s, false))
+            .transpose()?;
+        let to = to
+            .filter(|s| !s.is_empty())
+            .map(|s| parse_time(s, true))
+            .transpose()?;
+        if let (Some(from), Some(to)) = (from, to) {
+            if from > to {
+                return Err("from must not be later than to".to_owned());
+            }
+        }
+        Ok(TimeRange {
+            min: from.map(|from| ulid_at(from, false)),
+            max: to.map(|to| ulid_at(to, true)),
+        })
+    }
+
+    pub fn contains(&self, job_id: &JobId) -> bool {
+        let id = job_id.0.as_str();
+        self.min.as_deref().is_none_or(|min| min <= id)
+            && self.max.as_deref().is_none_or(|max| id <= max)
+    }
+}
+
+/// Parses a time, or a date as its first or, if `end` is true, last millisecond.
+fn parse_time(s: &str, end: bool) -> Result<DateTime<Utc>, String> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    let date = NaiveDate::parse_from_str(s, "%Y-%m-%d").map_err(|_| {
+        format!(
+            "invalid time {:?} (expected e.g. 2021-05-01 or 2021-05-01T09:00:00Z)",
+            s
+        )
+    })?;
+    let start = Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).unwrap());
+    Ok(if end {
+        start + Duration::days(1) - Duration::milliseconds(1)
+    } else {
+        start
+    })
+}
+
+/// The lowest, or if `highest` is true the highest, ULID made at `time`.
+fn ulid_at(time: DateTime<Utc>, highest: bool) -> String {
+    let timestamp = time.timestamp_millis().max(0) as u128;
+    let random = if highest { (1 << RANDOM_BITS) - 1 } else { 0 };
+    Ulid((timestamp << RANDOM_BITS) | random).to_string()
+}
@@ -0,0 +1,126 @@
+use std::time::Duration;
+
+use serde_json::Value as Json;
+
+use super::{Job, JobOptions, JobState, PostStep, Recorder};
+
+/// How often the process of an orphaned job is checked for having ended.
+const ORPHAN_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// What `Recorder::reconcile_jobs` found.
+#[derive(Default)]
+pub struct ReconcileReport {
+    /// Jobs that were running when vrec stopped, now marked interrupted.
+    pub interrupted: Vec<Job>,
+    /// Jobs that had started a process that is gone before being marked running.
+    pub failed: usize,
+    /// Jobs whose process outlived the previous vrec and is still running.
+    pub orphaned: usize,
+}
+
+impl Recorder {
+    /// Brings the recorded state of jobs in line with their processes after a restart, so that
+    /// no job is left "running" without a process. Jobs whose process is still running are
+    /// marked interrupted once it ends, as its exit status can't be known.
+    pub fn reconcile_jobs(&self) -> ReconcileReport {
+        let mut report = ReconcileReport::default();
+        for job in self.jobs() {
+            let state = job.state();
+            if !matches!(state, Some(JobState::Running | JobState::Queued)) {
+                continue;
+            }
+            // Queued jobs that never started are left to `resume_pending_jobs`.
+            if state == Some(JobState::Queued) && job.pid().is_none() {
+                continue;
+            }
+
+            if job.is_running() {
+                println!("job {} is still running without vrec", job.job_id);
+                report.orphaned += 1;
+                job.watch_orphan();
+            } else if state == Some(JobState::Running) {
+                let note = Some("vrec stopped while the job was running");
+                match job.transition_with_note(JobState::Interrupted, note) {
+                    Ok(()) => report.interrupted.push(job),
+                    Err(err) => println!("job {} transition failed: {}", job.job_id, err),
+                }
+            } else {
+                let note = Some("the process ended while vrec was stopped");
+                match job.transition_with_note(JobState::Failed, note) {
+                    Ok(()) => report.failed += 1,
+                    Err(err) => println!("job {} transition failed: {}", job.job_id, err),
+                }
+            }
+        }
+        report
+    }
+
+    /// Continues `jobs`, interrupted ones from `reconcile_jobs`, that were running `downloader`,
+    /// passing `--continue` so that the downloader picks up its partial files. Returns how many
+    /// jobs were continued.
+    pub fn resume_interrupted_jobs<F>(&self, jobs: &[Job], downloader: &str, post_steps: F) -> usize
+    where
+        F: Fn(&Json) -> Vec<PostStep>,
+    {
+        let mut count = 0;
+        for job in jobs {
+            if job.state() != Some(JobState::Interrupted) {
+                continue;
+            }
+            let invocation = match job.invocation() {
+                Some(invocation) if invocation["command"] == downloader => invocation,
+                _ => continue,
+            };
+            let mut args = match serde_json::from_value::<Vec<String>>(invocation["args"].clone()) {
+                Ok(args) => args,
+                Err(_) => continue,
+            };
+            if !args.iter().any(|arg| arg == "--continue" || arg == "-c") {
+                args.insert(0, "--continue".to_owned());
+            }
+
+            println!("job {} continues after a restart", job.job_id);
+            let note = Some("resumed with --continue after a restart");
+            if let Err(err) = job.transition_with_note(JobState::Queued, note) {
+                println!("job {} transition failed: {}", job.job_id, err);
+                continue;
+            }
+            let options = JobOptions {
+                post_steps: post_steps(&invocation),
+                start_at: None,
+                time_limit: invocation["time_limit"].as_u64().map(Duration::from_secs),
+                request_id: None,
+                idempotency_key: None,
+                browser_cookies: invocation["browser_cookies"] == true,
+                after: None,
+            };
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+            match job.enqueue(downloader, &args, options) {
+                Ok(()) => count += 1,
+                Err(err) => println!("job {} could not start: {}", job.job_id, err),
+            }
+        }
+        count
+    }
+}
+
+impl Job {
+    /// Marks the job interrupted (or failed, if it was not marked running yet) once its process,
+    /// which vrec can't wait for, has ended.
+    fn watch_orphan(&self) {
+        let job = self.clone();
+        std::thread::spawn(move || {
+            while job.is_running() {
+                std::thread::sleep(ORPHAN_POLL_INTERVAL);
+            }
+            let next = match job.state() {
+                Some(JobState::Running) => JobState::Interrupted,
+                _ => JobState::Failed,
+            };
+            let note = Some("the process ended while not supervised by vrec");
+            if let Err(err) = job.transition_with_note(next, note) {
+                println!("job {} transition failed: {}", job.job_id, err);
+            }
+        });
+    }
+}
@@ -0,0 +1,131 @@
+use std::io;
+use std::time::Duration;
+
+use chrono::Utc;
+use serde_json::json;
+
+use super::failure::STDERR_TAIL_LINES;
+use super::{FailureReason, Job, JobOptions, JobState};
+
+/// How jobs that fail for a reason that may pass, such as a network error, are run again.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Retries after the first run; 0 never retries.
+    pub limit: u32,
+    /// The wait before the first retry, doubled for each one after.
+    pub backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// The wait before retry `attempt`, counted from 1.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        self.backoff * 2u32.saturating_pow(attempt.saturating_sub(1).min(16))
+    }
+}
+
+impl Job {
+    /// How many times the job has been retried, i.e. moved from running back to waiting.
+    pub fn retry_count(&self) -> u32 {
+        self.state_record()
+            .map(|record| {
+                record
+                    .transitions
+                    .windows(2)
+                    .filter(|pair| {
+                        pair[0].state == JobState::Running && pair[1].state == JobState::Waiting
+                    })
+                    .count() as u32
+            })
+            .unwrap_or(0)
+    }
+
+    /// Makes the job that has just failed wait and run again, if its error is transient and it
+    /// has retries left, and returns whether it will. The retry is recorded as a move to
+    /// `Waiting` noting the attempt and the error, and its time as the invocation's `start_at`,
+    /// so that it is resumed after a restart.
+    pub(super) fn retry_later(&self, command: &str, mut options: JobOptions) -> bool {
+        let policy = self.retry_policy;
+        let attempt = self.retry_count() + 1;
+        if attempt > policy.limit {
+            return false;
+        }
+        let reason = self
+            .read_log_tail("info/stderr.txt", STDERR_TAIL_LINES)
+            .ok()
+            .and_then(|stderr| FailureReason::classify(&stderr).map(|(reason, _)| reason))
+            .filter(|reason| reason.is_transient());
+        let reason = match reason {
+            Some(reason) => reason,
+            None => return false,
+        };
+        let mut invocation = match self.invocation() {
+            Some(invocation) => invocation,
+            None => return false,
+        };
+        let args: Vec<String> = match serde_json::from_value(invocation["args"].clone()) {
+            Ok(args) => args,
+            Err(_) => return false,
+        };
+
+        let delay = policy.delay(attempt);
+        let start_at = Utc::now() + chrono::Duration::from_std(delay).unwrap_or_default();
+        invocation["start_at"] = json!(start_at);
+        let written = serde_json::to_vec(&invocation)
+            .map_err(io::Error::from)
+            .and_then(|json| {
+                self.job_dir
+                    .write_file_atomically("info/invocation.json", &json)
+            });
+        if let Err(err) = written {
+            println!("job {} could not be retried: {}", self.job_id, err);
+            return false;
+        }
+        let note = format!(
+            "retry {} of {} in {}s after a {} error",
+            attempt,
+            policy.limit,
+            delay.as_secs(),
+            reason
+        );
+        if let Err(err) = self.transition_with_note(JobState::Waiting, Some(&note)) {
+            println!("job {} could not be retried: {}", self.job_id, err);
+            return false;
+        }
+
+        println!(
+            "job {} failed with a {} error; {}",
+            self.job_id, reason, note
+        );
+        options.start_at = Some(start_at);
+        self.clone().wait(command.to_owned(), args, options);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_delay_doubles_with_each_attempt() {
+        let policy = RetryPolicy {
+            limit: 3,
+            backoff: Duration::from_secs(30),
+        };
+        let delays: Vec<u64> = (1..=4)
+            .map(|attempt| policy.delay(attempt).as_secs())
+            .collect();
+        assert_eq!(delays, [30, 60, 120, 240]);
+        assert_eq!(policy.delay(0), policy.delay(1));
+    }
+
+    #[test]
+    fn the_delay_stops_growing_at_a_cap() {
+        let policy = RetryPolicy {
+            limit: u32::MAX,
+            backoff: Duration::from_secs(1),
+        };
+        assert_eq!(policy.delay(17), Duration::from_secs(1 << 16));
+        assert_eq!(policy.delay(u32::MAX), Duration::from_secs(1 << 16));
+    }
+}
@@ -0,0 +1,89 @@
+use std::env;
+use std::fmt;
+use std::path::Path;
+use std::process::Command;
+use std::str::FromStr;
+
+/// How job commands are isolated from the host.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Sandbox {
+    /// Runs the command as is.
+    #[default]
+    None,
+    /// Clears the environment except `PATH` and `LANG`, and points `HOME` at the job dir.
+    Env,
+    /// Like `Env`, and runs the command in bubblewrap with a read-only view of the host where only
+    /// the job dir is writable.
+    Bubblewrap,
+}
+
+impl Sandbox {
+    /// Builds a command that runs `program` in `dir`, which must be absolute.
+    pub fn command(self, dir: &Path, program: &str, args: &[&str]) -> Command {
+        let mut command = match self {
+            Sandbox::None | Sandbox::Env => Command::new(program),
+            Sandbox::Bubblewrap => {
+                let mut command = Command::new("bwrap");
+                command
+                    .args(["--ro-bind", "/", "/"])
+                    .args(["--dev", "/dev", "--proc", "/proc", "--tmpfs", "/tmp"])
+                    .arg("--bind")
+                    .arg(dir)
+                    .arg(dir)
+                    .args(["--unshare-all", "--share-net", "--new-session"])
+                    .arg("--chdir")
+                    .arg(dir)
+                    .args(["--", program]);
+                command
+            }
+        };
+
+        command.args(args).current_dir(dir);
+
+        if self != Sandbox::None {
+            command.env_clear().env("HOME", dir);
+            for name in &["PATH", "LANG"] {
+                if let Some(value) = env::var_os(name) {
+                    command.env(name, value);
+                }
+            }
+        }
+
+        command
+    }
+
+    /// Returns the wrapper executable this sandbox needs, if any.
+    pub fn wrapper(self) -> Option<&'static str> {
+        match self {
+            Sandbox::None | Sandbox::Env => None,
+            Sandbox::Bubblewrap => Some("bwrap"),
+        }
+    }
+}
+
+impl FromStr for Sandbox {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Sandbox::None),
+            "env" => Ok(Sandbox::Env),
+            "bwrap" => Ok(Sandbox::Bubblewrap),
+            _ => Err(format!(
+                "unknown sandbox {:?} (expected none, env, or bwrap)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Sandbox {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Sandbox::None => "none",
+            Sandbox::Env => "env",
+            Sandbox::Bubblewrap => "bwrap",
+        }
+        .fmt(fmt)
+    }
+}
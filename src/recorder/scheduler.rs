@@ -0,0 +1,108 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use super::{Job, JobId, JobOptions, JobState};
+
+/// The longest the scheduler sleeps at once, so that jobs still start on time after the clock
+/// jumps, e.g. when the machine wakes from sleep.
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+
+/// Queues jobs once their `start_at` has come, from a single thread.
+///
+/// The schedule itself is kept in the job dirs, as the `Waiting` state of each job and the
+/// `start_at` of its invocation, so that `Recorder::resume_pending_jobs` can fill the scheduler
+/// again after a restart.
+#[derive(Clone, Default)]
+pub(super) struct Scheduler {
+    inner: Arc<(Mutex<Schedule>, Condvar)>,
+}
+
+#[derive(Default)]
+struct Schedule {
+    jobs: BTreeMap<(DateTime<Utc>, JobId), ScheduledJob>,
+    /// Whether the thread that starts the jobs is running.
+    started: bool,
+}
+
+struct ScheduledJob {
+    job: Job,
+    command: String,
+    args: Vec<String>,
+    options: JobOptions,
+}
+
+impl Scheduler {
+    /// Queues the job at `options.start_at`, replacing the time it was scheduled at before.
+    pub(super) fn add(&self, job: Job, command: String, args: Vec<String>, options: JobOptions) {
+        let start_at = options.start_at.unwrap_or_else(Utc::now);
+        let (schedule, wakeup) = &*self.inner;
+        let mut schedule = schedule.lock().unwrap();
+        schedule.jobs.retain(|(_, job_id), _| *job_id != job.job_id);
+        schedule.jobs.insert(
+            (start_at, job.job_id.clone()),
+            ScheduledJob {
+                job,
+                command,
+                args,
+                options,
+            },
+        );
+        if !schedule.started {
+            schedule.started = true;
+            let scheduler = self.clone();
+            std::thread::spawn(move || scheduler.run());
+        }
+        wakeup.notify_one();
+    }
+
+    /// The jobs that are still waiting, soonest first, with when they start.
+    pub(super) fn jobs(&self) -> Vec<(DateTime<Utc>, Job)> {
+        let (schedule, _) = &*self.inner;
+        let schedule = schedule.lock().unwrap();
+        schedule
+            .jobs
+            .iter()
+            .map(|((start_at, _), scheduled)| (*start_at, scheduled.job.clone()))
+            .collect()
+    }
+
+    fn run(&self) {
+        let (schedule, wakeup) = &*self.inner;
+        let mut guard = schedule.lock().unwrap();
+        loop {
+            let now = Utc::now();
+            let next = guard.jobs.keys().next().cloned();
+            match next {
+                Some(key) if key.0 <= now => {
+                    let scheduled = guard.jobs.remove(&key).expect("scheduled job must exist");
+                    drop(guard);
+                    scheduled.start();
+                    guard = schedule.lock().unwrap();
+                }
+                next => {
+                    let wait = next
+                        .and_then(|(start_at, _)| (start_at - now).to_std().ok())
+                        .map_or(MAX_SLEEP, |wait| wait.min(MAX_SLEEP));
+                    guard = wakeup.wait_timeout(guard, wait).unwrap().0;
+                }
+            }
+        }
+    }
+}
+
+impl ScheduledJob {
+    /// Queues the job, unless it has been cancelled or deleted while it waited.
+    fn start(self) {
+        let job = self.job;
+        if job.state() != Some(JobState::Waiting) {
+            return;
+        }
+        let args: Vec<&str> = self.args.iter().map(String::as_str).collect();
+        if let Err(err) = job.run(&self.command, &args, self.options) {
+            println!("job {} could not start: {}", job.job_id, err);
+        }
+    }
+}
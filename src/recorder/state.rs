@@ -0,0 +1,254 @@
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Lifecycle state of a job.
+///
+/// ```text
+/// Created ----------> Queued -> Running -> Succeeded
+///    |                  ^  |          |-----> Failed
+///    |-> Waiting -------'  |          |-----> Cancelled
+///    |      `-> Cancelled  |          `-----> Interrupted
+///    |                     |                        |
+///    |                     |<-----------------------' (resumed)
+///    |                     |-> Failed (could not spawn)
+///    |                     `-> Cancelled
+///    |-> Succeeded (imported)
+///    `-> Cancelled
+/// ```
+///
+/// A running job that fails with a transient error also goes back to `Waiting` to be retried.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    Created,
+    /// Scheduled to start later, or after another job.
+    Waiting,
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+    Interrupted,
+}
+
+impl JobState {
+    pub fn can_transition_to(self, next: JobState) -> bool {
+        use JobState::*;
+
+        matches!(
+            (self, next),
+            (Created, Queued)
+                | (Created, Waiting)
+                | (Created, Cancelled)
+                // Imported jobs have nothing to run.
+                | (Created, Succeeded)
+                | (Waiting, Queued)
+                | (Waiting, Cancelled)
+                | (Queued, Running)
+                | (Queued, Failed)
+                | (Queued, Cancelled)
+                | (Running, Succeeded)
+                | (Running, Failed)
+                | (Running, Cancelled)
+                | (Running, Interrupted)
+                // Retried later after a transient failure.
+                | (Running, Waiting)
+                // Continued after a restart.
+                | (Interrupted, Queued)
+        )
+    }
+
+    pub fn as_str(self) -> &'static str {
+        use JobState::*;
+
+        match self {
+            Created => "created",
+            Waiting => "waiting",
+            Queued => "queued",
+            Running => "running",
+            Succeeded => "succeeded",
+            Failed => "failed",
+            Cancelled => "cancelled",
+            Interrupted => "interrupted",
+        }
+    }
+}
+
+impl fmt::Display for JobState {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        self.as_str().fmt(fmt)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Transition {
+    pub state: JobState,
+    pub at: DateTime<Utc>,
+    /// Why the transition happened, if it was not the job's own doing, e.g. a restart.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+/// Contents of `info/state.json`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct StateRecord {
+    pub transitions: Vec<Transition>,
+}
+
+impl StateRecord {
+    pub fn state(&self) -> Option<JobState> {
+        self.transitions.last().map(|t| t.state)
+    }
+
+    /// Appends a transition to `next`, rejecting it if the current state does not allow it.
+    /// An empty record only accepts `Created`.
+    pub fn push(&mut self, next: JobState, note: Option<String>) -> Result<(), InvalidTransition> {
+        let allowed = match self.state() {
+            Some(current) => current.can_transition_to(next),
+            None => next == JobState::Created,
+        };
+
+        if !allowed {
+            return Err(InvalidTransition {
+                from: self.state(),
+                to: next,
+            });
+        }
+
+        self.transitions.push(Transition {
+            state: next,
+            at: Utc::now(),
+            note,
+        });
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidTransition {
+    pub from: Option<JobState>,
+    pub to: JobState,
+}
+
+impl fmt::Display for InvalidTransition {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self.from {
+            Some(from) => write!(fmt, "invalid job state transition {} -> {}", from, self.to),
+            None => write!(fmt, "invalid initial job state {}", self.to),
+        }
+    }
+}
+
+impl std::error::Error for InvalidTransition {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use JobState::*;
+
+    const ALL: [JobState; 8] = [
+        Created,
+        Waiting,
+        Queued,
+        Running,
+        Succeeded,
+        Failed,
+        Cancelled,
+        Interrupted,
+    ];
+
+    #[test]
+    fn only_the_drawn_transitions_are_allowed() {
+        let allowed = [
+            (Created, Queued),
+            (Created, Waiting),
+            (Created, Cancelled),
+            (Created, Succeeded),
+            (Waiting, Queued),
+            (Waiting, Cancelled),
+            (Queued, Running),
+            (Queued, Failed),
+            (Queued, Cancelled),
+            (Running, Succeeded),
+            (Running, Failed),
+            (Running, Cancelled),
+            (Running, Interrupted),
+            (Running, Waiting),
+            (Interrupted, Queued),
+        ];
+        for &from in &ALL {
+            for &to in &ALL {
+                assert_eq!(
+                    from.can_transition_to(to),
+                    allowed.contains(&(from, to)),
+                    "{} -> {}",
+                    from,
+                    to
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn finished_jobs_stay_finished() {
+        for &from in &[Succeeded, Failed, Cancelled] {
+            assert!(ALL.iter().all(|&to| !from.can_transition_to(to)));
+        }
+    }
+
+    #[test]
+    fn a_record_starts_with_created() {
+        let mut record = StateRecord::default();
+        let err = record.push(Queued, None).unwrap_err();
+        assert_eq!(err.from, None);
+        assert_eq!(err.to, Queued);
+        assert!(record.transitions.is_empty());
+
+        record.push(Created, None).unwrap();
+        assert_eq!(record.state(), Some(Created));
+    }
+
+    #[test]
+    fn a_refused_transition_leaves_the_record_as_it_was() {
+        let mut record = StateRecord::default();
+        record.push(Created, None).unwrap();
+        record.push(Queued, None).unwrap();
+        record.push(Running, None).unwrap();
+        record.push(Succeeded, None).unwrap();
+
+        let err = record.push(Running, None).unwrap_err();
+        assert_eq!(err.from, Some(Succeeded));
+        assert_eq!(err.to.to_string(), "running");
+        assert_eq!(record.transitions.len(), 4);
+        assert_eq!(record.state(), Some(Succeeded));
+    }
+
+    #[test]
+    fn the_history_survives_being_saved_and_loaded() {
+        let mut record = StateRecord::default();
+        record.push(Created, None).unwrap();
+        record.push(Queued, None).unwrap();
+        record.push(Running, None).unwrap();
+        record
+            .push(Interrupted, Some("restarted".to_owned()))
+            .unwrap();
+
+        let json = serde_json::to_string(&record).unwrap();
+        let mut loaded: StateRecord = serde_json::from_str(&json).unwrap();
+        let states: Vec<JobState> = loaded.transitions.iter().map(|t| t.state).collect();
+        assert_eq!(states, vec![Created, Queued, Running, Interrupted]);
+        assert_eq!(loaded.transitions[3].note.as_deref(), Some("restarted"));
+        assert!(loaded.transitions[0].note.is_none());
+        assert_eq!(
+            loaded.transitions.iter().map(|t| t.at).collect::<Vec<_>>(),
+            record.transitions.iter().map(|t| t.at).collect::<Vec<_>>()
+        );
+
+        // The loaded record goes on checking transitions from where it left off.
+        assert!(loaded.push(Succeeded, None).is_err());
+        loaded.push(Queued, Some("resumed".to_owned())).unwrap();
+        assert_eq!(loaded.state(), Some(Queued));
+    }
+}
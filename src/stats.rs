@@ -0,0 +1,187 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+
+use chrono::{Datelike, Duration, NaiveDate, Utc};
+use serde::Serialize;
+
+use crate::disk_stat::{ByteUnits, DiskStat};
+use crate::platform;
+use crate::recorder::{guess_mime, JobState, Recorder};
+
+const DAYS: i64 = 30;
+const WEEKS: i64 = 12;
+const TOP_UPLOADERS: usize = 10;
+
+/// Aggregates over all jobs.
+#[derive(Debug, Serialize)]
+pub struct Stats {
+    pub job_count: usize,
+    /// Job counts by state.
+    pub states: BTreeMap<String, usize>,
+    /// Succeeded jobs out of the succeeded and failed ones, if any.
+    pub success_ratio: Option<f64>,
+    /// Jobs created per day over the last 30 days, oldest first.
+    pub per_day: Vec<PeriodCount>,
+    /// Jobs created per ISO week over the last 12 weeks, oldest first.
+    pub per_week: Vec<PeriodCount>,
+    /// Bytes stored by MIME type (`video`, `audio`, `image`, ...), largest first. Files
+    /// hard-linked between jobs count once.
+    pub bytes_by_type: Vec<TypeSize>,
+    pub top_uploaders: Vec<UploaderCount>,
+    /// Space and inodes of the disk the jobs are on, if known.
+    pub disk: Option<DiskStat>,
+    /// Seconds taken by the last runs of jobs, of those that have finished.
+    pub total_runtime_seconds: i64,
+    /// The mean of those runs, if any.
+    pub mean_runtime_seconds: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PeriodCount {
+    pub period: String,
+    pub count: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TypeSize {
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub bytes: u64,
+    pub size: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UploaderCount {
+    pub uploader: String,
+    pub count: usize,
+}
+
+/// Returns how many jobs each uploader has, by name.
+pub fn uploader_counts(recorder: &Recorder) -> Vec<UploaderCount> {
+    let mut uploaders: BTreeMap<String, usize> = BTreeMap::new();
+    for job in recorder.jobs() {
+        if let Some(uploader) = job.uploader() {
+            *uploaders.entry(uploader).or_default() += 1;
+        }
+    }
+    uploaders
+        .into_iter()
+        .map(|(uploader, count)| UploaderCount { uploader, count })
+        .collect()
+}
+
+impl Stats {
+    /// Counts the recorder's jobs; `disk` is the stat of the disk they are on, if known. Sizes
+    /// are humanized in `units`.
+    pub fn new(recorder: &Recorder, disk: Option<DiskStat>, units: ByteUnits) -> Stats {
+        let jobs = recorder.jobs();
+        let today = Utc::now().date_naive();
+
+        let mut states = BTreeMap::new();
+        let mut days: BTreeMap<NaiveDate, usize> =
+            (0..DAYS).map(|i| (today - Duration::days(i), 0)).collect();
+        let mut weeks: BTreeMap<(i32, u32), usize> = (0..WEEKS)
+            .map(|i| {
+                let week = (today - Duration::weeks(i)).iso_week();
+                ((week.year(), week.week()), 0)
+            })
+            .collect();
+        let mut bytes: HashMap<String, u64> = HashMap::new();
+        let mut seen_files = HashSet::new();
+        let mut uploaders: HashMap<String, usize> = HashMap::new();
+        let mut total_runtime = Duration::zero();
+        let mut timed_runs = 0;
+
+        for job in &jobs {
+            let state = job.state().map(JobState::as_str).unwrap_or("unknown");
+            *states.entry(state.to_owned()).or_default() += 1;
+
+            if let Some(created_at) = job.created_at() {
+                let date = created_at.date_naive();
+                if let Some(count) = days.get_mut(&date) {
+                    *count += 1;
+                }
+                let week = date.iso_week();
+                if let Some(count) = weeks.get_mut(&(week.year(), week.week())) {
+                    *count += 1;
+                }
+            }
+
+            for name in job.file_names() {
+                let metadata = match fs::metadata(job.path().join(&name)) {
+                    Ok(metadata) => metadata,
+                    Err(_) => continue,
+                };
+                if let Some(file_id) = platform::file_id(&metadata) {
+                    if !seen_files.insert(file_id) {
+                        continue;
+                    }
+                }
+                let type_ = guess_mime(&job.path().join(&name)).type_().to_string();
+                *bytes.entry(type_).or_default() += metadata.len();
+            }
+
+            if let Some(uploader) = job.uploader() {
+                *uploaders.entry(uploader).or_default() += 1;
+            }
+
+            if let Some(runtime) = job
+                .runtime()
+                .filter(|runtime| runtime.finished_at.is_some())
+            {
+                total_runtime += runtime.duration();
+                timed_runs += 1;
+            }
+        }
+
+        let succeeded = states.get("succeeded").copied().unwrap_or(0);
+        let failed = states.get("failed").copied().unwrap_or(0);
+        let success_ratio = Some(succeeded + failed)
+            .filter(|&finished| finished > 0)
+            .map(|finished| succeeded as f64 / finished as f64);
+
+        let mut bytes_by_type: Vec<TypeSize> = bytes
+            .into_iter()
+            .map(|(type_, bytes)| TypeSize {
+                type_,
+                bytes,
+                size: units.humanize(bytes),
+            })
+            .collect();
+        bytes_by_type.sort_by(|a, b| b.bytes.cmp(&a.bytes).then(a.type_.cmp(&b.type_)));
+
+        let mut top_uploaders: Vec<UploaderCount> = uploaders
+            .into_iter()
+            .map(|(uploader, count)| UploaderCount { uploader, count })
+            .collect();
+        top_uploaders.sort_by(|a, b| b.count.cmp(&a.count).then(a.uploader.cmp(&b.uploader)));
+        top_uploaders.truncate(TOP_UPLOADERS);
+
+        Stats {
+            job_count: jobs.len(),
+            states,
+            success_ratio,
+            per_day: days
+                .into_iter()
+                .map(|(date, count)| PeriodCount {
+                    period: date.to_string(),
+                    count,
+                })
+                .collect(),
+            per_week: weeks
+                .into_iter()
+                .map(|((year, week), count)| PeriodCount {
+                    period: format!("{}-W{:02}", year, week),
+                    count,
+                })
+                .collect(),
+            bytes_by_type,
+            top_uploaders,
+            disk,
+            total_runtime_seconds: total_runtime.num_seconds(),
+            mean_runtime_seconds: Some(timed_runs)
+                .filter(|&runs| runs > 0)
+                .map(|runs| total_runtime.num_seconds() / runs),
+        }
+    }
+}
@@ -0,0 +1,395 @@
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::config::Config;
+use crate::downloader;
+use crate::recorder::Recorder;
+
+/// How often subscriptions are looked at to see whether one is due.
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A channel or playlist whose new entries are downloaded as they appear.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Subscription {
+    pub id: String,
+    pub url: String,
+    /// Minutes between checks for new entries.
+    pub interval_minutes: u64,
+    pub created_at: DateTime<Utc>,
+    pub checked_at: Option<DateTime<Utc>>,
+    /// Whether the entries that were already there when it was added are downloaded too.
+    pub backfill: bool,
+    /// Why the last check failed, if it did.
+    pub error: Option<String>,
+}
+
+impl Subscription {
+    fn is_due(&self, now: DateTime<Utc>) -> bool {
+        let interval = chrono::Duration::minutes(self.interval_minutes as i64);
+        self.checked_at
+            .is_none_or(|checked_at| checked_at + interval <= now)
+    }
+}
+
+/// The subscriptions, kept in `$VAR_DIR/subscriptions.json`, and the archive of the entries they
+/// have downloaded, kept in `$VAR_DIR/subscription-archive.txt` in the format of youtube-dl's
+/// `--download-archive`.
+pub struct Subscriptions {
+    config: Arc<Config>,
+    /// Held while the files are read and written.
+    lock: Mutex<()>,
+}
+
+impl Subscriptions {
+    pub fn new(config: Arc<Config>) -> Self {
+        Subscriptions {
+            config,
+            lock: Mutex::new(()),
+        }
+    }
+
+    pub fn list(&self) -> io::Result<Vec<Subscription>> {
+        let _lock = self.lock.lock().unwrap();
+        self.read()
+    }
+
+    pub fn add(
+        &self,
+        url: &str,
+        interval_minutes: u64,
+        backfill: bool,
+    ) -> io::Result<Subscription> {
+        let subscription = Subscription {
+            id: ulid::Ulid::new().to_string(),
+            url: url.to_owned(),
+            interval_minutes,
+            created_at: Utc::now(),
+            checked_at: None,
+            backfill,
+            error: None,
+        };
+        let _lock = self.lock.lock().unwrap();
+        let mut subscriptions = self.read()?;
+        subscriptions.push(subscription.clone());
+        self.write(&subscriptions)?;
+        Ok(subscription)
+    }
+
+    /// Removes a subscription and returns whether there was one. Its jobs and the entries in the
+    /// archive are kept.
+    pub fn remove(&self, id: &str) -> io::Result<bool> {
+        let _lock = self.lock.lock().unwrap();
+        let mut subscriptions = self.read()?;
+        let count = subscriptions.len();
+        subscriptions.retain(|subscription| subscription.id != id);
+        if subscriptions.len() == count {
+            return Ok(false);
+        }
+        self.write(&subscriptions)?;
+        Ok(true)
+    }
+
+    /// Checks the subscriptions that are due on a new thread, every `CHECK_INTERVAL`.
+    pub fn start(self: &Arc<Self>, recorder: Recorder) {
+        let subscriptions = self.clone();
+        std::thread::spawn(move || loop {
+            let now = Utc::now();
+            match subscriptions.list() {
+                Ok(list) => {
+                    for subscription in list {
+                        if subscription.is_due(now) && !recorder.is_read_only() {
+                            subscriptions.check(&recorder, &subscription);
+                        }
+                    }
+                }
+                Err(err) => println!("checking subscriptions failed: {}", err),
+            }
+            std::thread::sleep(CHECK_INTERVAL);
+        });
+    }
+
+    /// Lists the entries of a subscription and spawns jobs for those not in the archive. The
+    /// first check of one without `backfill` only adds the entries to the archive.
+    fn check(&self, recorder: &Recorder, subscription: &Subscription) {
+        let result =
+            downloader::playlist_entries(&self.config, &subscription.url).and_then(|entries| {
+                let mut archive = self.read_archive();
+                let mut spawned = 0;
+                for entry in entries {
+                    if !archive.insert(entry.key.clone()) {
+                        continue;
+                    }
+                    if subscription.checked_at.is_some() || subscription.backfill {
+                        self.spawn_job(recorder, subscription, &entry.url)?;
+                        spawned += 1;
+                    }
+                    self.append_to_archive(&entry.key)?;
+                }
+                Ok(spawned)
+            });
+        let error = match result {
+            Ok(0) => None,
+            Ok(spawned) => {
+                println!(
+                    "subscription {} spawned {} job(s)",
+                    subscription.id, spawned
+                );
+                None
+            }
+            Err(err) => {
+                println!("subscription {} check failed: {}", subscription.id, err);
+                Some(err.to_string())
+            }
+        };
+
+        let _lock = self.lock.lock().unwrap();
+        let saved = self.read().and_then(|mut subscriptions| {
+            if let Some(checked) = subscriptions.iter_mut().find(|s| s.id == subscription.id) {
+                checked.checked_at = Some(Utc::now());
+                checked.error = error;
+            }
+            self.write(&subscriptions)
+        });
+        if let Err(err) = saved {
+            println!("saving subscriptions failed: {}", err);
+        }
+    }
+
+    fn spawn_job(
+        &self,
+        recorder: &Recorder,
+        subscription: &Subscription,
+        url: &str,
+    ) -> io::Result<()> {
+        let args = downloader::with_output_template(
+            &["--write-all-thumbnails", "--write-info-json", url],
+            self.config.output_template.as_deref(),
+        )
+        .map_err(io::Error::other)?;
+        let job = recorder
+            .spawn_job(&self.config.downloader, &args)
+            .map_err(io::Error::other)?;
+        job.set_metadata(&json!({ "subscription_id": subscription.id }))
+            .map_err(io::Error::other)?;
+        println!(
+            "subscription {} spawned job {} for {}",
+            subscription.id,
+            job.id(),
+            url
+        );
+        Ok(())
+    }
+
+    /// Reads the subscriptions, or none if there is no file yet. A file that can't be parsed is
+    /// an error rather than no subscriptions, so that the next write doesn't replace it; it is
+    /// left for the user to fix or remove.
+    fn read(&self) -> io::Result<Vec<Subscription>> {
+        let path = self.config.subscriptions_path();
+        let json = match fs::read(&path) {
+            Ok(json) => json,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(err),
+        };
+        serde_json::from_slice(&json).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} could not be parsed: {}", path.display(), err),
+            )
+        })
+    }
+
+    fn write(&self, subscriptions: &[Subscription]) -> io::Result<()> {
+        let path = self.config.subscriptions_path();
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_vec_pretty(subscriptions)?)?;
+        fs::rename(tmp_path, path)
+    }
+
+    fn read_archive(&self) -> HashSet<String> {
+        fs::read_to_string(self.config.subscription_archive_path())
+            .map(|archive| archive.lines().map(str::to_owned).collect())
+            .unwrap_or_default()
+    }
+
+    fn append_to_archive(&self, key: &str) -> io::Result<()> {
+        let _lock = self.lock.lock().unwrap();
+        let mut f = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.config.subscription_archive_path())?;
+        writeln!(f, "{}", key)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// A var dir in the temp dir with a fake downloader that lists `playlist.json`, removed when
+    /// dropped.
+    struct TestDir(PathBuf);
+
+    impl TestDir {
+        fn new() -> Self {
+            let path =
+                std::env::temp_dir().join(format!("vrec-subscription-{}", ulid::Ulid::new()));
+            fs::create_dir_all(&path).unwrap();
+            let downloader = path.join("downloader");
+            let script = format!(
+                "#!/bin/sh\n[ \"$1\" = -J ] && cat '{}'\nexit 0\n",
+                path.join("playlist.json").display()
+            );
+            fs::write(&downloader, script).unwrap();
+            fs::set_permissions(&downloader, fs::Permissions::from_mode(0o755)).unwrap();
+            TestDir(path)
+        }
+
+        fn subscriptions(&self) -> Arc<Subscriptions> {
+            let mut config = Config::from_env();
+            config.var_dir = self.0.clone();
+            config.downloader = self.0.join("downloader").display().to_string();
+            config.output_template = None;
+            config.sandbox = Default::default();
+            config.job_uid = None;
+            config.job_gid = None;
+            Arc::new(Subscriptions::new(Arc::new(config)))
+        }
+
+        fn set_playlist(&self, ids: &[&str]) {
+            let entries: Vec<_> = ids
+                .iter()
+                .map(|id| json!({ "id": id, "url": format!("https://example.com/{}", id) }))
+                .collect();
+            let playlist = json!({ "extractor_key": "Example", "entries": entries });
+            fs::write(self.0.join("playlist.json"), playlist.to_string()).unwrap();
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            fs::remove_dir_all(&self.0).ok();
+        }
+    }
+
+    fn job_urls(recorder: &Recorder) -> Vec<String> {
+        let mut urls: Vec<_> = recorder
+            .jobs()
+            .iter()
+            .filter_map(|job| job.invocation())
+            .filter_map(|invocation| {
+                let args = invocation["args"].as_array()?.clone();
+                args.last()?.as_str().map(str::to_owned)
+            })
+            .collect();
+        urls.sort();
+        urls
+    }
+
+    fn archive(subscriptions: &Subscriptions) -> Vec<String> {
+        let mut archive: Vec<_> = subscriptions.read_archive().into_iter().collect();
+        archive.sort();
+        archive
+    }
+
+    #[test]
+    fn subscriptions_are_added_and_removed() {
+        let dir = TestDir::new();
+        let subscriptions = dir.subscriptions();
+        assert!(subscriptions.list().unwrap().is_empty());
+
+        let first = subscriptions
+            .add("https://example.com/a", 60, false)
+            .unwrap();
+        let second = subscriptions
+            .add("https://example.com/b", 30, true)
+            .unwrap();
+        let ids: Vec<_> = subscriptions
+            .list()
+            .unwrap()
+            .into_iter()
+            .map(|s| s.id)
+            .collect();
+        assert_eq!(ids, vec![first.id.clone(), second.id.clone()]);
+
+        assert!(subscriptions.remove(&first.id).unwrap());
+        assert!(!subscriptions.remove(&first.id).unwrap());
+        let list = subscriptions.list().unwrap();
+        assert_eq!(list.len(), 1);
+        assert_eq!(list[0].url, "https://example.com/b");
+        assert_eq!(list[0].interval_minutes, 30);
+        assert!(list[0].backfill);
+    }
+
+    #[test]
+    fn a_file_that_cannot_be_parsed_is_not_overwritten() {
+        let dir = TestDir::new();
+        let subscriptions = dir.subscriptions();
+        let path = subscriptions.config.subscriptions_path();
+        fs::write(&path, "[{\"id\": ").unwrap();
+
+        assert!(subscriptions.list().is_err());
+        assert!(subscriptions
+            .add("https://example.com/a", 60, false)
+            .is_err());
+        assert!(subscriptions.remove("01ARZ3NDEKTSV4RRFFQ69G5FAV").is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "[{\"id\": ");
+    }
+
+    #[test]
+    fn the_first_check_only_archives_the_entries_unless_backfilled() {
+        let dir = TestDir::new();
+        let subscriptions = dir.subscriptions();
+        let recorder = subscriptions.config.recorder();
+        dir.set_playlist(&["1", "2"]);
+
+        let subscription = subscriptions
+            .add("https://example.com/list", 60, false)
+            .unwrap();
+        subscriptions.check(&recorder, &subscription);
+        assert!(job_urls(&recorder).is_empty());
+        assert_eq!(archive(&subscriptions), vec!["example 1", "example 2"]);
+
+        let subscription = subscriptions.list().unwrap().remove(0);
+        assert!(subscription.checked_at.is_some());
+        assert_eq!(subscription.error, None);
+
+        dir.set_playlist(&["1", "2", "3"]);
+        subscriptions.check(&recorder, &subscription);
+        assert_eq!(job_urls(&recorder), vec!["https://example.com/3"]);
+        assert_eq!(
+            archive(&subscriptions),
+            vec!["example 1", "example 2", "example 3"]
+        );
+    }
+
+    #[test]
+    fn a_backfilled_subscription_downloads_the_entries_already_there() {
+        let dir = TestDir::new();
+        let subscriptions = dir.subscriptions();
+        let recorder = subscriptions.config.recorder();
+        dir.set_playlist(&["1", "2"]);
+
+        let subscription = subscriptions
+            .add("https://example.com/list", 60, true)
+            .unwrap();
+        subscriptions.check(&recorder, &subscription);
+        assert_eq!(
+            job_urls(&recorder),
+            vec!["https://example.com/1", "https://example.com/2"]
+        );
+
+        let subscription = subscriptions.list().unwrap().remove(0);
+        subscriptions.check(&recorder, &subscription);
+        assert_eq!(job_urls(&recorder).len(), 2);
+    }
+}
@@ -0,0 +1,96 @@
+use mime::Mime;
+
+/// Whether the file is a subtitle file that the player can show, as WebVTT or as SubRip converted
+/// by `srt_to_vtt`.
+pub fn is_subtitle_file_name(file_name: &str) -> bool {
+    subtitle_mime(file_name).is_some()
+}
+
+/// The content type of a subtitle file, which both formats give as UTF-8.
+pub fn subtitle_mime(file_name: &str) -> Option<Mime> {
+    let lower = file_name.to_lowercase();
+    let mime = if lower.ends_with(".vtt") {
+        "text/vtt; charset=utf-8"
+    } else if lower.ends_with(".srt") {
+        "application/x-subrip; charset=utf-8"
+    } else {
+        return None;
+    };
+    Some(mime.parse().expect("subtitle mime must be valid"))
+}
+
+/// The language of the subtitle file if it is one for the media file, i.e. named like
+/// `<name>.<lang>.vtt` for `<name>.mp4` as youtube-dl writes them, or `<name>.vtt` with no
+/// language, which is then empty.
+pub fn subtitle_language<'a>(
+    media_file_name: &str,
+    subtitle_file_name: &'a str,
+) -> Option<&'a str> {
+    if !is_subtitle_file_name(subtitle_file_name) {
+        return None;
+    }
+    let stem = media_file_name
+        .rsplit_once('.')
+        .map_or(media_file_name, |(stem, _)| stem);
+    let rest = subtitle_file_name.strip_prefix(stem)?.strip_prefix('.')?;
+    // `rest` is `<ext>` or `<lang>.<ext>`; a longer one belongs to another file named after this.
+    match rest.rsplit_once('.') {
+        None => Some(""),
+        Some((lang, _)) if !lang.is_empty() && !lang.contains('.') => Some(lang),
+        Some(_) => None,
+    }
+}
+
+/// Converts SubRip subtitles to WebVTT, which is the only format browsers show in `<track>`.
+///
+/// Cue numbers are kept as cue identifiers, and only the commas of the timings change, as the
+/// formatting tags of SubRip that browsers know are the same in WebVTT.
+pub fn srt_to_vtt(srt: &str) -> String {
+    let srt = srt.strip_prefix('\u{feff}').unwrap_or(srt);
+    let mut vtt = String::from("WEBVTT\n\n");
+    for line in srt.lines() {
+        if line.contains("-->") {
+            vtt.push_str(&line.replace(',', "."));
+        } else {
+            vtt.push_str(line);
+        }
+        vtt.push('\n');
+    }
+    vtt
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn srt_is_converted_to_vtt() {
+        let srt = "\u{feff}1\r\n00:00:01,000 --> 00:00:02,500\r\nHello, <i>world</i>\r\n\r\n\
+                   2\r\n00:01:00,250 --> 00:01:02,000\r\nBye\r\n";
+        assert_eq!(
+            srt_to_vtt(srt),
+            "WEBVTT\n\n\
+             1\n00:00:01.000 --> 00:00:02.500\nHello, <i>world</i>\n\n\
+             2\n00:01:00.250 --> 00:01:02.000\nBye\n"
+        );
+    }
+
+    #[test]
+    fn subtitles_are_matched_to_the_media_file_by_name() {
+        assert_eq!(subtitle_language("name.mp4", "name.en.srt"), Some("en"));
+        assert_eq!(
+            subtitle_language("name.mp4", "name.pt-BR.vtt"),
+            Some("pt-BR")
+        );
+        assert_eq!(subtitle_language("name.mp4", "name.srt"), Some(""));
+        assert_eq!(subtitle_language("name.mp4", "name.part2.en.srt"), None);
+        assert_eq!(
+            subtitle_language("name.part2.mp4", "name.part2.en.srt"),
+            Some("en")
+        );
+        assert_eq!(subtitle_language("name.mp4", "other.en.srt"), None);
+        assert_eq!(subtitle_language("name.mp4", "name..srt"), None);
+        assert_eq!(subtitle_language("name.mp4", "name.en.txt"), None);
+        assert_eq!(subtitle_language("name.mp4", "names.en.vtt"), None);
+    }
+}
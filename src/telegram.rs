@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde_json::{json, Value as Json};
+
+use crate::config::Config;
+use crate::disk_stat::DiskStatCache;
+use crate::downloader;
+use crate::notify::{CurlConfig, CURL};
+use crate::recorder::{Job, JobId, JobState, Recorder};
+
+/// How long a `getUpdates` request waits for messages, in seconds.
+const POLL_TIMEOUT: u64 = 50;
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// A bot that downloads the URLs allowlisted users send it, and replies when the jobs finish.
+pub struct TelegramBot {
+    config: Arc<Config>,
+    token: String,
+    disk_stats: Arc<DiskStatCache>,
+    /// Chats to reply to when jobs finish.
+    chats: Mutex<HashMap<JobId, i64>>,
+}
+
+impl TelegramBot {
+    /// Returns a bot if `TELEGRAM_BOT_TOKEN` and `TELEGRAM_ALLOWED_USER_IDS` are set.
+    pub fn from_config(
+        config: Arc<Config>,
+        disk_stats: Arc<DiskStatCache>,
+    ) -> Option<Arc<TelegramBot>> {
+        let token = config.telegram_bot()?.to_owned();
+        Some(Arc::new(TelegramBot {
+            config,
+            token,
+            disk_stats,
+            chats: Mutex::new(HashMap::new()),
+        }))
+    }
+
+    /// Polls for messages on a new thread.
+    pub fn start(self: &Arc<Self>, recorder: Recorder) {
+        let bot = self.clone();
+        std::thread::spawn(move || {
+            let mut offset = 0;
+            loop {
+                match bot.get_updates(offset) {
+                    Ok(updates) => {
+                        for update in updates {
+                            if let Some(update_id) = update["update_id"].as_i64() {
+                                offset = offset.max(update_id + 1);
+                            }
+                            bot.handle_message(&recorder, &update["message"]);
+                        }
+                    }
+                    Err(err) => {
+                        println!("telegram: getUpdates failed: {}", err);
+                        std::thread::sleep(RETRY_INTERVAL);
+                    }
+                }
+            }
+        });
+    }
+
+    /// Replies to the chat that started the job once it has finished.
+    pub fn job_state_changed(self: &Arc<Self>, job: &Job, state: JobState) {
+        if !matches!(
+            state,
+            JobState::Succeeded | JobState::Failed | JobState::Cancelled | JobState::Interrupted
+        ) {
+            return;
+        }
+        let chat_id = match self.chats.lock().unwrap().remove(job.id()) {
+            Some(chat_id) => chat_id,
+            None => return,
+        };
+
+        let bot = self.clone();
+        let job = job.clone();
+        std::thread::spawn(move || {
+            let mut text = format!("Job {} {}", bot.config.job_url(job.id()), state);
+            if let Some(title) = job.title() {
+                text.push_str(&format!("\n{}", title));
+            }
+            if state == JobState::Failed {
+                if let Some(line) = job.last_stderr_line() {
+                    text.push_str(&format!("\n{}", line));
+                }
+            }
+            bot.send_message(chat_id, &text);
+        });
+    }
+
+    fn handle_message(&self, recorder: &Recorder, message: &Json) {
+        let (user_id, chat_id, text) = match (
+            message["from"]["id"].as_i64(),
+            message["chat"]["id"].as_i64(),
+            message["text"].as_str(),
+        ) {
+            (Some(user_id), Some(chat_id), Some(text)) => (user_id, chat_id, text),
+            _ => return,
+        };
+
+        if !self.config.telegram_allowed_user_ids.contains(&user_id) {
+            println!("telegram: ignoring message from user {}", user_id);
+            self.send_message(
+                chat_id,
+                &format!("You are not allowed to use this bot (user id {}).", user_id),
+            );
+            return;
+        }
+
+        if recorder.is_read_only() {
+            self.send_message(
+                chat_id,
+                "vrec is in maintenance mode and is not accepting new jobs. Try again later.",
+            );
+            return;
+        }
+
+        let mut finder = linkify::LinkFinder::new();
+        finder.kinds(&[linkify::LinkKind::Url]);
+        let urls: Vec<&str> = finder
+            .links(text)
+            .map(|link| link.as_str())
+            .filter(|url| url.starts_with("http://") || url.starts_with("https://"))
+            .collect();
+        if urls.is_empty() {
+            self.send_message(chat_id, "Send me a URL to download.");
+            return;
+        }
+
+        for url in urls {
+            let reply = match self.spawn_job(recorder, url) {
+                Ok(job) => {
+                    self.chats.lock().unwrap().insert(job.id().clone(), chat_id);
+                    format!("Started job {}", self.config.job_url(job.id()))
+                }
+                Err(err) => format!("Could not download {}: {}", url, err),
+            };
+            self.send_message(chat_id, &reply);
+        }
+    }
+
+    fn spawn_job(&self, recorder: &Recorder, url: &str) -> Result<Job, String> {
+        println!("telegram: downloading {:?}", url);
+        let args = downloader::with_output_template(
+            &["--write-all-thumbnails", "--write-info-json", url],
+            self.config.output_template.as_deref(),
+        )?;
+        let estimate = downloader::check_size(&self.config, &self.disk_stats, &args)?;
+        let job = recorder
+            .spawn_job(&self.config.downloader, &args)
+            .map_err(|err| err.to_string())?;
+        if let Some(estimate) = estimate {
+            job.set_metadata(&estimate.to_metadata()).ok();
+        }
+        Ok(job)
+    }
+
+    fn get_updates(&self, offset: i64) -> io::Result<Vec<Json>> {
+        let result = self.call(
+            "getUpdates",
+            &json!({
+                "offset": offset,
+                "timeout": POLL_TIMEOUT,
+                "allowed_updates": ["message"],
+            }),
+            POLL_TIMEOUT + 10,
+        )?;
+        Ok(result.as_array().cloned().unwrap_or_default())
+    }
+
+    fn send_message(&self, chat_id: i64, text: &str) {
+        let body = json!({ "chat_id": chat_id, "text": text });
+        if let Err(err) = self.call("sendMessage", &body, 30) {
+            println!("telegram: sendMessage failed: {}", err);
+        }
+    }
+
+    /// Calls a Bot API method and returns its result.
+    fn call(&self, method: &str, body: &Json, max_time: u64) -> io::Result<Json> {
+        let url = format!("https://api.telegram.org/bot{}/{}", self.token, method);
+        let config = CurlConfig::new(&[("url", &url)])?;
+        let mut child = Command::new(CURL)
+            .args(["-sS", "-X", "POST", "-H", "Content-Type: application/json"])
+            .args(["--data-binary", "@-", "--max-time"])
+            .arg(max_time.to_string())
+            .arg("-K")
+            .arg(config.path())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(&serde_json::to_vec(body)?)?;
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            return Err(io::Error::other(format!(
+                "curl exited with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+
+        let response: Json = serde_json::from_slice(&output.stdout)?;
+        if response["ok"].as_bool() != Some(true) {
+            let description = response["description"].as_str().unwrap_or("request failed");
+            return Err(io::Error::other(description.to_owned()));
+        }
+        Ok(response["result"].clone())
+    }
+}
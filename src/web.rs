@@ -1,53 +1,443 @@
-use std::path::PathBuf;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
 
-use actix_web::{App, HttpServer};
-use handlebars::Handlebars;
+use actix_web::dev::Service;
+use actix_web::{middleware, web, App, HttpServer};
+use chrono::{DateTime, Utc};
 use listenfd::ListenFd;
 
-use crate::recorder::{start_child_reaper, Recorder};
+use crate::config::Config;
+use crate::digest::{self, Digest};
+use crate::disk_stat::{humanize_byte_size, DiskMonitor, DiskStatCache};
+use crate::notify::{Email, Event, Notification, Notifier, Notifiers};
+use crate::postprocess;
+use crate::recorder::{compare_file_names, Job, JobState, Recorder};
+use crate::subscription::Subscriptions;
+use crate::telegram::TelegramBot;
+use crate::web::audit::AuditLog;
+use crate::web::events::Broadcaster;
+use crate::web::graphql::JobEvents;
+use crate::web::history::SubmissionHistory;
+use crate::web::idempotency::PendingKeys;
+use crate::web::security_headers::SecurityHeaders;
 use crate::web::services::{configure_app, AppData};
 
+mod api_error;
+mod assets;
+mod audit;
+mod events;
+mod file_watch;
+mod graphql;
 mod helpers;
+mod history;
+mod idempotency;
+mod preflight;
+mod request_id;
+mod security_headers;
+mod systemd;
+mod thumbnail;
+
+pub use self::security_headers::{DEFAULT_CONTENT_SECURITY_POLICY, DEFAULT_FRAME_OPTIONS};
 mod services;
+mod templates;
 
-pub async fn start() -> std::io::Result<()> {
-    dotenv::dotenv().ok();
+const MAINTENANCE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const DISK_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// How long a disk stat is reused. The background thread takes it again twice as often, so that
+/// requests rarely wait for the file system.
+const DISK_STAT_TTL: Duration = Duration::from_secs(10);
 
-    start_child_reaper();
+pub async fn start() -> io::Result<()> {
+    let config = Arc::new(Config::from_env());
+    preflight::check(&config)?;
+    assert!(config.access_key.is_some(), "ACCESS_KEY must be set");
+    let started_at = Utc::now();
 
-    let mut listenfd = ListenFd::from_env();
+    let broadcaster = Broadcaster::create();
+    let job_events = Arc::new(JobEvents::default());
 
-    let mut server = HttpServer::new(move || {
-        let access_key = std::env::var("ACCESS_KEY").expect("ACCESS_KEY must be set");
+    let notifiers = Arc::new(Notifiers::from_config(&config));
 
-        let mut handlebars = Handlebars::new();
-        helpers::register_handlebars_helpers(&mut handlebars);
-        handlebars
-            .register_templates_directory(".hbs", "./templates")
-            .expect("Handlebars must initialize");
+    let disk_stats = Arc::new(DiskStatCache::new(config.jobs_dir(), DISK_STAT_TTL));
+    {
+        let disk_stats = disk_stats.clone();
+        std::thread::spawn(move || loop {
+            disk_stats.refresh();
+            std::thread::sleep(DISK_STAT_TTL / 2);
+        });
+    }
 
-        let var_dir_path = dotenv::var("VAR_DIR").unwrap_or_else(|_| "var".to_owned());
-        let recorder_dir_path = PathBuf::from(var_dir_path).join("jobs");
+    let telegram_bot = TelegramBot::from_config(config.clone(), disk_stats.clone());
 
-        let recorder = Recorder::new(recorder_dir_path);
+    let mut recorder = config.recorder();
+    // Held until the server stops.
+    let _lock = recorder
+        .lock()
+        .map_err(|err| io::Error::other(err.to_string()))?;
+    if config.worker_key.is_some() {
+        recorder.dispatch_to_workers(&config.downloader);
+    }
+    let b = broadcaster.clone();
+    let e = job_events.clone();
+    let n = notifiers.clone();
+    let c = config.clone();
+    let t = telegram_bot.clone();
+    recorder.set_listener(Arc::new(move |job, state| {
+        b.send_job_state(job, state);
+        e.publish(job, state);
+        notify_job_state(&n, &c, job, state);
+        if c.email_reply {
+            reply_to_sender(&c, job, state);
+        }
+        if c.clean_partial_files {
+            clean_partial_files(job, state);
+        }
+        if let Some(t) = &t {
+            t.job_state_changed(job, state);
+        }
+    }));
+    let report = recorder.reconcile_jobs();
+    println!(
+        "reconciled jobs: {} interrupted, {} failed, {} still running",
+        report.interrupted.len(),
+        report.failed,
+        report.orphaned
+    );
+    recorder.resume_pending_jobs(postprocess::steps_from_invocation);
+    if config.resume_interrupted {
+        let resumed = recorder.resume_interrupted_jobs(
+            &report.interrupted,
+            &config.downloader,
+            postprocess::steps_from_invocation,
+        );
+        println!("resumed {} interrupted job(s)", resumed);
+    }
 
-        let data = AppData {
-            access_key,
-            recorder,
-            handlebars,
-        };
+    recorder.load_index();
+
+    if let Some(bot) = &telegram_bot {
+        bot.start(recorder.clone());
+    }
+
+    file_watch::start(recorder.clone(), broadcaster.clone(), config.byte_units);
 
-        App::new().data(data).configure(configure_app)
+    let subscriptions = Arc::new(Subscriptions::new(config.clone()));
+    subscriptions.start(recorder.clone());
+
+    if config.worker_key.is_some() {
+        // Also checked on each claim, but no worker may be left to claim.
+        let recorder = recorder.clone();
+        let interval = (recorder.worker_lease() / 4).max(Duration::from_secs(1));
+        std::thread::spawn(move || loop {
+            std::thread::sleep(interval);
+            recorder.expire_worker_claims();
+        });
+    }
+
+    let retention = config.trash_retention();
+    let compact_age = config.compact_age();
+    if retention.is_some() || compact_age.is_some() {
+        let recorder = recorder.clone();
+        let repack = config.compact_repack;
+        std::thread::spawn(move || loop {
+            if let Some(retention) = retention {
+                if let Err(err) = recorder.purge_trash(retention) {
+                    println!("purging trash failed: {}", err);
+                }
+            }
+            if let Some(compact_age) = compact_age {
+                if let Err(err) = recorder.compact_old_jobs(compact_age, repack) {
+                    println!("compacting jobs failed: {}", err);
+                }
+            }
+            std::thread::sleep(MAINTENANCE_INTERVAL);
+        });
+    }
+
+    let disk_monitor = config.low_disk_threshold.map(|threshold| {
+        let monitor = Arc::new(DiskMonitor::new(threshold));
+        let m = monitor.clone();
+        let config = config.clone();
+        let notifiers = notifiers.clone();
+        let disk_stats = disk_stats.clone();
+        std::thread::spawn(move || loop {
+            if let Some(stat) = disk_stats.get().and_then(|stat| m.check(stat)) {
+                let notification = Notification {
+                    event: Event::LowDisk,
+                    title: "Low disk space".to_owned(),
+                    message: format!(
+                        "{} available of {} for {}",
+                        config.byte_units.humanize(stat.available),
+                        config.byte_units.humanize(stat.total),
+                        config.jobs_dir().display()
+                    ),
+                    url: None,
+                };
+                notifiers.send(&notification);
+            }
+            std::thread::sleep(DISK_CHECK_INTERVAL);
+        });
+        monitor
+    });
+
+    if let Some(digest_at) = config.digest_at {
+        let recorder = recorder.clone();
+        let config = config.clone();
+        let notifiers = notifiers.clone();
+        std::thread::spawn(move || loop {
+            let next = digest::next_time(digest_at, config.display_timezone, Utc::now());
+            if let Ok(wait) = (next - Utc::now()).to_std() {
+                std::thread::sleep(wait);
+            }
+            let now = Utc::now();
+            let digest = Digest::new(&recorder, &config, digest::last_sent_at(&config, now));
+            notifiers.send(&digest.to_notification(&config));
+            if let Err(err) = digest.save(&config, now) {
+                println!("saving the digest failed: {}", err);
+            }
+        });
+    }
+
+    let mut listenfd = ListenFd::from_env();
+
+    // Shared by the workers so that their appends don't interleave.
+    let history = Arc::new(SubmissionHistory::new(config.history_path()));
+    let audit_log = Arc::new(AuditLog::new(config.audit_log_path()));
+    let pending_keys = Arc::new(PendingKeys::default());
+    let security_headers = Arc::new(SecurityHeaders::new(&config));
+
+    let server_config = config.clone();
+    let mut server = HttpServer::new(move || {
+        let data = app_data(
+            server_config.clone(),
+            recorder.clone(),
+            job_events.clone(),
+            broadcaster.clone(),
+            history.clone(),
+            audit_log.clone(),
+            pending_keys.clone(),
+            disk_stats.clone(),
+            subscriptions.clone(),
+            disk_monitor.clone(),
+            started_at,
+        );
+        let security_headers = security_headers.clone();
+
+        App::new()
+            .wrap(middleware::Compress::default())
+            .wrap(api_error::error_handlers())
+            .wrap_fn(request_id::assign)
+            .wrap_fn(move |req, service| {
+                let security_headers = security_headers.clone();
+                let response = service.call(req);
+                async move {
+                    let mut response = response.await?;
+                    security_headers.apply(response.headers_mut());
+                    Ok(response)
+                }
+            })
+            .data(data)
+            .configure(configure_app)
     });
 
     server = if let Some(listener) = listenfd.take_tcp_listener(0)? {
         server.listen(listener)?
     } else {
-        let port = dotenv::var("PORT").unwrap_or_else(|_| "3000".to_owned());
-        let addr = format!("127.0.0.1:{}", port);
-        println!("binding to {}", &addr);
-        server.bind(addr)?
+        for addr in config.bind_addrs() {
+            println!("binding to {}", &addr);
+            server = server.bind(addr)?;
+        }
+        server
     };
 
-    server.run().await
+    let server = server.run();
+    systemd::notify_ready();
+    systemd::start_watchdog();
+    let result = server.await;
+    systemd::notify_stopping();
+    result
+}
+
+/// Registers the routes and their data without the background work `start` does, such as
+/// notifications and trash purging, for running the app elsewhere, e.g. in integration tests.
+pub fn configure(config: Arc<Config>, recorder: Recorder) -> impl Fn(&mut web::ServiceConfig) {
+    let job_events = Arc::new(JobEvents::default());
+    let broadcaster = Broadcaster::create();
+    let history = Arc::new(SubmissionHistory::new(config.history_path()));
+    let audit_log = Arc::new(AuditLog::new(config.audit_log_path()));
+    let pending_keys = Arc::new(PendingKeys::default());
+    let disk_stats = Arc::new(DiskStatCache::new(config.jobs_dir(), DISK_STAT_TTL));
+    let subscriptions = Arc::new(Subscriptions::new(config.clone()));
+    let started_at = Utc::now();
+    move |service_config| {
+        let data = app_data(
+            config.clone(),
+            recorder.clone(),
+            job_events.clone(),
+            broadcaster.clone(),
+            history.clone(),
+            audit_log.clone(),
+            pending_keys.clone(),
+            disk_stats.clone(),
+            subscriptions.clone(),
+            None,
+            started_at,
+        );
+        service_config.data(data);
+        configure_app(service_config);
+    }
+}
+
+// Each argument is state shared by all of the server's workers.
+#[allow(clippy::too_many_arguments)]
+fn app_data(
+    config: Arc<Config>,
+    recorder: Recorder,
+    job_events: Arc<JobEvents>,
+    broadcaster: Arc<Broadcaster>,
+    history: Arc<SubmissionHistory>,
+    audit_log: Arc<AuditLog>,
+    pending_keys: Arc<PendingKeys>,
+    disk_stats: Arc<DiskStatCache>,
+    subscriptions: Arc<Subscriptions>,
+    disk_monitor: Option<Arc<DiskMonitor>>,
+    started_at: DateTime<Utc>,
+) -> AppData<'static> {
+    let access_key = config.access_key.clone().expect("ACCESS_KEY must be set");
+
+    let mut handlebars = templates::new_handlebars(
+        config.templates_dir.as_deref(),
+        config.display_timezone,
+        config.byte_units,
+    )
+    .expect("Handlebars must initialize");
+    if let Some(monitor) = disk_monitor {
+        helpers::register_low_disk_helper(&mut handlebars, monitor, config.byte_units);
+    }
+
+    let schema = graphql::build_schema(
+        config.clone(),
+        access_key.clone(),
+        recorder.clone(),
+        job_events,
+        history.clone(),
+        audit_log.clone(),
+        disk_stats.clone(),
+    );
+
+    AppData {
+        access_key,
+        config,
+        recorder,
+        schema,
+        handlebars,
+        broadcaster,
+        history,
+        audit_log,
+        pending_keys,
+        disk_stats,
+        subscriptions,
+        started_at,
+    }
+}
+
+/// Sends a notification when a job has finished, without holding up the job.
+fn notify_job_state(notifiers: &Arc<Notifiers>, config: &Config, job: &Job, state: JobState) {
+    let event = match state {
+        JobState::Succeeded => Event::JobSucceeded,
+        JobState::Failed => Event::JobFailed,
+        _ => return,
+    };
+    if notifiers.is_empty() {
+        return;
+    }
+
+    let notifiers = notifiers.clone();
+    let url = config.public_url.as_ref().map(|_| config.job_url(job.id()));
+    let job = job.clone();
+    std::thread::spawn(move || {
+        let title = job.title().unwrap_or_else(|| format!("Job {}", job.id()));
+        let mut message = format!("Job {} {}", job.id(), state);
+        if state == JobState::Failed {
+            if let Some(line) = job.last_stderr_line() {
+                message.push_str(&format!(": {}", line));
+            }
+        }
+        notifiers.send(&Notification {
+            event,
+            title,
+            message,
+            url,
+        });
+    });
+}
+
+/// Removes the partial downloads of a job that failed or was cancelled. Interrupted jobs keep them
+/// so that they can be continued.
+fn clean_partial_files(job: &Job, state: JobState) {
+    if !matches!(state, JobState::Failed | JobState::Cancelled) {
+        return;
+    }
+    let job = job.clone();
+    std::thread::spawn(move || match job.remove_partial_files() {
+        Ok(0) => {}
+        Ok(freed) => println!(
+            "job {} freed {} of partial files",
+            job.id(),
+            humanize_byte_size(freed)
+        ),
+        Err(err) => println!("job {} partial files not removed: {}", job.id(), err),
+    });
+}
+
+/// Emails the sender of a job submitted by email once it has finished.
+fn reply_to_sender(config: &Arc<Config>, job: &Job, state: JobState) {
+    let event = match state {
+        JobState::Succeeded => Event::JobSucceeded,
+        JobState::Failed => Event::JobFailed,
+        _ => return,
+    };
+    let to = match job
+        .metadata()
+        .and_then(|metadata| metadata["email_from"].as_str().map(str::to_owned))
+    {
+        Some(to) => to,
+        None => return,
+    };
+
+    let config = config.clone();
+    let job = job.clone();
+    std::thread::spawn(move || {
+        let title = job.title().unwrap_or_else(|| format!("Job {}", job.id()));
+        let mut message = format!("Job {} {}\n", config.job_url(job.id()), state);
+        if state == JobState::Failed {
+            if let Some(line) = job.last_stderr_line() {
+                message.push_str(&format!("\n{}\n", line));
+            }
+        }
+        let mut file_names = job.file_names();
+        file_names.sort_by(|a, b| compare_file_names(a, b));
+        if !file_names.is_empty() {
+            message.push_str("\nFiles:\n");
+            for name in file_names {
+                message.push_str(&format!("  {}\n", name));
+            }
+        }
+
+        let email = Email {
+            to,
+            from: config.notify_email_from.clone(),
+            smtp_url: config.notify_smtp_url.clone(),
+        };
+        let notification = Notification {
+            event,
+            title,
+            message,
+            url: config.public_url.as_ref().map(|_| config.job_url(job.id())),
+        };
+        if let Err(err) = email.notify(&notification) {
+            println!("replying to sender failed: {}", err);
+        }
+    });
 }
@@ -0,0 +1,258 @@
+use std::fmt;
+use std::io;
+
+use actix_web::body::{Body, ResponseBody};
+use actix_web::dev::ServiceResponse;
+use actix_web::http::{header, HeaderValue, StatusCode};
+use actix_web::middleware::errhandlers::{ErrorHandlerResponse, ErrorHandlers};
+use actix_web::{HttpResponse, ResponseError};
+use serde_json::json;
+
+use crate::recorder::RecorderError;
+use crate::web::helpers::READ_ONLY_MESSAGE;
+use crate::web::request_id::RequestId;
+
+/// Statuses whose responses from `/api` routes are rewritten to JSON if a handler or extractor
+/// didn't send JSON itself.
+const HANDLED_STATUSES: &[StatusCode] = &[
+    StatusCode::BAD_REQUEST,
+    StatusCode::UNAUTHORIZED,
+    StatusCode::FORBIDDEN,
+    StatusCode::NOT_FOUND,
+    StatusCode::METHOD_NOT_ALLOWED,
+    StatusCode::CONFLICT,
+    StatusCode::PAYLOAD_TOO_LARGE,
+    StatusCode::UNSUPPORTED_MEDIA_TYPE,
+    StatusCode::INTERNAL_SERVER_ERROR,
+    StatusCode::SERVICE_UNAVAILABLE,
+    StatusCode::INSUFFICIENT_STORAGE,
+];
+
+/// What clients are told of failures on the server, whose details are only logged.
+const INTERNAL_ERROR_MESSAGE: &str = "internal error; the server log has the details";
+
+pub type ApiResult<T> = Result<T, ApiError>;
+
+/// An error from an `/api` route, sent as `{"error": {"code": ..., "message": ...}}` so that
+/// clients can branch on the code. `error_handlers` adds the `requestId` of the request.
+#[derive(Clone, Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    code: &'static str,
+    message: String,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        ApiError {
+            status,
+            code,
+            message: message.into(),
+        }
+    }
+
+    pub fn unauthorized() -> Self {
+        ApiError::new(
+            StatusCode::UNAUTHORIZED,
+            "invalid_access_key",
+            "invalid access key",
+        )
+    }
+
+    pub fn bad_request(code: &'static str, message: impl fmt::Display) -> Self {
+        ApiError::new(StatusCode::BAD_REQUEST, code, message.to_string())
+    }
+
+    pub fn read_only() -> Self {
+        ApiError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "read_only",
+            READ_ONLY_MESSAGE,
+        )
+    }
+
+    /// An unexpected failure. It is logged, since the client can do little about it, and only a
+    /// generic message is sent, as the details may hold paths and other internals.
+    pub fn internal(err: impl fmt::Display) -> Self {
+        println!("internal error: {}", err);
+        ApiError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "internal_error",
+            INTERNAL_ERROR_MESSAGE,
+        )
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}: {}", self.code, self.message)
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status).json(error_body(self.code, &self.message, None))
+    }
+}
+
+impl From<io::Error> for ApiError {
+    fn from(err: io::Error) -> Self {
+        ApiError::internal(err)
+    }
+}
+
+impl From<serde_json::Error> for ApiError {
+    fn from(err: serde_json::Error) -> Self {
+        ApiError::internal(err)
+    }
+}
+
+impl From<RecorderError> for ApiError {
+    fn from(err: RecorderError) -> Self {
+        let status = err.status_code();
+        let message = if status.is_server_error() {
+            println!("internal error: {}", err);
+            INTERNAL_ERROR_MESSAGE.to_owned()
+        } else {
+            err.to_string()
+        };
+        ApiError::new(status, recorder_error_code(&err), message)
+    }
+}
+
+/// Lets non-API handlers return recorder errors with `?`. The response is the default plain text
+/// one.
+impl ResponseError for RecorderError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            RecorderError::JobNotFound(_) => StatusCode::NOT_FOUND,
+            RecorderError::JobExists(_)
+            | RecorderError::JobRunning(_)
+            | RecorderError::NotClaimed(_)
+            | RecorderError::WorkDirLocked(_)
+            | RecorderError::InvalidTransition(_) => StatusCode::CONFLICT,
+            RecorderError::InvalidJobId(_)
+            | RecorderError::InvalidFileName(_)
+            | RecorderError::InvalidCredential(_) => StatusCode::BAD_REQUEST,
+            RecorderError::Spawn { .. }
+            | RecorderError::CorruptMetadata { .. }
+            | RecorderError::CredentialsUnreadable
+            | RecorderError::PermissionDenied(_)
+            | RecorderError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+fn recorder_error_code(err: &RecorderError) -> &'static str {
+    match err {
+        RecorderError::Spawn { .. } => "spawn_failed",
+        RecorderError::JobNotFound(_) => "job_not_found",
+        RecorderError::JobExists(_) => "job_exists",
+        RecorderError::JobRunning(_) => "job_running",
+        RecorderError::NotClaimed(_) => "not_claimed",
+        RecorderError::WorkDirLocked(_) => "work_dir_locked",
+        RecorderError::CorruptMetadata { .. } => "corrupt_metadata",
+        RecorderError::InvalidTransition(_) => "invalid_state",
+        RecorderError::InvalidJobId(_) => "invalid_job_id",
+        RecorderError::InvalidFileName(_) => "invalid_file_name",
+        RecorderError::InvalidCredential(_) => "invalid_credential",
+        RecorderError::CredentialsUnreadable => "credentials_unreadable",
+        RecorderError::PermissionDenied(_) => "permission_denied",
+        RecorderError::Io(_) => "internal_error",
+    }
+}
+
+/// Keeps the status and message of errors from extractors and shared helpers.
+impl From<actix_web::Error> for ApiError {
+    fn from(err: actix_web::Error) -> Self {
+        if let Some(err) = err.as_error::<ApiError>() {
+            return err.clone();
+        }
+        let status = err.as_response_error().status_code();
+        if status.is_server_error() {
+            return ApiError::internal(err);
+        }
+        ApiError::new(status, code_for_status(status), err.to_string())
+    }
+}
+
+fn code_for_status(status: StatusCode) -> &'static str {
+    match status {
+        StatusCode::BAD_REQUEST => "bad_request",
+        StatusCode::UNAUTHORIZED => "unauthorized",
+        StatusCode::FORBIDDEN => "forbidden",
+        StatusCode::NOT_FOUND => "not_found",
+        StatusCode::METHOD_NOT_ALLOWED => "method_not_allowed",
+        StatusCode::CONFLICT => "conflict",
+        StatusCode::PAYLOAD_TOO_LARGE => "payload_too_large",
+        StatusCode::UNSUPPORTED_MEDIA_TYPE => "unsupported_media_type",
+        StatusCode::SERVICE_UNAVAILABLE => "service_unavailable",
+        StatusCode::INSUFFICIENT_STORAGE => "insufficient_storage",
+        _ if status.is_client_error() => "client_error",
+        _ => "internal_error",
+    }
+}
+
+fn error_body(code: &str, message: &str, request_id: Option<&str>) -> serde_json::Value {
+    let mut body = json!({ "error": { "code": code, "message": message } });
+    if let Some(request_id) = request_id {
+        body["error"]["requestId"] = json!(request_id);
+    }
+    body
+}
+
+/// Rewrites error responses of `/api` routes in the format of `ApiError` with the id of the
+/// request, including those that aren't JSON, such as those of unknown routes or of malformed
+/// request bodies. JSON that handlers sent themselves is kept.
+pub fn error_handlers<B: 'static>() -> ErrorHandlers<B> {
+    HANDLED_STATUSES
+        .iter()
+        .fold(ErrorHandlers::new(), |handlers, &status| {
+            handlers.handler(status, to_json_error)
+        })
+}
+
+fn to_json_error<B>(mut res: ServiceResponse<B>) -> actix_web::Result<ErrorHandlerResponse<B>> {
+    let is_json = res
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    if !res.request().path().starts_with("/api/") {
+        return Ok(ErrorHandlerResponse::Response(res));
+    }
+
+    let status = res.status();
+    let (code, message) = match res.response().error() {
+        Some(err) => match err.as_error::<ApiError>() {
+            Some(err) => (err.code, err.message.clone()),
+            None if is_json => return Ok(ErrorHandlerResponse::Response(res)),
+            None if status.is_server_error() => {
+                println!("internal error: {}", err);
+                (code_for_status(status), INTERNAL_ERROR_MESSAGE.to_owned())
+            }
+            None => (code_for_status(status), err.to_string()),
+        },
+        None if is_json => return Ok(ErrorHandlerResponse::Response(res)),
+        None => (
+            code_for_status(status),
+            status.canonical_reason().unwrap_or("error").to_owned(),
+        ),
+    };
+    let request_id = res
+        .request()
+        .extensions()
+        .get::<RequestId>()
+        .map(ToString::to_string);
+    let body = error_body(code, &message, request_id.as_deref()).to_string();
+    res.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    let res = res.map_body(|_, _| ResponseBody::Other(Body::from(body)));
+    Ok(ErrorHandlerResponse::Response(res))
+}
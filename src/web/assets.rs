@@ -0,0 +1,30 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use rust_embed::RustEmbed;
+use sha2::{Digest, Sha256};
+
+#[derive(RustEmbed)]
+#[folder = "static/"]
+struct Assets;
+
+/// The contents of the asset at `path`, if there is one.
+pub fn asset(path: &str) -> Option<Cow<'static, [u8]>> {
+    Assets::get(path)
+}
+
+/// A short hash of an asset's contents, which its URLs carry so that a changed asset is fetched
+/// again however long the old one was cached.
+pub fn version(contents: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(contents))[..16].to_owned()
+}
+
+/// The versions of all assets, by path.
+pub fn versions() -> HashMap<String, String> {
+    Assets::iter()
+        .filter_map(|path| {
+            let contents = Assets::get(&path)?;
+            Some((path.into_owned(), version(&contents)))
+        })
+        .collect()
+}
@@ -0,0 +1,113 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use actix_web::HttpRequest;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as Json;
+
+/// How many entries `/admin/audit` shows.
+pub const AUDIT_PAGE_SIZE: usize = 500;
+
+/// Where a request came from.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Origin {
+    /// The address of the peer, which is the reverse proxy's if there is one.
+    pub ip: Option<String>,
+    /// The `X-Forwarded-For` or `Forwarded` header, as the proxy, or the client, sent it.
+    pub forwarded_for: Option<String>,
+}
+
+impl Origin {
+    pub fn of(req: &HttpRequest) -> Self {
+        let forwarded_for = ["x-forwarded-for", "forwarded"].iter().find_map(|name| {
+            req.headers()
+                .get(*name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_owned)
+        });
+        Origin {
+            ip: req.peer_addr().map(|addr| addr.ip().to_string()),
+            forwarded_for,
+        }
+    }
+}
+
+/// An action that changed jobs or settings, such as a submission, a deletion, or entering
+/// maintenance mode.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AuditEntry {
+    pub at: DateTime<Utc>,
+    /// What was done, e.g. `submit`, `delete_jobs`, or `set_read_only`.
+    pub action: String,
+    /// Who did it: the key it was done with (`access`, `admin`, or `worker`), or `email` with the
+    /// sender for submissions by email.
+    pub actor: String,
+    #[serde(flatten)]
+    pub origin: Origin,
+    /// What it was done to and how, such as the args submitted or the jobs deleted.
+    pub details: Json,
+}
+
+impl AuditEntry {
+    pub fn new(origin: Origin, actor: impl ToString, action: &str, details: Json) -> Self {
+        AuditEntry {
+            at: Utc::now(),
+            action: action.to_owned(),
+            actor: actor.to_string(),
+            origin,
+            details,
+        }
+    }
+}
+
+/// Administrative actions in the order they were done, appended as JSON lines to a file in the
+/// var dir. Entries are never rewritten or removed.
+pub struct AuditLog {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl AuditLog {
+    pub fn new(path: PathBuf) -> Self {
+        AuditLog {
+            path,
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Appends an entry. Failing to is logged, as it should not fail the action.
+    pub fn record(&self, entry: &AuditEntry) {
+        let _lock = self.lock.lock().unwrap();
+        let result = serde_json::to_string(entry)
+            .map_err(io::Error::from)
+            .and_then(|line| {
+                let mut f = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.path)?;
+                f.write_all(format!("{}\n", line).as_bytes())
+            });
+        if let Err(err) = result {
+            println!("recording audit entry failed: {}", err);
+        }
+    }
+
+    /// The last `limit` entries, or all of them if `limit` is `None`, newest first. Lines that
+    /// can't be parsed are skipped.
+    pub fn recent(&self, limit: Option<usize>) -> io::Result<Vec<AuditEntry>> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(err),
+        };
+        Ok(contents
+            .lines()
+            .rev()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .take(limit.unwrap_or(usize::MAX))
+            .collect())
+    }
+}
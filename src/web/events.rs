@@ -0,0 +1,71 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use actix_web::web::Bytes;
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use serde_json::{json, Value as Json};
+
+use crate::recorder::{Job, JobState};
+
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Fans out server-sent events to connected clients.
+pub struct Broadcaster {
+    clients: Mutex<Vec<UnboundedSender<Bytes>>>,
+}
+
+impl Broadcaster {
+    /// Creates a broadcaster and starts a task that periodically drops disconnected clients.
+    pub fn create() -> Arc<Self> {
+        let broadcaster = Arc::new(Broadcaster {
+            clients: Mutex::new(vec![]),
+        });
+
+        let b = broadcaster.clone();
+        actix_rt::spawn(async move {
+            let mut interval = actix_rt::time::interval(HEARTBEAT_INTERVAL);
+            loop {
+                interval.tick().await;
+                b.send_bytes(Bytes::from_static(b": heartbeat\n\n"));
+            }
+        });
+
+        broadcaster
+    }
+
+    pub fn new_client(&self) -> UnboundedReceiver<Bytes> {
+        let (tx, rx) = unbounded();
+        tx.unbounded_send(Bytes::from_static(b": connected\n\n")).ok();
+        self.clients.lock().unwrap().push(tx);
+        rx
+    }
+
+    pub fn has_clients(&self) -> bool {
+        !self.clients.lock().unwrap().is_empty()
+    }
+
+    pub fn send(&self, event: &str, data: &Json) {
+        let message = format!("event: {}\ndata: {}\n\n", event, data);
+        self.send_bytes(Bytes::from(message));
+    }
+
+    pub fn send_job_state(&self, job: &Job, state: JobState) {
+        self.send("job", &job_event(job, state));
+    }
+
+    fn send_bytes(&self, bytes: Bytes) {
+        self.clients
+            .lock()
+            .unwrap()
+            .retain(|client| client.unbounded_send(bytes.clone()).is_ok());
+    }
+}
+
+fn job_event(job: &Job, state: JobState) -> Json {
+    let id = job.id().to_string();
+    let datetime = ulid::Ulid::from_string(&id)
+        .map(|ulid| ulid.datetime().to_rfc3339())
+        .unwrap_or_default();
+
+    json!({ "id": id, "state": state, "datetime": datetime })
+}
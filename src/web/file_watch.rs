@@ -0,0 +1,76 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::json;
+
+use crate::disk_stat::ByteUnits;
+use crate::recorder::{compare_file_names, is_partial_file_name, JobId, JobState, Recorder};
+use crate::web::events::Broadcaster;
+
+/// How often the dirs of running jobs are checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// File names and sizes in a job dir.
+type Snapshot = Vec<(String, u64)>;
+
+/// Sends a `files` event whenever the files of a running job change, e.g. when a thumbnail is
+/// written or a `.part` file grows, so that job pages can show the progress.
+///
+/// The dirs are polled rather than watched, which works the same on every platform and costs
+/// little since only running jobs are looked at, and only while someone is listening.
+pub fn start(recorder: Recorder, broadcaster: Arc<Broadcaster>, units: ByteUnits) {
+    std::thread::spawn(move || {
+        let mut snapshots: HashMap<JobId, Snapshot> = HashMap::new();
+        loop {
+            std::thread::sleep(POLL_INTERVAL);
+            if !broadcaster.has_clients() {
+                snapshots.clear();
+                continue;
+            }
+
+            let mut next_snapshots = HashMap::new();
+            // Finished jobs are summarized from memory, so only running jobs are read.
+            for (job, summary) in recorder.job_summaries() {
+                if summary.state != Some(JobState::Running) {
+                    continue;
+                }
+                let snapshot = snapshot(job.path(), summary.file_names);
+                if snapshots.get(job.id()) != Some(&snapshot) {
+                    let files: Vec<_> = snapshot
+                        .iter()
+                        .map(|(name, size)| {
+                            json!({
+                                "name": name,
+                                "size": size,
+                                "humanizedSize": units.humanize(*size),
+                                "partial": is_partial_file_name(name),
+                            })
+                        })
+                        .collect();
+                    broadcaster.send(
+                        "files",
+                        &json!({ "id": job.id().to_string(), "files": files }),
+                    );
+                }
+                next_snapshots.insert(job.id().clone(), snapshot);
+            }
+            snapshots = next_snapshots;
+        }
+    });
+}
+
+fn snapshot(dir: &Path, mut file_names: Vec<String>) -> Snapshot {
+    file_names.sort_by(|a, b| compare_file_names(a, b));
+    file_names
+        .into_iter()
+        .map(|name| {
+            let size = fs::metadata(dir.join(&name))
+                .map(|metadata| metadata.len())
+                .unwrap_or(0);
+            (name, size)
+        })
+        .collect()
+}
@@ -0,0 +1,364 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_graphql::{
+    Context, Enum, Error, Object, Result as GraphQLResult, Schema, SimpleObject, Subscription, ID,
+};
+use chrono::{DateTime, Utc};
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use futures::{future, Stream, StreamExt};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::config::Config;
+use crate::disk_stat::DiskStatCache;
+use crate::downloader;
+use crate::postprocess::PostProcessor;
+use crate::recorder::{
+    compare_file_names, is_partial_file_name, Job, JobId, JobOptions, JobState, Recorder,
+};
+use crate::web::audit::{AuditEntry, AuditLog, Origin};
+use crate::web::helpers::{check_download_size, READ_ONLY_MESSAGE};
+use crate::web::history::{Submission, SubmissionHistory};
+use crate::web::request_id::RequestId;
+
+pub type VrecSchema = Schema<QueryRoot, MutationRoot, SubscriptionRoot>;
+
+/// The key that mutations must be authorized with.
+struct AccessKey(String);
+
+/// The key sent with a request, if any.
+pub struct RequestAccessKey(pub Option<String>);
+
+/// Fails unless the request was sent with the access key.
+fn authorize(ctx: &Context<'_>) -> GraphQLResult<()> {
+    let AccessKey(access_key) = ctx.data_unchecked::<AccessKey>();
+    let authorized = ctx
+        .data_opt::<RequestAccessKey>()
+        .and_then(|key| key.0.as_ref())
+        .is_some_and(|key| key == access_key);
+    if !authorized {
+        return Err(Error::new("invalid access key"));
+    }
+    Ok(())
+}
+
+pub fn build_schema(
+    config: Arc<Config>,
+    access_key: String,
+    recorder: Recorder,
+    job_events: Arc<JobEvents>,
+    history: Arc<SubmissionHistory>,
+    audit_log: Arc<AuditLog>,
+    disk_stats: Arc<DiskStatCache>,
+) -> VrecSchema {
+    Schema::build(QueryRoot, MutationRoot, SubscriptionRoot)
+        .data(config)
+        .data(AccessKey(access_key))
+        .data(recorder)
+        .data(job_events)
+        .data(history)
+        .data(audit_log)
+        .data(disk_stats)
+        .finish()
+}
+
+/// Distributes job state changes to subscriptions.
+#[derive(Default)]
+pub struct JobEvents {
+    subscribers: Mutex<Vec<UnboundedSender<JobStateEvent>>>,
+}
+
+impl JobEvents {
+    pub fn publish(&self, job: &Job, state: JobState) {
+        let event = JobStateEvent {
+            id: ID(job.id().to_string()),
+            state: state.into(),
+        };
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|subscriber| subscriber.unbounded_send(event.clone()).is_ok());
+    }
+
+    fn subscribe(&self) -> UnboundedReceiver<JobStateEvent> {
+        let (tx, rx) = unbounded();
+        self.subscribers.lock().unwrap().push(tx);
+        rx
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Enum)]
+#[graphql(name = "PostProcessor", remote = "crate::postprocess::PostProcessor")]
+enum PostProcessorValue {
+    EmbedMetadata,
+    SplitChapters,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Enum)]
+#[graphql(name = "JobState", remote = "crate::recorder::JobState")]
+enum JobStateValue {
+    Created,
+    Waiting,
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+    Interrupted,
+}
+
+#[derive(Clone, SimpleObject)]
+pub struct JobStateEvent {
+    id: ID,
+    state: JobStateValue,
+}
+
+#[derive(SimpleObject)]
+struct Transition {
+    state: JobStateValue,
+    at: DateTime<Utc>,
+    /// Why the transition happened, e.g. `resumed after a restart`.
+    note: Option<String>,
+}
+
+/// The command line a job was submitted with.
+#[derive(Deserialize, SimpleObject)]
+struct Invocation {
+    command: String,
+    args: Vec<String>,
+}
+
+#[derive(SimpleObject)]
+struct JobFile {
+    name: String,
+    url: String,
+    /// Whether the file is a partial download that is still being written.
+    partial: bool,
+}
+
+struct JobObject(Job);
+
+#[Object(name = "Job")]
+impl JobObject {
+    async fn id(&self) -> ID {
+        ID(self.0.id().to_string())
+    }
+
+    async fn created_at(&self) -> Option<DateTime<Utc>> {
+        self.0.created_at()
+    }
+
+    /// When a scheduled job starts.
+    async fn start_at(&self) -> Option<DateTime<Utc>> {
+        serde_json::from_value(self.0.invocation()?["start_at"].clone()).ok()
+    }
+
+    /// The video title, or the title the job was submitted with until it is known.
+    async fn title(&self) -> Option<String> {
+        self.0.title()
+    }
+
+    /// The uploader or channel the job downloaded from, if known.
+    async fn uploader(&self) -> Option<String> {
+        self.0.uploader()
+    }
+
+    async fn state(&self) -> Option<JobStateValue> {
+        self.0.state().map(Into::into)
+    }
+
+    async fn transitions(&self) -> Vec<Transition> {
+        self.0
+            .state_record()
+            .map(|record| record.transitions)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|transition| Transition {
+                state: transition.state.into(),
+                at: transition.at,
+                note: transition.note,
+            })
+            .collect()
+    }
+
+    /// How the job was started. Requires the access key, as the args may hold anything that was
+    /// submitted.
+    async fn invocation(&self, ctx: &Context<'_>) -> GraphQLResult<Option<Invocation>> {
+        authorize(ctx)?;
+        Ok(self
+            .0
+            .invocation()
+            .and_then(|invocation| serde_json::from_value(invocation).ok()))
+    }
+
+    async fn files(&self) -> Vec<JobFile> {
+        let mut file_names = self.0.file_names();
+        file_names.sort_by(|a, b| compare_file_names(a, b));
+        file_names
+            .into_iter()
+            .map(|name| JobFile {
+                url: format!(
+                    "/jobs/{}/{}",
+                    self.0.id(),
+                    utf8_percent_encode(&name, NON_ALPHANUMERIC)
+                ),
+                partial: is_partial_file_name(&name),
+                name,
+            })
+            .collect()
+    }
+
+    async fn is_running(&self) -> bool {
+        self.0.is_running()
+    }
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// Jobs, newest first, optionally only those of `uploader`.
+    async fn jobs(
+        &self,
+        ctx: &Context<'_>,
+        first: Option<usize>,
+        uploader: Option<String>,
+    ) -> Vec<JobObject> {
+        let mut jobs = ctx.data_unchecked::<Recorder>().jobs();
+        if uploader.is_some() {
+            jobs.retain(|job| job.uploader() == uploader);
+        }
+        jobs.sort_by(|a, b| b.id().cmp(a.id()));
+        jobs.into_iter()
+            .take(first.unwrap_or(usize::MAX))
+            .map(JobObject)
+            .collect()
+    }
+
+    async fn job(&self, ctx: &Context<'_>, id: ID) -> Option<JobObject> {
+        let job_id: JobId = id.parse().ok()?;
+        ctx.data_unchecked::<Recorder>().job(&job_id).map(JobObject)
+    }
+}
+
+pub struct MutationRoot;
+
+// The arguments of `createJob` are the fields of the download form.
+#[allow(clippy::too_many_arguments)]
+#[Object]
+impl MutationRoot {
+    /// Starts the downloader with `args`, naming files after `outputTemplate` or the configured
+    /// default, and runs `postProcessors` once it succeeds. With `startAt`, the job waits until
+    /// then; with `timeLimitMinutes`, it is stopped after that long; with `browserCookies`, it uses
+    /// the cookies of the configured browser; with `after`, it waits until that job has succeeded.
+    /// Requires the access key in the `X-Access-Key` header.
+    async fn create_job(
+        &self,
+        ctx: &Context<'_>,
+        args: Vec<String>,
+        output_template: Option<String>,
+        #[graphql(default)] post_processors: Vec<PostProcessorValue>,
+        start_at: Option<DateTime<Utc>>,
+        time_limit_minutes: Option<u64>,
+        #[graphql(default)] browser_cookies: bool,
+        after: Option<ID>,
+    ) -> GraphQLResult<JobObject> {
+        authorize(ctx)?;
+
+        let args: Vec<&str> = args
+            .iter()
+            .map(|arg| arg.trim())
+            .filter(|arg| !arg.is_empty())
+            .collect();
+        if args.is_empty() {
+            return Err(Error::new("args must not be empty"));
+        }
+
+        let origin = ctx.data_opt::<Origin>().cloned().unwrap_or_default();
+        ctx.data_unchecked::<Arc<AuditLog>>()
+            .record(&AuditEntry::new(
+                origin,
+                "access",
+                "submit",
+                json!({ "source": "graphql", "args": args }),
+            ));
+
+        let history = ctx.data_unchecked::<Arc<SubmissionHistory>>();
+        let submission = Submission::new("graphql", &args, output_template.as_deref());
+        let skip = |submission: Submission, reason: &str| {
+            history.record(&submission.skipped(reason));
+            Error::new(reason)
+        };
+
+        let recorder = ctx.data_unchecked::<Recorder>();
+        if recorder.is_read_only() {
+            return Err(skip(submission, READ_ONLY_MESSAGE));
+        }
+
+        if time_limit_minutes == Some(0) {
+            return Err(skip(submission, "timeLimitMinutes must be positive"));
+        }
+        let after = match after.map(|id| id.parse::<JobId>()).transpose() {
+            Ok(after) => after,
+            Err(err) => return Err(skip(submission, &err.to_string())),
+        };
+
+        let config = ctx.data_unchecked::<Arc<Config>>();
+        if browser_cookies && config.cookies_from_browser.is_none() {
+            return Err(skip(submission, "browser cookies are not enabled"));
+        }
+        let output_template = output_template
+            .as_deref()
+            .or(config.output_template.as_deref());
+        let args = match downloader::with_output_template(&args, output_template) {
+            Ok(args) => args,
+            Err(err) => return Err(skip(submission, err)),
+        };
+        let options = JobOptions {
+            post_steps: post_processors
+                .into_iter()
+                .map(|processor| PostProcessor::from(processor).step())
+                .collect(),
+            start_at,
+            time_limit: time_limit_minutes.map(|minutes| Duration::from_secs(minutes * 60)),
+            request_id: ctx
+                .data_opt::<RequestId>()
+                .map(|request_id| request_id.to_string()),
+            idempotency_key: None,
+            browser_cookies,
+            after,
+        };
+        let disk_stats = ctx.data_unchecked::<Arc<DiskStatCache>>();
+        let estimate = match check_download_size(config.clone(), disk_stats.clone(), &args).await {
+            Ok(estimate) => estimate,
+            Err(err) => return Err(skip(submission, &err.to_string())),
+        };
+        let job = match recorder.spawn_job_with(&config.downloader, &args, options) {
+            Ok(job) => job,
+            Err(err) => return Err(skip(submission, &err.to_string())),
+        };
+        history.record(&submission.accepted(job.id()));
+        if let Some(estimate) = estimate {
+            job.set_metadata(&estimate.to_metadata())?;
+        }
+        Ok(JobObject(job))
+    }
+}
+
+pub struct SubscriptionRoot;
+
+#[Subscription]
+impl SubscriptionRoot {
+    /// State changes of the job with `id`, or of all jobs if omitted.
+    async fn job_state_changed(
+        &self,
+        ctx: &Context<'_>,
+        id: Option<ID>,
+    ) -> impl Stream<Item = JobStateEvent> {
+        ctx.data_unchecked::<Arc<JobEvents>>()
+            .subscribe()
+            .filter(move |event| future::ready(id.as_ref().is_none_or(|id| *id == event.id)))
+    }
+}
@@ -1,5 +1,17 @@
-use actix_web::{error, HttpResponse, Result as AppResult};
-use handlebars::Handlebars;
+use std::sync::Arc;
+
+use actix_web::cookie::Cookie;
+use actix_web::error::BlockingError;
+use actix_web::{error, web, HttpRequest, HttpResponse, Result as AppResult};
+use chrono::{DateTime, Utc};
+use handlebars::{Context, Handlebars, Helper, Output, RenderContext, RenderError};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+
+use crate::config::Config;
+use crate::disk_stat::{ByteUnits, DiskMonitor, DiskStatCache};
+use crate::display_time::DisplayTimezone;
+use crate::downloader::{self, SizeEstimate};
+use crate::web::assets;
 
 pub fn render_html<T>(handlebars: &Handlebars, template: &str, data: &T) -> AppResult<HttpResponse>
 where
@@ -11,14 +23,186 @@ where
     }
 }
 
-pub fn register_handlebars_helpers(handlebars: &mut Handlebars) {
+/// Explains why submissions are refused while the recorder is read-only.
+pub const READ_ONLY_MESSAGE: &str = "vrec is in maintenance mode and is not accepting new jobs. \
+     Existing jobs and files can still be viewed and downloaded.";
+
+/// The response to submissions while the recorder is read-only.
+pub fn read_only_response() -> HttpResponse {
+    HttpResponse::ServiceUnavailable()
+        .content_type("text/plain")
+        .body(format!(
+            "503 Service Unavailable\n\n{}\n",
+            READ_ONLY_MESSAGE
+        ))
+}
+
+/// A message for the next page to show, e.g. after an action redirects. The layout shows it once
+/// and removes the cookie.
+pub fn flash_cookie(message: &str) -> Cookie<'static> {
+    Cookie::build(
+        "flash",
+        utf8_percent_encode(message, NON_ALPHANUMERIC).to_string(),
+    )
+    .path("/")
+    .finish()
+}
+
+/// Returns the access key sent in the `X-Access-Key` header.
+pub fn header_access_key(req: &HttpRequest) -> Option<&str> {
+    req.headers()
+        .get("x-access-key")
+        .and_then(|value| value.to_str().ok())
+}
+
+/// Estimates the size of a download on the blocking thread pool, failing if it will not fit and
+/// `SIZE_CHECK` is `reject`. Downloads whose size can't be estimated are let through.
+pub async fn check_download_size(
+    config: Arc<Config>,
+    disk_stats: Arc<DiskStatCache>,
+    args: &[&str],
+) -> Result<Option<SizeEstimate>, String> {
+    let args: Vec<String> = args.iter().map(|&arg| arg.to_owned()).collect();
+    web::block(move || {
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        downloader::check_size(&config, &disk_stats, &args)
+    })
+    .await
+    .map_err(|err| match err {
+        BlockingError::Error(err) => err,
+        BlockingError::Canceled => "size check was canceled".to_owned(),
+    })
+}
+
+pub fn humanize_duration(duration: chrono::Duration) -> String {
+    let secs = duration.num_seconds().max(0);
+    let (days, hours, minutes, seconds) = (secs / 86400, secs / 3600 % 24, secs / 60 % 60, secs % 60);
+
+    if days > 0 {
+        format!("{}d {}h {}m", days, hours, minutes)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else if minutes > 0 {
+        format!("{}m {}s", minutes, seconds)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+pub fn register_handlebars_helpers(
+    handlebars: &mut Handlebars,
+    timezone: DisplayTimezone,
+    byte_units: ByteUnits,
+) {
     use self::handlebars_helpers::*;
 
     handlebars.register_helper("encode", Box::new(percent_encode_helper));
+    register_filesize_helper(handlebars, byte_units);
     handlebars.register_helper(
         "datetime_from_job_id",
         Box::new(datetime_from_job_id_helper),
     );
+    register_format_datetime_helper(handlebars, timezone);
+    register_static_url_helper(handlebars);
+}
+
+/// Registers `static_url`, which renders the URL of an embedded asset, e.g.
+/// `{{static_url "vrec.css"}}`, with its version so that it can be cached for long.
+fn register_static_url_helper(handlebars: &mut Handlebars) {
+    let versions = assets::versions();
+    handlebars.register_helper(
+        "static_url",
+        Box::new(
+            move |h: &Helper,
+                  _: &Handlebars,
+                  _: &Context,
+                  _: &mut RenderContext,
+                  out: &mut dyn Output| {
+                let path = h
+                    .param(0)
+                    .and_then(|param| param.value().as_str())
+                    .unwrap_or_default();
+                match versions.get(path) {
+                    Some(version) => out.write(&format!("/static/{}?v={}", path, version))?,
+                    None => out.write(&format!("/static/{}", path))?,
+                }
+                Ok(())
+            },
+        ),
+    );
+}
+
+/// Registers `filesize`, which renders a size in bytes in `byte_units`, or in the units given,
+/// e.g. `{{filesize size units="binary"}}`.
+fn register_filesize_helper(handlebars: &mut Handlebars, byte_units: ByteUnits) {
+    handlebars.register_helper(
+        "filesize",
+        Box::new(
+            move |h: &Helper,
+                  _: &Handlebars,
+                  _: &Context,
+                  _: &mut RenderContext,
+                  out: &mut dyn Output| {
+                let size = match h.param(0).and_then(|param| param.value().as_u64()) {
+                    Some(size) => size,
+                    None => return Ok(()),
+                };
+                let units = match h.hash_get("units").and_then(|units| units.value().as_str()) {
+                    Some(units) => units.parse().map_err(|err: String| RenderError::new(err))?,
+                    None => byte_units,
+                };
+                out.write(&units.humanize(size))?;
+                Ok(())
+            },
+        ),
+    );
+}
+
+/// Registers `format_datetime`, which renders an RFC 3339 time for reading in `timezone`. Other
+/// values are rendered as they are.
+fn register_format_datetime_helper(handlebars: &mut Handlebars, timezone: DisplayTimezone) {
+    handlebars.register_helper(
+        "format_datetime",
+        Box::new(
+            move |h: &Helper,
+                  _: &Handlebars,
+                  _: &Context,
+                  _: &mut RenderContext,
+                  out: &mut dyn Output| {
+                let value = match h.param(0).and_then(|param| param.value().as_str()) {
+                    Some(value) => value,
+                    None => return Ok(()),
+                };
+                match DateTime::parse_from_rfc3339(value) {
+                    Ok(time) => out.write(&timezone.format(time.with_timezone(&Utc)))?,
+                    Err(_) => out.write(value)?,
+                }
+                Ok(())
+            },
+        ),
+    );
+}
+
+/// Registers `low_disk_warning`, which renders a banner while `monitor` reports low space.
+pub fn register_low_disk_helper(
+    handlebars: &mut Handlebars,
+    monitor: Arc<DiskMonitor>,
+    byte_units: ByteUnits,
+) {
+    handlebars.register_helper(
+        "low_disk_warning",
+        Box::new(
+            move |_: &Helper, _: &Handlebars, _: &Context, _: &mut RenderContext, out: &mut dyn Output| {
+                if let Some(stat) = monitor.low_stat() {
+                    out.write(&format!(
+                        "<p class=\"low-disk-warning\"><strong>Low disk space:</strong> {} available. Downloads may fail.</p>",
+                        byte_units.humanize(stat.available)
+                    ))?;
+                }
+                Ok(())
+            },
+        ),
+    );
 }
 
 #[allow(clippy::redundant_closure)]
@@ -0,0 +1,99 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// How many submissions `/history` shows.
+pub const HISTORY_PAGE_SIZE: usize = 200;
+
+/// A request to download something, whether or not it became a job.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Submission {
+    pub submitted_at: DateTime<Utc>,
+    /// Where it came from: `form`, `email`, `batch`, or `graphql`.
+    pub source: String,
+    /// The args as submitted, without the output template.
+    pub args: Vec<String>,
+    pub output_template: Option<String>,
+    /// The job it became, if it was accepted.
+    pub job_id: Option<String>,
+    /// Why it was not, if it was skipped.
+    pub skipped: Option<String>,
+}
+
+impl Submission {
+    pub fn new(source: &str, args: &[&str], output_template: Option<&str>) -> Self {
+        Submission {
+            submitted_at: Utc::now(),
+            source: source.to_owned(),
+            args: args.iter().map(|&arg| arg.to_owned()).collect(),
+            output_template: output_template.map(str::to_owned),
+            job_id: None,
+            skipped: None,
+        }
+    }
+
+    pub fn accepted(self, job_id: impl ToString) -> Self {
+        Submission {
+            job_id: Some(job_id.to_string()),
+            ..self
+        }
+    }
+
+    pub fn skipped(self, reason: impl ToString) -> Self {
+        Submission {
+            skipped: Some(reason.to_string()),
+            ..self
+        }
+    }
+}
+
+/// Submissions in the order they came, appended as JSON lines to a file in the var dir.
+pub struct SubmissionHistory {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl SubmissionHistory {
+    pub fn new(path: PathBuf) -> Self {
+        SubmissionHistory {
+            path,
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Appends a submission. Failing to is logged, as it should not fail the submission.
+    pub fn record(&self, submission: &Submission) {
+        let _lock = self.lock.lock().unwrap();
+        let result = serde_json::to_string(submission)
+            .map_err(io::Error::from)
+            .and_then(|line| {
+                let mut f = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.path)?;
+                f.write_all(format!("{}\n", line).as_bytes())
+            });
+        if let Err(err) = result {
+            println!("recording submission failed: {}", err);
+        }
+    }
+
+    /// The last `limit` submissions, newest first. Lines that can't be parsed are skipped.
+    pub fn recent(&self, limit: usize) -> io::Result<Vec<Submission>> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(err),
+        };
+        Ok(contents
+            .lines()
+            .rev()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .take(limit)
+            .collect())
+    }
+}
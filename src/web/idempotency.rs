@@ -0,0 +1,67 @@
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+use actix_web::http::StatusCode;
+use actix_web::HttpRequest;
+
+use crate::web::api_error::ApiError;
+
+/// The header a client names a submission with, so that retrying it does not create another job.
+const IDEMPOTENCY_KEY: &str = "idempotency-key";
+
+const MAX_KEY_LEN: usize = 255;
+
+/// Reads the `Idempotency-Key` header, if sent.
+pub fn idempotency_key(req: &HttpRequest) -> Result<Option<String>, ApiError> {
+    let value = match req.headers().get(IDEMPOTENCY_KEY) {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+    let key = value
+        .to_str()
+        .ok()
+        .filter(|key| !key.is_empty() && key.len() <= MAX_KEY_LEN)
+        .ok_or_else(|| {
+            ApiError::bad_request(
+                "invalid_idempotency_key",
+                format_args!(
+                    "Idempotency-Key must be 1 to {} visible ASCII characters",
+                    MAX_KEY_LEN
+                ),
+            )
+        })?;
+    Ok(Some(key.to_owned()))
+}
+
+/// Keys of submissions still being processed, so that a retry arriving meanwhile is refused
+/// rather than racing the original.
+#[derive(Default)]
+pub struct PendingKeys(Mutex<HashSet<String>>);
+
+impl PendingKeys {
+    /// Marks `key` as in progress until the returned guard is dropped, or fails if it already is.
+    pub fn claim(self: &Arc<Self>, key: &str) -> Result<PendingKey, ApiError> {
+        if !self.0.lock().unwrap().insert(key.to_owned()) {
+            return Err(ApiError::new(
+                StatusCode::CONFLICT,
+                "idempotency_key_in_use",
+                "a request with this Idempotency-Key is in progress",
+            ));
+        }
+        Ok(PendingKey {
+            keys: self.clone(),
+            key: key.to_owned(),
+        })
+    }
+}
+
+pub struct PendingKey {
+    keys: Arc<PendingKeys>,
+    key: String,
+}
+
+impl Drop for PendingKey {
+    fn drop(&mut self) {
+        self.keys.0.lock().unwrap().remove(&self.key);
+    }
+}
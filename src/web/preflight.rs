@@ -0,0 +1,175 @@
+use std::fs;
+use std::io;
+
+use serde_json::json;
+
+use crate::config::Config;
+use crate::downloader;
+use crate::notify::{self, Email, Notifier, Notifiers};
+use crate::platform;
+use crate::web::templates;
+
+/// Checks the environment before the server starts, so that misconfiguration is reported at once
+/// instead of as errors on the first requests.
+pub fn check(config: &Config) -> io::Result<()> {
+    let problems: Vec<String> = vec![
+        check_access_key(config),
+        check_work_dir(config),
+        check_downloader(config),
+        check_output_template(config),
+        check_sandbox(config),
+        check_notifications(config),
+        check_email_reply(config),
+        check_telegram_bot(config),
+        check_job_user(config),
+        check_templates(config),
+    ]
+    .into_iter()
+    .filter_map(Result::err)
+    .collect();
+
+    if problems.is_empty() {
+        return Ok(());
+    }
+
+    for problem in &problems {
+        eprintln!("preflight: {}", problem);
+    }
+    Err(io::Error::other(format!(
+        "{} preflight check(s) failed",
+        problems.len()
+    )))
+}
+
+fn check_access_key(config: &Config) -> Result<(), String> {
+    match config.access_key {
+        Some(_) => Ok(()),
+        None => Err("ACCESS_KEY is not set (set it in the environment or .env)".to_owned()),
+    }
+}
+
+fn check_work_dir(config: &Config) -> Result<(), String> {
+    let dir = config.jobs_dir();
+
+    fs::create_dir_all(&dir).map_err(|err| {
+        format!(
+            "could not create work dir {}: {} (check VAR_DIR)",
+            dir.display(),
+            err
+        )
+    })?;
+
+    let probe = dir.join(".preflight");
+    fs::write(&probe, b"")
+        .and_then(|_| fs::remove_file(&probe))
+        .map_err(|err| {
+            format!(
+                "work dir {} is not writable: {} (check VAR_DIR and its permissions)",
+                dir.display(),
+                err
+            )
+        })
+}
+
+fn check_downloader(config: &Config) -> Result<(), String> {
+    match downloader::resolve(&config.downloader) {
+        Some(_) => Ok(()),
+        None => Err(format!(
+            "downloader {:?} is not an executable on PATH (install it or set DOWNLOADER)",
+            config.downloader
+        )),
+    }
+}
+
+fn check_output_template(config: &Config) -> Result<(), String> {
+    match &config.output_template {
+        Some(template) => downloader::validate_output_template(template)
+            .map_err(|err| format!("OUTPUT_TEMPLATE is invalid: {}", err)),
+        None => Ok(()),
+    }
+}
+
+fn check_sandbox(config: &Config) -> Result<(), String> {
+    match config.sandbox.wrapper() {
+        Some(wrapper) if downloader::resolve(wrapper).is_none() => Err(format!(
+            "sandbox {} needs {:?} on PATH (install it or change SANDBOX)",
+            config.sandbox, wrapper
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn check_notifications(config: &Config) -> Result<(), String> {
+    for (name, command) in Notifiers::from_config(config).commands() {
+        if downloader::resolve(command).is_none() {
+            return Err(format!(
+                "{} notifications need {:?} on PATH (install it or change the NOTIFY_ settings)",
+                name, command
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn check_email_reply(config: &Config) -> Result<(), String> {
+    if !config.email_reply {
+        return Ok(());
+    }
+    if config.notify_email_from.is_none() {
+        return Err("EMAIL_REPLY needs NOTIFY_EMAIL_FROM to send replies from".to_owned());
+    }
+    let email = Email {
+        to: String::new(),
+        from: None,
+        smtp_url: config.notify_smtp_url.clone(),
+    };
+    for command in email.commands() {
+        if downloader::resolve(command).is_none() {
+            return Err(format!(
+                "EMAIL_REPLY needs {:?} on PATH (install it or set NOTIFY_SMTP_URL)",
+                command
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn check_telegram_bot(config: &Config) -> Result<(), String> {
+    if config.telegram_allowed_user_ids.is_empty() {
+        return Ok(());
+    }
+    if config.telegram_bot_token.is_none() {
+        return Err("TELEGRAM_ALLOWED_USER_IDS is set but TELEGRAM_BOT_TOKEN is not".to_owned());
+    }
+    if downloader::resolve(notify::CURL).is_none() {
+        return Err(format!(
+            "the Telegram bot needs {:?} on PATH (install it or unset TELEGRAM_ALLOWED_USER_IDS)",
+            notify::CURL
+        ));
+    }
+    Ok(())
+}
+
+fn check_job_user(config: &Config) -> Result<(), String> {
+    if (config.job_uid.is_some() || config.job_gid.is_some()) && !platform::is_superuser() {
+        return Err("JOB_UID and JOB_GID require running vrec as root".to_owned());
+    }
+    Ok(())
+}
+
+fn check_templates(config: &Config) -> Result<(), String> {
+    let handlebars = templates::new_handlebars(
+        config.templates_dir.as_deref(),
+        config.display_timezone,
+        config.byte_units,
+    )
+    .map_err(|err| format!("could not load templates: {} (check TEMPLATES_DIR)", err))?;
+
+    for name in handlebars.get_templates().keys() {
+        handlebars
+            .render(name, &json!({}))
+            .map_err(|err| format!("template {:?} does not render: {}", name, err))?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,82 @@
+use std::fmt;
+use std::future::Future;
+
+use actix_web::dev::{Payload, Service, ServiceRequest, ServiceResponse};
+use actix_web::http::{HeaderName, HeaderValue};
+use actix_web::{Error, FromRequest, HttpMessage, HttpRequest};
+use futures::future;
+
+/// The header the request id is read from and sent back in.
+const X_REQUEST_ID: &str = "x-request-id";
+
+/// Identifies a request in logs, in its response, and in the jobs it creates.
+#[derive(Clone, Debug)]
+pub struct RequestId(String);
+
+impl RequestId {
+    /// Takes the id a proxy sent in `X-Request-Id` if it is safe to log, or makes a new one.
+    fn from_service_request(req: &ServiceRequest) -> Self {
+        let sent = req
+            .headers()
+            .get(X_REQUEST_ID)
+            .and_then(|value| value.to_str().ok())
+            .filter(|id| {
+                !id.is_empty()
+                    && id.len() <= 64
+                    && id
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            });
+        match sent {
+            Some(id) => RequestId(id.to_owned()),
+            None => RequestId(ulid::Ulid::new().to_string()),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(fmt)
+    }
+}
+
+impl FromRequest for RequestId {
+    type Config = ();
+    type Error = Error;
+    type Future = future::Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let request_id = req.extensions().get::<RequestId>().cloned();
+        future::ok(request_id.unwrap_or_else(|| RequestId(ulid::Ulid::new().to_string())))
+    }
+}
+
+/// Middleware that assigns each request an id, logs the request with it, and returns it in the
+/// `X-Request-Id` response header.
+pub fn assign<S, B>(
+    req: ServiceRequest,
+    service: &mut S,
+) -> impl Future<Output = Result<ServiceResponse<B>, Error>>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+{
+    let request_id = RequestId::from_service_request(&req);
+    println!("[{}] {} {}", request_id, req.method(), req.path());
+    req.extensions_mut().insert(request_id.clone());
+
+    let response = service.call(req);
+    async move {
+        let mut response = response.await?;
+        println!("[{}] {}", request_id, response.status());
+        if let Ok(value) = HeaderValue::from_str(request_id.as_str()) {
+            response
+                .headers_mut()
+                .insert(HeaderName::from_static(X_REQUEST_ID), value);
+        }
+        Ok(response)
+    }
+}
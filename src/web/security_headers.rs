@@ -0,0 +1,74 @@
+use actix_web::http::header::{self, HeaderMap, HeaderValue};
+
+use crate::config::Config;
+
+/// Allows the pages' inline scripts and the libraries they load, and media from vrec itself so
+/// that files open in the browser's player. Anything else, e.g. a downloaded HTML file loading
+/// scripts from elsewhere, is refused.
+pub const DEFAULT_CONTENT_SECURITY_POLICY: &str = "default-src 'self'; \
+     script-src 'self' 'unsafe-inline' https://cdnjs.cloudflare.com; \
+     style-src 'self' 'unsafe-inline' https://cdnjs.cloudflare.com; \
+     img-src 'self' data:; media-src 'self' blob:; object-src 'none'; base-uri 'self'; \
+     form-action 'self'";
+
+pub const DEFAULT_FRAME_OPTIONS: &str = "DENY";
+
+/// Headers that limit what HTML pages can load and who can frame them.
+pub struct SecurityHeaders {
+    content_security_policy: Option<HeaderValue>,
+    frame_options: Option<HeaderValue>,
+}
+
+impl SecurityHeaders {
+    pub fn new(config: &Config) -> Self {
+        let value = |value: &str, name| {
+            Some(value)
+                .filter(|value| *value != "off")
+                .map(|value| HeaderValue::from_str(value).expect(name))
+        };
+        SecurityHeaders {
+            content_security_policy: value(
+                &config.content_security_policy,
+                "CONTENT_SECURITY_POLICY must be a valid header value",
+            ),
+            frame_options: value(
+                &config.frame_options,
+                "FRAME_OPTIONS must be a valid header value",
+            ),
+        }
+    }
+
+    /// Adds the headers to a response, keeping those a handler has set, e.g. the looser policy
+    /// of the GraphQL playground.
+    pub fn apply(&self, headers: &mut HeaderMap) {
+        let set = |headers: &mut HeaderMap, name, value: &HeaderValue| {
+            if !headers.contains_key(&name) {
+                headers.insert(name, value.clone());
+            }
+        };
+
+        set(
+            headers,
+            header::X_CONTENT_TYPE_OPTIONS,
+            &HeaderValue::from_static("nosniff"),
+        );
+        let is_html = headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("text/html"));
+        if !is_html {
+            return;
+        }
+        set(
+            headers,
+            header::REFERRER_POLICY,
+            &HeaderValue::from_static("same-origin"),
+        );
+        if let Some(value) = &self.content_security_policy {
+            set(headers, header::CONTENT_SECURITY_POLICY, value);
+        }
+        if let Some(value) = &self.frame_options {
+            set(headers, header::X_FRAME_OPTIONS, value);
+        }
+    }
+}
@@ -1,23 +1,106 @@
 use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Component, Path};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use actix_files::NamedFile;
-use actix_web::{error, http, web, HttpRequest, HttpResponse, Responder, Result as ActixResult};
+use actix_multipart::Multipart;
+use actix_web::dev::BodyEncoding;
+use actix_web::error::BlockingError;
+use actix_web::http::{header, ContentEncoding, HeaderValue, StatusCode};
+use actix_web::web::Bytes;
+use actix_web::{
+    error, http, web, HttpMessage, HttpRequest, HttpResponse, Responder, ResponseError,
+    Result as ActixResult,
+};
+use async_graphql::http::{playground_source, GraphQLPlaygroundConfig};
+use chrono::{DateTime, Utc};
+use futures::{future, stream, StreamExt, TryStreamExt};
 use handlebars::Handlebars;
-use percent_encoding::percent_decode;
+use percent_encoding::{percent_decode, percent_encode, utf8_percent_encode, NON_ALPHANUMERIC};
 use serde::Deserialize;
-use serde_json::json;
+use serde_json::{json, Value as Json};
 use url::Url;
 
-use crate::disk_stat::{humanize_byte_size, DiskStat};
-use crate::recorder::{JobId, Recorder};
-use crate::web::helpers::render_html;
+use crate::config::Config;
+use crate::disk_stat::DiskStatCache;
+use crate::downloader::{self, Profile};
+use crate::export::{self, ExportFormat};
+use crate::platform;
+use crate::postprocess::PostProcessor;
+use crate::recorder::{
+    compare_file_names, guess_mime, is_partial_file_name, Credential, CredentialVault, Job,
+    JobFilter, JobId, JobOptions, JobState, MediaInfo, Recorder, RecorderError, TimeRange,
+};
+use crate::stats::{uploader_counts, Stats, UploaderCount};
+use crate::subscription::{Subscription, Subscriptions};
+use crate::subtitles::{srt_to_vtt, subtitle_language, subtitle_mime};
+use crate::web::api_error::{ApiError, ApiResult};
+use crate::web::assets;
+use crate::web::audit::{AuditEntry, AuditLog, Origin, AUDIT_PAGE_SIZE};
+use crate::web::events::Broadcaster;
+use crate::web::graphql::{RequestAccessKey, VrecSchema};
+use crate::web::helpers::{
+    check_download_size, flash_cookie, header_access_key, humanize_duration, read_only_response,
+    render_html, READ_ONLY_MESSAGE,
+};
+use crate::web::history::{Submission, SubmissionHistory, HISTORY_PAGE_SIZE};
+use crate::web::idempotency::{idempotency_key, PendingKeys};
+use crate::web::request_id::RequestId;
+use crate::web::thumbnail::{cached_thumbnail, DEFAULT_THUMBNAIL_WIDTH, THUMBNAIL_WIDTHS};
 
 type Data<'a> = web::Data<AppData<'a>>;
 
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+const GIT_HASH: Option<&str> = option_env!("VREC_GIT_HASH");
+
+/// Downloaded files come from anywhere, so those a browser would run, such as HTML or SVG, run
+/// without scripts and in an origin of their own, away from the access key the pages remember.
+const JOB_FILE_CONTENT_SECURITY_POLICY: &str = "sandbox";
+
 pub struct AppData<'a> {
     pub access_key: String,
+    pub config: Arc<Config>,
     pub recorder: Recorder,
+    pub schema: VrecSchema,
     pub handlebars: Handlebars<'a>,
+    pub broadcaster: Arc<Broadcaster>,
+    pub history: Arc<SubmissionHistory>,
+    pub audit_log: Arc<AuditLog>,
+    pub pending_keys: Arc<PendingKeys>,
+    pub disk_stats: Arc<DiskStatCache>,
+    pub subscriptions: Arc<Subscriptions>,
+    pub started_at: DateTime<Utc>,
+}
+
+impl AppData<'_> {
+    /// Returns true if `key` grants access to administrative pages and actions.
+    fn is_admin_key(&self, key: &str) -> bool {
+        key == self.config.admin_key.as_deref().unwrap_or(&self.access_key)
+    }
+
+    /// Returns true if `key` lets a worker claim and report jobs.
+    fn is_worker_key(&self, key: &str) -> bool {
+        self.config.worker_key.as_deref() == Some(key)
+    }
+
+    /// Records an action done with `key` in the audit log, naming the actor after the key.
+    fn audit(&self, req: &HttpRequest, key: &str, action: &str, details: Json) {
+        let actor = if key == self.access_key {
+            "access"
+        } else if self.is_admin_key(key) {
+            "admin"
+        } else if self.is_worker_key(key) {
+            "worker"
+        } else {
+            "unknown"
+        };
+        let entry = AuditEntry::new(Origin::of(req), actor, action, details);
+        self.audit_log.record(&entry);
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -26,37 +109,316 @@ struct PostApiRecordPayload {
     access_key: String,
     email_subject: String,
     email_body: String,
+    /// The sender, checked against `EMAIL_ALLOWED_SENDERS`.
+    email_from: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GetDownloadQuery {
+    from: Option<String>,
+    /// The job to run the download after.
+    after: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct DeleteJobsPayload {
     access_key: String,
-    job_ids: Vec<String>,
+    job_ids: Vec<JobId>,
+    /// Deletes only the media files, keeping the metadata and logs.
+    #[serde(default)]
+    slim: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct GetJobFileQuery {
+    /// `vtt` to convert SubRip subtitles.
+    format: Option<String>,
+    /// `1` to download the file rather than show it.
+    download: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GetJobThumbQuery {
+    w: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GetJobsQuery {
+    uploader: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+    /// The jobs just moved to the trash, separated by commas, whose deletion can be undone.
+    deleted: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GetApiJobsQuery {
+    from: Option<String>,
+    to: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GetApiJobLogQuery {
+    tail: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GetShareQuery {
+    title: Option<String>,
+    text: Option<String>,
+    url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GetStaticQuery {
+    v: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GetApiJobsExportQuery {
+    format: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DeleteApiJobQuery {
+    #[serde(default)]
+    slim: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct DeleteApiJobsQuery {
+    filter: String,
+    #[serde(default)]
+    slim: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RestoreJobsPayload {
+    access_key: String,
+    job_ids: Vec<JobId>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CleanPartialFilesPayload {
+    access_key: String,
+    job_ids: Vec<JobId>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PostApiV1JobsBatchPayload {
+    urls: Vec<BatchItem>,
+}
+
+/// A URL to download with the default profile, or with the profile named.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum BatchItem {
+    Url(String),
+    WithProfile {
+        url: String,
+        profile: Option<String>,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PostApiBatchPayload {
+    access_key: String,
+    /// Pasted text to find links in.
+    #[serde(default)]
+    text: String,
+    /// The links to download, e.g. those of a preview that were confirmed. If not given, all
+    /// links found in `text` are.
+    urls: Option<Vec<String>>,
+    /// Only lists the links found in `text`, without downloading them.
+    #[serde(default)]
+    preview: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PostApiSubscriptionsPayload {
+    access_key: String,
+    /// A channel or playlist URL.
+    url: String,
+    #[serde(default = "default_subscription_interval")]
+    interval_minutes: u64,
+    /// Also downloads the entries that are already there.
+    #[serde(default)]
+    backfill: bool,
+}
+
+fn default_subscription_interval() -> u64 {
+    60
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AdminPayload {
+    access_key: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AdminReadOnlyPayload {
+    access_key: String,
+    read_only: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AdminCredentialPayload {
+    access_key: String,
+    #[serde(flatten)]
+    credential: Credential,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AdminRemoveCredentialPayload {
+    access_key: String,
+    machine: String,
+    domain: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WorkerClaimPayload {
+    access_key: String,
+    /// The name of the worker, recorded in the job's state.
+    worker: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WorkerHeartbeatPayload {
+    access_key: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WorkerFinishPayload {
+    access_key: String,
+    /// How the worker's run ended: `succeeded`, `failed`, or `interrupted`.
+    state: JobState,
+    #[serde(default)]
+    stdout: String,
+    #[serde(default)]
+    stderr: String,
 }
 
 pub fn configure_app(config: &mut web::ServiceConfig) {
-    use web::{delete, get, head, post, resource as r};
+    use web::{delete, get, head, post, put, resource as r};
 
     config
         .service(r("/").route(get().to(get_index)))
+        .service(r("/admin").route(get().to(get_admin)))
+        .service(r("/admin/status").route(get().to(get_admin_status)))
+        .service(r("/admin/audit").route(get().to(get_admin_audit)))
+        .service(r("/admin/audit/entries").route(get().to(get_admin_audit_entries)))
+        .service(r("/api/admin/audit").route(get().to(get_api_admin_audit)))
+        .service(r("/api/admin/gc").route(post().to(post_api_admin_gc)))
+        .service(r("/api/admin/dedup").route(post().to(post_api_admin_dedup)))
+        .service(r("/api/admin/queue/pause").route(post().to(post_api_admin_queue_pause)))
+        .service(r("/api/admin/queue/resume").route(post().to(post_api_admin_queue_resume)))
+        .service(r("/api/admin/read-only").route(post().to(post_api_admin_read_only)))
+        .service(r("/api/admin/credentials").route(post().to(post_api_admin_credentials)))
+        .service(
+            r("/api/admin/credentials/remove").route(post().to(post_api_admin_credentials_remove)),
+        )
+        .service(
+            r("/api/admin/update-downloader").route(post().to(post_api_admin_update_downloader)),
+        )
+        .service(
+            r("/api/jobs")
+                .route(get().to(get_api_jobs))
+                .route(delete().to(delete_api_jobs)),
+        )
+        .service(r("/api/jobs/export").route(get().to(get_api_jobs_export)))
+        .service(r("/api/jobs/scheduled").route(get().to(get_api_jobs_scheduled)))
+        .service(r("/api/jobs/restore").route(post().to(post_api_jobs_restore)))
+        .service(r("/api/jobs/clean-partial").route(post().to(post_api_jobs_clean_partial)))
+        .service(r("/api/jobs/upload").route(post().to(post_api_jobs_upload)))
+        .service(r("/api/jobs/{id:[0-9A-Z]+}").route(delete().to(delete_api_job)))
+        .service(r("/api/jobs/{id:[0-9A-Z]+}/cancel").route(post().to(post_api_job_cancel)))
+        .service(r("/api/jobs/{id:[0-9A-Z]+}/diagnostics").route(get().to(get_api_job_diagnostics)))
+        .service(r("/api/jobs/{id:[0-9A-Z]+}/invocation").route(get().to(get_api_job_invocation)))
+        .service(r("/api/jobs/{id:[0-9A-Z]+}/media").route(get().to(get_api_job_media)))
+        .service(
+            r("/api/jobs/{id:[0-9A-Z]+}/{stream:stdout|stderr}").route(get().to(get_api_job_log)),
+        )
+        .service(r("/api/stats").route(get().to(get_api_stats)))
+        .service(
+            r("/api/subscriptions")
+                .route(get().to(get_api_subscriptions))
+                .route(post().to(post_api_subscriptions)),
+        )
+        .service(r("/api/subscriptions/{id:[0-9A-Z]+}").route(delete().to(delete_api_subscription)))
+        .service(r("/api/uploaders").route(get().to(get_api_uploaders)))
+        .service(r("/api/version").route(get().to(get_api_version)))
+        .service(r("/api/worker/claim").route(post().to(post_api_worker_claim)))
+        .service(
+            r("/api/worker/jobs/{id:[0-9A-Z]+}/files/{file_name}")
+                .route(put().to(put_api_worker_job_file)),
+        )
+        .service(
+            r("/api/worker/jobs/{id:[0-9A-Z]+}/heartbeat")
+                .route(post().to(post_api_worker_job_heartbeat)),
+        )
+        .service(
+            r("/api/worker/jobs/{id:[0-9A-Z]+}/finish")
+                // Room for the logs of the worker's run.
+                .app_data(web::JsonConfig::default().limit(16 * 1024 * 1024))
+                .route(post().to(post_api_worker_job_finish)),
+        )
         .service(r("/api/record").route(post().to(post_api_record)))
+        .service(r("/api/batch").route(post().to(post_api_batch)))
+        .service(
+            r("/api/v1/jobs:batch")
+                // Room for a long playlist.
+                .app_data(web::JsonConfig::default().limit(1024 * 1024))
+                .route(post().to(post_api_v1_jobs_batch)),
+        )
         .service(
             r("/download")
                 .route(get().to(get_download))
                 .route(post().to(post_download)),
         )
+        .service(r("/download/prefill").route(get().to(get_download_prefill)))
+        .service(r("/events").route(get().to(get_events)))
+        .service(r("/history").route(get().to(get_history)))
+        .service(r("/history/entries").route(get().to(get_history_entries)))
+        .service(
+            r("/graphql")
+                .route(get().to(get_graphql))
+                .route(post().to(post_graphql)),
+        )
+        .service(r("/graphql/stream").route(post().to(post_graphql_stream)))
+        .service(r("/share").route(get().to(get_share)))
+        .service(r("/static/{path:.*}").route(get().to(get_static)))
+        .service(r("/stats").route(get().to(get_stats)))
+        .service(r("/uploaders").route(get().to(get_uploaders)))
         .service(r("/jobs/{id:[0-9A-Z]+}").route(get().to(get_job)))
         .service(r("/jobs/{id:[0-9A-Z]+}/process").route(head().to(head_job_process)))
+        .service(r("/jobs/{id:[0-9A-Z]+}/thumb/{file_name:.*}").route(get().to(get_job_thumb)))
         .service(r("/jobs/{id:[0-9A-Z]+}/{file_name:.*}").route(get().to(get_job_file)))
-        .service(r("/jobs").route(get().to(get_jobs)))
-        .service(r("/jobs").route(delete().to(delete_jobs)));
+        .service(
+            r("/jobs")
+                .route(get().to(get_jobs))
+                .route(delete().to(delete_jobs)),
+        )
+        .service(r("/jobs/delete").route(post().to(post_jobs_delete)));
 }
 
 async fn post_api_record(
+    req: HttpRequest,
+    request_id: RequestId,
     data: Data<'_>,
     payload: web::Json<PostApiRecordPayload>,
-) -> ActixResult<impl Responder> {
+) -> ApiResult<HttpResponse> {
     fn find_youtube_link(link: linkify::Link) -> Option<String> {
         Url::parse(link.as_str())
             .into_iter()
@@ -70,47 +432,359 @@ async fn post_api_record(
         finder.links(text).filter_map(find_youtube_link).next()
     }
 
-    println!("post_api_record {:?}", &payload);
+    println!("[{}] post_api_record {:?}", request_id, &payload);
 
     if payload.access_key != data.access_key {
-        return Ok(HttpResponse::Unauthorized().finish());
+        return Err(ApiError::unauthorized());
     }
 
-    if let Some(link) = extract_youtube_link(&payload.email_body) {
-        println!("post_api_record link = {:?}", &link);
-        data.recorder
-            .spawn_job(
-                "youtube-dl",
-                &["--write-all-thumbnails", "--write-info-json", link.as_str()],
-            )
-            .map(|_| Ok(HttpResponse::Created().finish()))
-            .unwrap_or_else(|_| Ok(HttpResponse::Ok().finish()))
+    let link = extract_youtube_link(&payload.email_body);
+    let submitted_args = match &link {
+        Some(link) => vec!["--write-all-thumbnails", "--write-info-json", link.as_str()],
+        None => vec![],
+    };
+    let submission = Submission::new("email", &submitted_args, None);
+    let actor = match &payload.email_from {
+        Some(from) => format!("email {}", from),
+        None => "email".to_owned(),
+    };
+    let details = json!({ "source": "email", "args": submitted_args });
+    data.audit_log
+        .record(&AuditEntry::new(Origin::of(&req), actor, "submit", details));
+
+    if !data.config.is_allowed_sender(payload.email_from.as_deref()) {
+        println!("post_api_record sender not allowed");
+        data.history
+            .record(&submission.skipped("sender is not allowed"));
+        return Err(ApiError::new(
+            StatusCode::FORBIDDEN,
+            "sender_not_allowed",
+            "sender is not allowed",
+        ));
+    }
+
+    // Held until the job is created, so that a retry meanwhile does not create another.
+    let idempotency_key = idempotency_key(&req)?;
+    let _pending_key = match &idempotency_key {
+        Some(key) => Some(data.pending_keys.claim(key)?),
+        None => None,
+    };
+    if let Some(job) = idempotency_key
+        .as_deref()
+        .and_then(|key| data.recorder.job_by_idempotency_key(key))
+    {
+        println!("post_api_record repeated for job {}", job.id());
+        data.history
+            .record(&submission.skipped(format!("repeated delivery of job {}", job.id())));
+        return Ok(HttpResponse::Ok().json(json!({ "id": job.id().to_string() })));
+    }
+
+    if data.recorder.is_read_only() {
+        data.history.record(&submission.skipped(READ_ONLY_MESSAGE));
+        return Err(ApiError::read_only());
+    }
+
+    if let Some(link) = &link {
+        println!("post_api_record link = {:?}", link);
+        let args = downloader::with_output_template(
+            &submitted_args,
+            data.config.output_template.as_deref(),
+        )
+        .map_err(ApiError::internal)?;
+        let estimate =
+            match check_download_size(data.config.clone(), data.disk_stats.clone(), &args).await {
+                Ok(estimate) => estimate,
+                Err(err) => {
+                    println!("post_api_record rejected: {}", err);
+                    data.history.record(&submission.skipped(&err));
+                    return Err(ApiError::new(
+                        StatusCode::INSUFFICIENT_STORAGE,
+                        "insufficient_storage",
+                        err,
+                    ));
+                }
+            };
+        let options = JobOptions {
+            request_id: Some(request_id.to_string()),
+            idempotency_key,
+            ..Default::default()
+        };
+        match data
+            .recorder
+            .spawn_job_with(&data.config.downloader, &args, options)
+        {
+            Ok(job) => {
+                data.history.record(&submission.accepted(job.id()));
+                let mut metadata = estimate
+                    .map(|estimate| estimate.to_metadata())
+                    .unwrap_or_else(|| json!({}));
+                let subject = payload.email_subject.trim();
+                if !subject.is_empty() {
+                    // Shown until the downloader writes the video title.
+                    metadata["title"] = json!(subject);
+                }
+                if let Some(from) = &payload.email_from {
+                    metadata["email_from"] = json!(from);
+                }
+                if metadata
+                    .as_object()
+                    .is_some_and(|metadata| !metadata.is_empty())
+                {
+                    job.set_metadata(&metadata).ok();
+                }
+                Ok(HttpResponse::Created().json(json!({ "id": job.id().to_string() })))
+            }
+            Err(err) => {
+                data.history.record(&submission.skipped(&err));
+                Err(err.into())
+            }
+        }
     } else {
         println!("post_api_record link not found");
+        data.history
+            .record(&submission.skipped("no YouTube link in the email"));
         Ok(HttpResponse::Ok().finish())
     }
 }
 
+/// Creates a job from uploaded files. Text fields other than `accessKey` are stored as the job's
+/// metadata (e.g. `title`, `url`). `accessKey` must precede the files.
+async fn post_api_jobs_upload(
+    req: HttpRequest,
+    request_id: RequestId,
+    data: Data<'_>,
+    payload: Multipart,
+) -> ApiResult<HttpResponse> {
+    let mut job = None;
+    match receive_upload(&request_id, &data, payload, &mut job).await {
+        Ok(file_names) => {
+            let job = job.expect("job must exist after upload");
+            // The upload was made with the access key, which `receive_upload` checks.
+            let details = json!({ "job_id": job.id().to_string(), "file_names": file_names });
+            data.audit(&req, &data.access_key, "upload", details);
+            Ok(HttpResponse::Created()
+                .header(http::header::LOCATION, format!("/jobs/{}", job.id()))
+                .json(json!({ "id": job.id().to_string(), "fileNames": file_names })))
+        }
+        Err(err) => {
+            if let Some(job) = job {
+                job.safe_delete();
+            }
+            Err(err.into())
+        }
+    }
+}
+
+/// Stores the uploaded files in a new job, which is put in `job` as soon as it is created so that
+/// the caller can clean it up on failure.
+async fn receive_upload(
+    request_id: &RequestId,
+    data: &AppData<'_>,
+    mut payload: Multipart,
+    job: &mut Option<Job>,
+) -> ActixResult<Vec<String>> {
+    const MAX_TEXT_FIELD_SIZE: usize = 64 * 1024;
+
+    let mut access_key = None;
+    let mut metadata = serde_json::Map::new();
+    let mut file_names = vec![];
+
+    while let Some(mut field) = payload.try_next().await? {
+        let disposition = field
+            .content_disposition()
+            .ok_or_else(|| error::ErrorBadRequest("missing Content-Disposition"))?;
+        let name = disposition.get_name().unwrap_or_default().to_owned();
+
+        let file_name = match disposition.get_filename() {
+            Some(file_name) => file_name.to_owned(),
+            None => {
+                let mut value = Vec::new();
+                while let Some(chunk) = field.try_next().await? {
+                    if value.len() + chunk.len() > MAX_TEXT_FIELD_SIZE {
+                        return Err(error::ErrorPayloadTooLarge("text field too large"));
+                    }
+                    value.extend_from_slice(&chunk);
+                }
+                let value = String::from_utf8(value).map_err(error::ErrorBadRequest)?;
+                if name == "accessKey" {
+                    access_key = Some(value);
+                } else {
+                    metadata.insert(name, Json::String(value));
+                }
+                continue;
+            }
+        };
+
+        if access_key.as_deref() != Some(data.access_key.as_str()) {
+            return Err(ApiError::unauthorized().into());
+        }
+
+        let job = match job {
+            Some(job) => job,
+            None if data.recorder.is_read_only() => return Err(ApiError::read_only().into()),
+            None => {
+                let imported = data.recorder.import_job().map_err(ApiError::from)?;
+                println!("[{}] importing job {}", request_id, imported.id());
+                job.insert(imported)
+            }
+        };
+        let mut f = job.create_file(&file_name).map_err(ApiError::from)?;
+        while let Some(chunk) = field.try_next().await? {
+            f = web::block(move || f.write_all(&chunk).map(|_| f)).await?;
+        }
+        file_names.push(file_name);
+    }
+
+    let job = job
+        .as_ref()
+        .ok_or_else(|| ApiError::bad_request("no_file", "no file uploaded"))?;
+    job.set_metadata(&Json::Object(metadata))
+        .map_err(ApiError::from)?;
+    job.transition(JobState::Succeeded)
+        .map_err(ApiError::from)?;
+    Ok(file_names)
+}
+
+/// Runs `--version` of the configured downloader on the blocking thread pool.
+async fn downloader_version(data: &AppData<'_>) -> Result<String, String> {
+    let command = data.config.downloader.clone();
+    web::block(move || downloader::version(&command))
+        .await
+        .map_err(|err| match err {
+            BlockingError::Error(err) => err.to_string(),
+            BlockingError::Canceled => "canceled".to_owned(),
+        })
+}
+
+async fn get_api_version(data: Data<'_>) -> ActixResult<impl Responder> {
+    let downloader = match downloader_version(&data).await {
+        Ok(version) => json!({ "command": data.config.downloader, "version": version }),
+        Err(err) => json!({
+            "command": data.config.downloader,
+            "version": null,
+            "error": err,
+        }),
+    };
+
+    Ok(HttpResponse::Ok().json(json!({
+        "version": VERSION,
+        "gitHash": GIT_HASH,
+        "downloader": downloader,
+    })))
+}
+
 async fn get_index(data: Data<'_>) -> ActixResult<impl Responder> {
     render_html(&data.handlebars, "index", &())
 }
 
-async fn get_download(data: Data<'_>) -> ActixResult<impl Responder> {
-    render_html(&data.handlebars, "download", &())
+/// Renders the download form, to run after the job `after` if given. The page fills in the args
+/// of the job `from` from `get_download_prefill`, with the key from its URL hash.
+async fn get_download(
+    data: Data<'_>,
+    query: web::Query<GetDownloadQuery>,
+) -> ActixResult<impl Responder> {
+    let from = query
+        .from
+        .as_ref()
+        .and_then(|id| id.parse::<JobId>().ok())
+        .map(|job_id| job_id.to_string());
+    let after = query
+        .after
+        .as_ref()
+        .and_then(|id| id.parse::<JobId>().ok())
+        .filter(|job_id| data.recorder.job(job_id).is_some())
+        .map(|job_id| job_id.to_string());
+    let post_processors: Vec<Json> = PostProcessor::ALL
+        .iter()
+        .map(|processor| json!({ "name": processor.as_str() }))
+        .collect();
+    let profiles: Vec<Json> = data
+        .config
+        .profiles
+        .iter()
+        .map(|profile| json!({ "name": profile.name, "args": profile.args.join(" ") }))
+        .collect();
+
+    render_html(
+        &data.handlebars,
+        "download",
+        &json!({
+            "profiles": profiles,
+            "from": from,
+            "default_output_template": data.config.output_template,
+            "post_processors": post_processors,
+            "cookies_from_browser": data.config.cookies_from_browser,
+            "after": after,
+            "read_only_message": Some(READ_ONLY_MESSAGE).filter(|_| data.recorder.is_read_only()),
+        }),
+    )
+}
+
+/// Sends what the download form is filled in with to run the job `from` again, to those with
+/// the access key: the profile and URL if it was started with a profile, and the args otherwise.
+async fn get_download_prefill(
+    req: HttpRequest,
+    data: Data<'_>,
+    query: web::Query<GetDownloadQuery>,
+) -> ActixResult<impl Responder> {
+    if header_access_key(&req) != Some(data.access_key.as_str()) {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+
+    let invocation = query
+        .from
+        .as_ref()
+        .and_then(|id| id.parse().ok())
+        .and_then(|job_id| data.recorder.job(&job_id))
+        .and_then(|job| job.invocation())
+        .ok_or_else(|| error::ErrorNotFound(""))?;
+    let args: Option<Vec<String>> = serde_json::from_value(invocation["args"].clone()).ok();
+    let post: Vec<String> = serde_json::from_value(invocation["post"].clone()).unwrap_or_default();
+    let (output_template, args) = match &args {
+        Some(args) => {
+            let (output_template, args) = downloader::split_output_template(args);
+            (output_template, Some(args))
+        }
+        None => (None, None),
+    };
+    let profile_and_url = args
+        .as_ref()
+        .and_then(|args| Profile::find(&data.config.profiles, args))
+        .map(|(profile, url)| (profile.name.as_str(), url));
+
+    Ok(HttpResponse::Ok().json(json!({
+        "profile": profile_and_url.map(|(name, _)| name),
+        "url": profile_and_url.map(|(_, url)| url),
+        "args": args.filter(|_| profile_and_url.is_none()),
+        "output_template": output_template,
+        "post": post,
+        "time_limit_minutes": invocation["time_limit"].as_u64().map(|secs| secs / 60),
+        "browser_cookies": invocation["browser_cookies"] == true,
+    })))
 }
 
-async fn post_download(data: Data<'_>, params: web::Form<Vec<(String, String)>>) -> impl Responder {
-    let has_access_key = params
-        .iter()
-        .any(|(name, value)| name == "access_key" && value == &data.access_key);
+async fn post_download(
+    req: HttpRequest,
+    request_id: RequestId,
+    data: Data<'_>,
+    params: web::Form<Vec<(String, String)>>,
+) -> impl Responder {
+    let param = |name: &str| {
+        params
+            .iter()
+            .find(|(param_name, _)| param_name == name)
+            .map(|(_, value)| value.trim())
+            .filter(|value| !value.is_empty())
+    };
 
-    if !has_access_key {
+    let access_key = param("access_key").unwrap_or_default();
+    if access_key != data.access_key && !data.is_admin_key(access_key) {
         return HttpResponse::Unauthorized()
             .content_type("text/plain")
             .body("401 Unauthorized\n\nInvalid access key\n");
     }
 
-    let args: Vec<&str> = params
+    let advanced_args: Vec<&str> = params
         .iter()
         .filter_map(|(name, value)| {
             if name == "args[]" {
@@ -123,121 +797,1119 @@ async fn post_download(data: Data<'_>, params: web::Form<Vec<(String, String)>>)
         })
         .collect();
 
-    if args.is_empty() {
-        return HttpResponse::Found()
-            .header(http::header::LOCATION, "/download")
-            .finish();
-    }
+    // Free-form args are for admins; others pick a profile for a URL.
+    let args = if !advanced_args.is_empty() {
+        if !data.is_admin_key(access_key) {
+            return HttpResponse::Forbidden()
+                .content_type("text/plain")
+                .body("403 Forbidden\n\nfree-form args require the admin key\n");
+        }
+        advanced_args
+    } else {
+        let url = match param("url") {
+            Some(url) => url,
+            None => {
+                return HttpResponse::Found()
+                    .header(http::header::LOCATION, "/download")
+                    .finish()
+            }
+        };
+        let is_http = Url::parse(url)
+            .map(|url| matches!(url.scheme(), "http" | "https"))
+            .unwrap_or(false);
+        if !is_http {
+            return HttpResponse::BadRequest()
+                .content_type("text/plain")
+                .body("400 Bad Request\n\nthe URL must be an http or https URL\n");
+        }
+        let profile = match param("profile") {
+            Some(name) => data.config.profiles.iter().find(|p| p.name == name),
+            None => data.config.profiles.first(),
+        };
+        match profile {
+            Some(profile) => profile.args_for(url),
+            None => {
+                return HttpResponse::BadRequest()
+                    .content_type("text/plain")
+                    .body("400 Bad Request\n\nunknown profile\n")
+            }
+        }
+    };
 
-    match data.recorder.spawn_job("youtube-dl", &args) {
-        Ok(job) => HttpResponse::Found()
-            .header(http::header::LOCATION, format!("/jobs/{}", job.id()))
-            .finish(),
-        Err(err) => HttpResponse::InternalServerError()
-            .content_type("text/plain")
-            .body(format!("500 Internal Server Error\n\n{:?}\n", err)),
+    let output_template = param("output_template");
+    let submission = Submission::new("form", &args, output_template);
+    let details = json!({ "source": "form", "args": args, "output_template": output_template });
+    data.audit(&req, access_key, "submit", details);
+
+    if data.recorder.is_read_only() {
+        data.history.record(&submission.skipped(READ_ONLY_MESSAGE));
+        return read_only_response();
     }
-}
 
-async fn get_job(req: HttpRequest, data: Data<'_>) -> ActixResult<impl Responder> {
-    fn sort_file_names(file_names: &mut Vec<String>) {
-        fn key(file_name: &str) -> (u8, &str) {
-            let mime = mime_guess::from_path(file_name).first_or_octet_stream();
-            let order = match mime.type_() {
-                mime::VIDEO => 0,
-                mime::AUDIO => 1,
-                mime::IMAGE => 2,
-                _ => 3,
-            };
-            (order, file_name)
+    let output_template = output_template.or(data.config.output_template.as_deref());
+    let args = match downloader::with_output_template(&args, output_template) {
+        Ok(args) => args,
+        Err(err) => {
+            data.history.record(&submission.skipped(err));
+            return HttpResponse::BadRequest()
+                .content_type("text/plain")
+                .body(format!("400 Bad Request\n\n{}\n", err));
         }
+    };
 
-        file_names.sort_by(|a, b| key(&a).cmp(&key(&b)));
+    let options = match job_options(&params) {
+        Ok(options) => JobOptions {
+            request_id: Some(request_id.to_string()),
+            ..options
+        },
+        Err(err) => {
+            data.history.record(&submission.skipped(&err));
+            return HttpResponse::BadRequest()
+                .content_type("text/plain")
+                .body(format!("400 Bad Request\n\n{}\n", err));
+        }
+    };
+    if options.browser_cookies && data.config.cookies_from_browser.is_none() {
+        data.history
+            .record(&submission.skipped("browser cookies are not enabled"));
+        return HttpResponse::BadRequest()
+            .content_type("text/plain")
+            .body("400 Bad Request\n\nbrowser cookies are not enabled\n");
     }
 
-    let job_id: JobId = From::<String>::from(req.match_info().query("id").to_owned());
+    let estimate =
+        match check_download_size(data.config.clone(), data.disk_stats.clone(), &args).await {
+            Ok(estimate) => estimate,
+            Err(err) => {
+                data.history.record(&submission.skipped(&err));
+                return HttpResponse::InsufficientStorage()
+                    .content_type("text/plain")
+                    .body(format!("507 Insufficient Storage\n\n{}\n", err));
+            }
+        };
 
-    let job = data
+    let result = data
         .recorder
-        .job(&job_id)
-        .ok_or_else(|| error::ErrorNotFound(format!("Job {} not found", &job_id)))?;
-
-    let invocation = job.invocation().unwrap_or_else(|| json!({}));
-
-    let mut file_names = job.file_names();
-    sort_file_names(&mut file_names);
+        .spawn_job_with(&data.config.downloader, &args, options);
+    if let (Ok(job), Some(estimate)) = (&result, &estimate) {
+        job.set_metadata(&estimate.to_metadata()).ok();
+    }
 
-    let mut h = HashMap::new();
-    h.insert("id", json!(format!("{}", job_id)));
-    h.insert("invocation", invocation);
-    h.insert("file_names", json!(file_names));
+    match result {
+        Ok(job) => {
+            data.history.record(&submission.accepted(job.id()));
+            HttpResponse::Found()
+                .header(http::header::LOCATION, format!("/jobs/{}", job.id()))
+                .cookie(flash_cookie("The download has been submitted."))
+                .finish()
+        }
+        Err(err) => {
+            data.history.record(&submission.skipped(&err));
+            HttpResponse::build(err.status_code())
+                .content_type("text/plain")
+                .body(format!("{}\n\n{}\n", err.status_code(), err))
+        }
+    }
+}
 
-    render_html(&data.handlebars, "job", &h)
+/// The http and https links in `text`, in order and without duplicates.
+fn find_links(text: &str) -> Vec<String> {
+    let mut finder = linkify::LinkFinder::new();
+    finder.kinds(&[linkify::LinkKind::Url]);
+    let mut links: Vec<String> = vec![];
+    for link in finder.links(text) {
+        let is_http = Url::parse(link.as_str())
+            .map(|url| matches!(url.scheme(), "http" | "https"))
+            .unwrap_or(false);
+        if is_http && !links.iter().any(|seen| seen == link.as_str()) {
+            links.push(link.as_str().to_owned());
+        }
+    }
+    links
 }
 
-async fn head_job_process(req: HttpRequest, data: Data<'_>) -> ActixResult<impl Responder> {
-    let job_id: JobId = From::<String>::from(req.match_info().query("id").to_owned());
-    let job = data.recorder.job(&job_id);
+/// How many links of a batch are checked at once, as a batch may list up to 1000 and the size
+/// check of each asks the downloader for its info.
+const BATCH_SIZE_CHECKS: usize = 4;
 
-    if job.map(|j| j.is_running()).unwrap_or(false) {
-        return Ok(HttpResponse::Ok().finish());
-    }
+/// Spawns a job for each link of a batch, given with the args submitted for it, or attaches it
+/// to the job spawned before for the same link, and tells whether the job is new or why the link
+/// was rejected. The links are checked first, `BATCH_SIZE_CHECKS` at a time, and the jobs are then
+/// spawned in order.
+async fn spawn_batch_jobs(
+    data: &Data<'_>,
+    request_id: &RequestId,
+    batch: &[(&str, Vec<&str>)],
+) -> Vec<Result<(Job, bool), String>> {
+    let checks: Vec<_> = stream::iter(batch)
+        .map(|(url, submitted_args)| async move {
+            if find_links(url) != [*url] {
+                return Err("not an http or https link".to_owned());
+            }
+            let args = downloader::with_output_template(
+                submitted_args,
+                data.config.output_template.as_deref(),
+            )?;
+            let estimate =
+                check_download_size(data.config.clone(), data.disk_stats.clone(), &args).await?;
+            Ok((args, estimate))
+        })
+        .buffered(BATCH_SIZE_CHECKS)
+        .collect()
+        .await;
 
-    Ok(HttpResponse::NoContent().finish())
+    checks
+        .into_iter()
+        .map(|check| {
+            let (args, estimate) = check?;
+            let (job, is_new) = data
+                .recorder
+                .spawn_or_attach_job(
+                    &data.config.downloader,
+                    &args,
+                    JobOptions {
+                        request_id: Some(request_id.to_string()),
+                        ..JobOptions::default()
+                    },
+                )
+                .map_err(|err| err.to_string())?;
+            if let (Some(estimate), true) = (estimate, is_new) {
+                job.set_metadata(&estimate.to_metadata()).ok();
+            }
+            Ok((job, is_new))
+        })
+        .collect()
 }
 
-async fn get_job_file(req: HttpRequest, data: Data<'_>) -> ActixResult<impl Responder> {
-    let job_id: JobId = From::<String>::from(req.match_info().query("id").to_owned());
-    let job = data
-        .recorder
-        .job(&job_id)
-        .ok_or_else(|| error::ErrorNotFound(""))?;
-
-    // Documentation says query is percent-decoded automatically, but it seems it isn't.
-    let file_name: String = req.match_info().query("file_name").to_owned();
-    let file_name = percent_decode(file_name.as_bytes())
-        .decode_utf8_lossy()
-        .to_string();
+/// Finds the links in pasted text, and downloads each as its own job unless `preview` is set.
+async fn post_api_batch(
+    req: HttpRequest,
+    request_id: RequestId,
+    data: Data<'_>,
+    payload: web::Json<PostApiBatchPayload>,
+) -> ApiResult<HttpResponse> {
+    println!(
+        "[{}] post_api_batch {} chars, {:?} urls, preview = {}",
+        request_id,
+        payload.text.len(),
+        payload.urls.as_ref().map(Vec::len),
+        payload.preview
+    );
 
-    let path = job.path().join(&file_name);
-    let mut f = NamedFile::open(path)?;
+    if payload.access_key != data.access_key {
+        return Err(ApiError::unauthorized());
+    }
 
-    if file_name.ends_with(".txt") {
-        f = f.set_content_type(mime::TEXT_PLAIN_UTF_8);
+    let urls = match &payload.urls {
+        Some(urls) => urls.clone(),
+        None => find_links(&payload.text),
+    };
+    if payload.preview {
+        return Ok(HttpResponse::Ok().json(json!({ "urls": urls })));
+    }
+    let details = json!({ "source": "batch", "urls": urls });
+    data.audit(&req, &payload.access_key, "submit", details);
+    if data.recorder.is_read_only() {
+        return Err(ApiError::read_only());
     }
 
-    Ok(f)
-}
+    let batch: Vec<(&str, Vec<&str>)> = urls
+        .iter()
+        .map(|url| {
+            let submitted_args = vec!["--write-all-thumbnails", "--write-info-json", url.as_str()];
+            (url.as_str(), submitted_args)
+        })
+        .collect();
+    let results = spawn_batch_jobs(&data, &request_id, &batch).await;
 
-async fn get_jobs(data: Data<'_>) -> ActixResult<impl Responder> {
-    fn first_media_file_name(mut file_names: Vec<String>) -> Option<String> {
-        file_names.sort();
-        file_names.into_iter().find(|file_name| {
-            let mime = mime_guess::from_path(&file_name).first_or_octet_stream();
-            [mime::AUDIO, mime::VIDEO].contains(&mime.type_())
+    let mut jobs = vec![];
+    let mut skipped = vec![];
+    for ((url, submitted_args), result) in batch.into_iter().zip(results) {
+        let submission = Submission::new("batch", &submitted_args, None);
+        match result {
+            Ok((job, _)) => {
+                data.history.record(&submission.accepted(job.id()));
+                jobs.push(json!({ "url": url, "id": job.id().to_string() }));
+            }
+            Err(err) => {
+                data.history.record(&submission.skipped(&err));
+                skipped.push(json!({ "url": url, "reason": err }));
+            }
+        }
+    }
+    Ok(HttpResponse::Ok().json(json!({ "jobs": jobs, "skipped": skipped })))
+}
+
+/// Downloads each of a list of URLs, such as a playlist exported from another tool, as its own
+/// job, and responds with what became of each: `created` with the new job's id, `duplicate` with
+/// the id of the job spawned for the same URL before, or `rejected` with the reason.
+async fn post_api_v1_jobs_batch(
+    req: HttpRequest,
+    request_id: RequestId,
+    data: Data<'_>,
+    payload: web::Json<PostApiV1JobsBatchPayload>,
+) -> ApiResult<HttpResponse> {
+    const MAX_BATCH_SIZE: usize = 1000;
+
+    println!(
+        "[{}] post_api_v1_jobs_batch {} urls",
+        request_id,
+        payload.urls.len()
+    );
+
+    let access_key = header_access_key(&req).unwrap_or_default();
+    if access_key != data.access_key {
+        return Err(ApiError::unauthorized());
+    }
+    if payload.urls.len() > MAX_BATCH_SIZE {
+        return Err(ApiError::bad_request(
+            "too_many_urls",
+            format!("at most {} urls can be submitted at once", MAX_BATCH_SIZE),
+        ));
+    }
+    if data.recorder.is_read_only() {
+        return Err(ApiError::read_only());
+    }
+
+    let items: Vec<(&str, Option<&str>)> = payload
+        .urls
+        .iter()
+        .map(|item| match item {
+            BatchItem::Url(url) => (url.as_str(), None),
+            BatchItem::WithProfile { url, profile } => (url.as_str(), profile.as_deref()),
+        })
+        .collect();
+    let details = json!({ "source": "batch", "urls": items });
+    data.audit(&req, access_key, "submit", details);
+
+    // The args of each item, or `None` if its profile is unknown. Each distinct args are spawned
+    // once, so that an item repeated in the list is a duplicate even if submissions of the same
+    // URL are not coalesced, and is checked once.
+    let item_args: Vec<Option<Vec<&str>>> = items
+        .iter()
+        .map(|&(url, profile_name)| {
+            let profile = match profile_name {
+                Some(name) => data.config.profiles.iter().find(|p| p.name == name),
+                None => data.config.profiles.first(),
+            };
+            profile.map(|profile| profile.args_for(url))
+        })
+        .collect();
+    let mut batch: Vec<(&str, Vec<&str>)> = vec![];
+    for (&(url, _), submitted_args) in items.iter().zip(&item_args) {
+        if let Some(submitted_args) = submitted_args {
+            if !batch.iter().any(|(_, args)| args == submitted_args) {
+                batch.push((url, submitted_args.clone()));
+            }
+        }
+    }
+    let spawned: HashMap<Vec<&str>, Result<(Job, bool), String>> = batch
+        .iter()
+        .map(|(_, submitted_args)| submitted_args.clone())
+        .zip(spawn_batch_jobs(&data, &request_id, &batch).await)
+        .collect();
+
+    let mut results = vec![];
+    let mut reported: Vec<&Vec<&str>> = vec![];
+    for (&(url, _), submitted_args) in items.iter().zip(&item_args) {
+        let submitted_args = match submitted_args {
+            Some(submitted_args) => submitted_args,
+            None => {
+                results
+                    .push(json!({ "url": url, "status": "rejected", "reason": "unknown profile" }));
+                continue;
+            }
+        };
+        let submission = Submission::new("batch", submitted_args, None);
+        match &spawned[submitted_args] {
+            Ok((job, is_new)) => {
+                data.history.record(&submission.accepted(job.id()));
+                let is_created = *is_new && !reported.contains(&submitted_args);
+                let status = if is_created { "created" } else { "duplicate" };
+                results.push(json!({ "url": url, "status": status, "id": job.id().to_string() }));
+            }
+            Err(err) => {
+                data.history.record(&submission.skipped(err));
+                results.push(json!({ "url": url, "status": "rejected", "reason": err }));
+            }
+        }
+        reported.push(submitted_args);
+    }
+    Ok(HttpResponse::Ok().json(json!({ "results": results })))
+}
+
+/// Reads the post-processors, start time, time limit and the job to run after of the download
+/// form.
+fn job_options(params: &[(String, String)]) -> Result<JobOptions, String> {
+    let mut options = JobOptions::default();
+    for (name, value) in params {
+        let value = value.trim();
+        match name.as_str() {
+            "post[]" => options
+                .post_steps
+                .push(value.parse::<PostProcessor>()?.step()),
+            "start_at" if !value.is_empty() => {
+                let start_at = DateTime::parse_from_rfc3339(value)
+                    .map_err(|err| format!("invalid start time: {}", err))?;
+                options.start_at = Some(start_at.with_timezone(&Utc));
+            }
+            "browser_cookies" => options.browser_cookies = true,
+            "after" if !value.is_empty() => {
+                let after: JobId = value
+                    .parse()
+                    .map_err(|err: RecorderError| err.to_string())?;
+                options.after = Some(after);
+            }
+            "time_limit" if !value.is_empty() => {
+                let minutes: u64 = value
+                    .parse()
+                    .ok()
+                    .filter(|&minutes| minutes > 0)
+                    .ok_or("time limit must be a positive number of minutes")?;
+                options.time_limit = Some(Duration::from_secs(minutes * 60));
+            }
+            _ => {}
+        }
+    }
+    Ok(options)
+}
+
+/// Lists recent submissions, including those that did not become jobs and why, with buttons to
+/// submit them again.
+async fn get_history(data: Data<'_>) -> ActixResult<impl Responder> {
+    render_html(&data.handlebars, "history", &())
+}
+
+/// Renders the latest submissions for the history page, which loads them with the key from its
+/// URL hash, as their args may hold anything that was submitted.
+async fn get_history_entries(req: HttpRequest, data: Data<'_>) -> ActixResult<impl Responder> {
+    if header_access_key(&req) != Some(data.access_key.as_str()) {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+
+    let history = data.history.clone();
+    let submissions = web::block(move || history.recent(HISTORY_PAGE_SIZE)).await?;
+    // Those made with a profile are resubmitted as the profile and URL, which needs no admin key.
+    let submissions: Vec<Json> = submissions
+        .iter()
+        .map(|submission| {
+            let args: Vec<&str> = submission.args.iter().map(String::as_str).collect();
+            let mut json = json!(submission);
+            if let Some((profile, url)) = Profile::find(&data.config.profiles, &args) {
+                json["profile"] = json!(profile.name);
+                json["url"] = json!(url);
+            }
+            json
+        })
+        .collect();
+
+    let mut h = HashMap::new();
+    h.insert("submissions", json!(submissions));
+    render_html(&data.handlebars, "history_entries", &h)
+}
+
+async fn get_events(data: Data<'_>) -> impl Responder {
+    let stream = data
+        .broadcaster
+        .new_client()
+        .map(Ok::<_, actix_web::Error>);
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .header(http::header::CACHE_CONTROL, "no-cache")
+        // The compressor buffers output, which would hold events back.
+        .encoding(ContentEncoding::Identity)
+        .streaming(stream)
+}
+
+async fn get_graphql() -> impl Responder {
+    HttpResponse::Ok()
+        .content_type("text/html")
+        // The playground is loaded from a CDN.
+        .header(
+            header::CONTENT_SECURITY_POLICY,
+            "default-src 'self' 'unsafe-inline' https://cdn.jsdelivr.net https://fonts.googleapis.com \
+             https://fonts.gstatic.com; img-src 'self' data: https://cdn.jsdelivr.net",
+        )
+        .body(playground_source(
+            GraphQLPlaygroundConfig::new("/graphql").subscription_endpoint("/graphql/stream"),
+        ))
+}
+
+async fn post_graphql(
+    req: HttpRequest,
+    request_id: RequestId,
+    data: Data<'_>,
+    request: web::Json<async_graphql::Request>,
+) -> impl Responder {
+    let request = request
+        .into_inner()
+        .data(request_access_key(&req))
+        .data(Origin::of(&req))
+        .data(request_id);
+    HttpResponse::Ok().json(data.schema.execute(request).await)
+}
+
+/// Executes a subscription and streams its results in the "distinct connections" mode of the
+/// GraphQL over SSE protocol.
+async fn post_graphql_stream(
+    req: HttpRequest,
+    data: Data<'_>,
+    request: web::Json<async_graphql::Request>,
+) -> impl Responder {
+    fn event(name: &str, data: &str) -> Result<Bytes, actix_web::Error> {
+        Ok(Bytes::from(format!("event: {}\ndata: {}\n\n", name, data)))
+    }
+
+    let request = request.into_inner().data(request_access_key(&req));
+    let stream = data
+        .schema
+        .execute_stream_with_session_data(request, Default::default())
+        .map(|response| {
+            event(
+                "next",
+                &serde_json::to_string(&response).unwrap_or_default(),
+            )
         })
+        .chain(stream::once(future::ready(event("complete", ""))));
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .header(http::header::CACHE_CONTROL, "no-cache")
+        .encoding(ContentEncoding::Identity)
+        .streaming(stream)
+}
+
+fn request_access_key(req: &HttpRequest) -> RequestAccessKey {
+    RequestAccessKey(header_access_key(req).map(str::to_owned))
+}
+
+async fn get_job(req: HttpRequest, data: Data<'_>) -> ActixResult<impl Responder> {
+    fn sort_file_names(job_path: &Path, file_names: Vec<OsString>) -> Vec<OsString> {
+        fn order(path: &Path) -> u8 {
+            match guess_mime(path).type_() {
+                mime::VIDEO => 0,
+                mime::AUDIO => 1,
+                mime::IMAGE => 2,
+                _ => 3,
+            }
+        }
+
+        // Ordered once per file, as files without an extension are read to tell their type.
+        let mut ordered: Vec<_> = file_names
+            .into_iter()
+            .map(|name| (order(&job_path.join(&name)), name))
+            .collect();
+        ordered.sort_by(|(a_order, a), (b_order, b)| {
+            a_order.cmp(b_order).then_with(|| {
+                compare_file_names(&a.to_string_lossy(), &b.to_string_lossy())
+                    .then_with(|| a.cmp(b))
+            })
+        });
+        ordered.into_iter().map(|(_, name)| name).collect()
     }
 
-    let mut jobs: Vec<(String, Option<String>)> = data
+    let job_id: JobId = req.match_info().query("id").parse()?;
+
+    let job = data
         .recorder
-        .jobs()
+        .job(&job_id)
+        .ok_or_else(|| error::ErrorNotFound(format!("Job {} not found", &job_id)))?;
+
+    let invocation = job.invocation().unwrap_or_else(|| json!({}));
+
+    let recorder = data.recorder.clone();
+    let chained_job = job.clone();
+    let chain = web::block(move || Ok::<_, ()>(recorder.job_chain(&chained_job)))
+        .await
+        .map_err(|_| error::ErrorInternalServerError("could not read the job chain"))?;
+    // A job with nothing chained to it has no chain to show.
+    let chain: Vec<Json> = chain
+        .iter()
+        .filter(|_| chain.len() > 1)
+        .map(|chained| {
+            json!({
+                "id": chained.id().to_string(),
+                "title": chained.title(),
+                "state": chained.state(),
+                "current": chained.id() == &job_id,
+            })
+        })
+        .collect();
+
+    let file_names = sort_file_names(job.path(), job.os_file_names());
+    let media_info = {
+        let job = job.clone();
+        web::block(move || Ok::<_, ()>(job.media_info()))
+            .await
+            .unwrap_or_default()
+    };
+    let mut files: Vec<_> = file_names
+        .iter()
+        .map(|name| {
+            let size = fs::metadata(job.path().join(name))
+                .map(|metadata| metadata.len())
+                .ok();
+            // Names that are not UTF-8 are shown with replacement characters, and their bytes are
+            // sent in the query as the router takes only UTF-8 paths.
+            let display_name = name.to_string_lossy();
+            let mut href = utf8_percent_encode(&display_name, NON_ALPHANUMERIC).to_string();
+            if name.to_str().is_none() {
+                let bytes = platform::file_name_bytes(name);
+                href = format!("{}?raw={}", href, percent_encode(&bytes, NON_ALPHANUMERIC));
+            }
+            let separator = if href.contains('?') { '&' } else { '?' };
+            let download_href = format!("{}{}download=1", href, separator);
+            json!({
+                "name": display_name,
+                "href": href,
+                "download_href": download_href,
+                "media": media_info.get(display_name.as_ref()).map(humanize_media_info),
+                "partial": is_partial_file_name(&display_name),
+                "size": size,
+            })
+        })
+        .collect();
+
+    // Subtitles are listed under the media file they are for, and shown by the player as tracks.
+    let is_media = |name: &OsString| {
+        !is_partial_file_name(&name.to_string_lossy())
+            && [mime::VIDEO, mime::AUDIO].contains(&guess_mime(&job.path().join(name)).type_())
+    };
+    let media_indices: Vec<usize> = (0..file_names.len())
+        .filter(|&i| is_media(&file_names[i]))
+        .collect();
+    let mut is_grouped = vec![false; files.len()];
+    for i in 0..files.len() {
+        let name = file_names[i].to_string_lossy();
+        let media_index = media_indices.iter().find_map(|&m| {
+            subtitle_language(&file_names[m].to_string_lossy(), &name).map(|lang| (m, lang))
+        });
+        let (m, lang) = match media_index {
+            Some(found) => found,
+            None => continue,
+        };
+        let href = files[i]["href"].as_str().unwrap_or_default().to_owned();
+        let track_href = if name.to_lowercase().ends_with(".srt") {
+            let separator = if href.contains('?') { '&' } else { '?' };
+            format!("{}{}format=vtt", href, separator)
+        } else {
+            href
+        };
+        let mut subtitle = files[i].clone();
+        subtitle["lang"] = json!(lang);
+        subtitle["track_href"] = json!(track_href);
+        match files[m]["subtitles"].as_array_mut() {
+            Some(subtitles) => subtitles.push(subtitle),
+            None => files[m]["subtitles"] = json!([subtitle]),
+        }
+        is_grouped[i] = true;
+    }
+    let player = media_indices.first().map(|&m| files[m].clone());
+    let files: Vec<_> = files
         .into_iter()
-        .map(|job| {
-            let id = job.id().to_string();
-            let media_file_name = first_media_file_name(job.file_names());
-            (id, media_file_name)
+        .zip(is_grouped)
+        .filter(|(_, is_grouped)| !is_grouped)
+        .map(|(file, _)| file)
+        .collect();
+
+    let state = job.state();
+    let waiting_for = match state {
+        Some(JobState::Waiting) => invocation["after"].clone(),
+        _ => Json::Null,
+    };
+    let has_partial_files = file_names
+        .iter()
+        .any(|name| is_partial_file_name(&name.to_string_lossy()));
+    let is_unfinished = matches!(
+        state,
+        Some(JobState::Failed | JobState::Cancelled | JobState::Interrupted)
+    );
+
+    let mut h = HashMap::new();
+    h.insert("id", json!(format!("{}", job_id)));
+    // The args are shown only through the API, to those with the access key.
+    h.insert("start_at", invocation["start_at"].clone());
+    h.insert("can_rerun", json!(invocation["command"].is_string()));
+    h.insert("metadata", json!(job.metadata()));
+    h.insert(
+        "failure",
+        json!(job.failure_reason().map(|reason| json!({
+            "reason": reason.as_str(),
+            "suggestion": reason.suggestion(),
+        }))),
+    );
+    h.insert("state", json!(state));
+    h.insert("chain", json!(chain));
+    h.insert("waiting_for", waiting_for);
+    h.insert("files", json!(files));
+    h.insert("player", json!(player));
+    h.insert(
+        "runtime",
+        json!(job.runtime().map(|runtime| json!({
+            "started_at": runtime.started_at,
+            "finished_at": runtime.finished_at,
+            "duration": humanize_duration(runtime.duration()),
+        }))),
+    );
+    h.insert(
+        "can_clean_partial_files",
+        json!(has_partial_files && is_unfinished && !job.is_running()),
+    );
+
+    render_html(&data.handlebars, "job", &h)
+}
+
+/// Serves one of the job's images resized to `w` pixels wide (default: 320), e.g. for the jobs
+/// list. Images that can't be resized, e.g. ones too large or in a format that can't be
+/// decoded, are not found rather than served as they are, which may be several megabytes.
+async fn get_job_thumb(
+    req: HttpRequest,
+    data: Data<'_>,
+    query: web::Query<GetJobThumbQuery>,
+) -> ActixResult<impl Responder> {
+    let job_id: JobId = req.match_info().query("id").parse()?;
+    let job = data
+        .recorder
+        .job(&job_id)
+        .ok_or_else(|| error::ErrorNotFound(""))?;
+    let width = query.w.unwrap_or(DEFAULT_THUMBNAIL_WIDTH);
+    if !THUMBNAIL_WIDTHS.contains(&width) {
+        return Err(error::ErrorBadRequest(format!(
+            "w must be one of {:?}",
+            THUMBNAIL_WIDTHS
+        )));
+    }
+    let file_name_bytes: Vec<u8> =
+        percent_decode(req.match_info().query("file_name").as_bytes()).collect();
+    // Only the job's own images, which are listed by name, so that no other file can be named.
+    let file_name = String::from_utf8(file_name_bytes)
+        .ok()
+        .filter(|name| job.file_names().contains(name))
+        .filter(|name| guess_mime(&job.path().join(name)).type_() == mime::IMAGE)
+        .ok_or_else(|| error::ErrorNotFound(""))?;
+
+    let (j, name) = (job.clone(), file_name.clone());
+    let path = web::block(move || cached_thumbnail(&j, &name, width))
+        .await
+        .map_err(|err| {
+            println!(
+                "job {} image {:?} could not be resized: {}",
+                job_id, file_name, err
+            );
+            error::ErrorNotFound("")
+        })?;
+    Ok(NamedFile::open(path)?)
+}
+
+async fn head_job_process(req: HttpRequest, data: Data<'_>) -> ActixResult<impl Responder> {
+    let job_id: JobId = req.match_info().query("id").parse()?;
+    let job = data.recorder.job(&job_id);
+
+    if job.map(|j| j.is_running()).unwrap_or(false) {
+        return Ok(HttpResponse::Ok().finish());
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+async fn get_job_file(
+    req: HttpRequest,
+    data: Data<'_>,
+    query: web::Query<GetJobFileQuery>,
+) -> ActixResult<impl Responder> {
+    let job_id: JobId = req.match_info().query("id").parse()?;
+    let job = data
+        .recorder
+        .job(&job_id)
+        .ok_or_else(|| error::ErrorNotFound(""))?;
+
+    // Names that are not UTF-8 can't be routed, so they are sent in `raw` instead. It is taken
+    // from the query string as it is, as `web::Query` would decode its bytes into a `String` and
+    // replace those that are not UTF-8.
+    let raw_file_name = req
+        .query_string()
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("raw="))
+        .unwrap_or_else(|| req.match_info().query("file_name"));
+    let file_name_bytes: Vec<u8> = percent_decode(raw_file_name.as_bytes()).collect();
+    // Hidden files, such as the credentials of a running job, are not served, nor are the job's
+    // own files, such as its pid and logs, which are sent only through the API. `info` is
+    // matched in any case, as the file system may fold it.
+    let mut parts = file_name_bytes.split(|&b| b == b'/' || b == b'\\');
+    let is_hidden = parts.clone().any(|part| part.starts_with(b"."));
+    let first = parts.next().unwrap_or_default();
+    if is_hidden || first.is_empty() || first.eq_ignore_ascii_case(b"info") {
+        return Err(error::ErrorNotFound(""));
+    }
+    let os_file_name = platform::file_name_from_bytes(file_name_bytes);
+    let file_name = os_file_name.to_string_lossy();
+    // Names are relative to the job dir. An absolute one, or one with a root or a drive, is
+    // refused even if it names a file in the job dir.
+    let is_relative = Path::new(&os_file_name)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+    if !is_relative {
+        return Err(error::ErrorNotFound(""));
+    }
+
+    let path = job.path().join(&os_file_name);
+    // A link may lead outside, or into `info`; only files whose real path is in the job dir, and
+    // not in its `info`, are served.
+    let is_inside = match (path.canonicalize(), job.path().canonicalize()) {
+        (Ok(path), Ok(job_path)) => path
+            .strip_prefix(job_path)
+            .ok()
+            .and_then(|relative| relative.components().next())
+            .is_some_and(|first| !first.as_os_str().eq_ignore_ascii_case("info")),
+        _ => false,
+    };
+    if !is_inside {
+        return Err(error::ErrorNotFound(""));
+    }
+    // Browsers show only WebVTT in `<track>`, so the player asks for SubRip converted.
+    let wants_vtt = query.format.as_deref() == Some("vtt");
+    if wants_vtt && file_name.to_lowercase().ends_with(".srt") {
+        let srt = web::block(move || fs::read(path))
+            .await
+            .map_err(|_| error::ErrorNotFound(""))?;
+        return Ok(HttpResponse::Ok()
+            .content_type("text/vtt; charset=utf-8")
+            .header(
+                header::CONTENT_SECURITY_POLICY,
+                JOB_FILE_CONTENT_SECURITY_POLICY,
+            )
+            .body(srt_to_vtt(&String::from_utf8_lossy(&srt))));
+    }
+
+    let mut f = NamedFile::open(&path)?;
+    let modified = f.file().metadata()?.modified()?;
+
+    if file_name.ends_with(".txt") {
+        f = f.set_content_type(mime::TEXT_PLAIN_UTF_8);
+    } else if let Some(mime) = subtitle_mime(&file_name) {
+        f = f.set_content_type(mime);
+    } else if mime_guess::from_path(&path).first().is_none() {
+        // Some extractors write files without an extension, which are told by their contents.
+        // Only media is shown inline as what it is; text, which may be HTML, is shown as plain
+        // text, and anything else, including SVG, which may run scripts, is downloaded.
+        let mime = guess_mime(&path);
+        let base_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let inline = header::ContentDisposition {
+            disposition: header::DispositionType::Inline,
+            parameters: vec![header::DispositionParam::Filename(base_name.to_string())],
+        };
+        let is_media = [mime::IMAGE, mime::VIDEO, mime::AUDIO].contains(&mime.type_())
+            && mime.subtype() != mime::SVG;
+        f = if is_media {
+            f.set_content_type(mime).set_content_disposition(inline)
+        } else if mime.type_() == mime::TEXT {
+            f.set_content_type(mime::TEXT_PLAIN_UTF_8)
+                .set_content_disposition(inline)
+        } else {
+            f.set_content_type(mime)
+                .set_content_disposition(attachment_disposition(&base_name))
+        };
+    }
+    if query.download.as_deref() == Some("1") {
+        let base_name = path.file_name().unwrap_or_default().to_string_lossy();
+        f = f.set_content_disposition(attachment_disposition(&base_name));
+    }
+    // Media files are already compressed, and compression would defeat range requests.
+    f = f.set_content_encoding(ContentEncoding::Identity);
+
+    let mut res = f.into_response(&req)?;
+    res.headers_mut().insert(
+        header::CONTENT_SECURITY_POLICY,
+        HeaderValue::from_static(JOB_FILE_CONTENT_SECURITY_POLICY),
+    );
+    if res.status() == StatusCode::OK && is_not_modified_since(&req, modified) {
+        let mut not_modified = HttpResponse::NotModified();
+        for name in &[header::ETAG, header::LAST_MODIFIED] {
+            if let Some(value) = res.headers().get(name) {
+                not_modified.header(name.clone(), value.clone());
+            }
+        }
+        return Ok(not_modified.finish());
+    }
+
+    Ok(res)
+}
+
+/// The media info of a file as shown next to it on the job page, e.g. `1920x1080`, `h264 / aac`,
+/// and `3m 20s`.
+fn humanize_media_info(info: &MediaInfo) -> Json {
+    let resolution = info
+        .width
+        .zip(info.height)
+        .map(|(width, height)| format!("{}x{}", width, height));
+    let codecs: Vec<&str> = [&info.video_codec, &info.audio_codec]
+        .iter()
+        .filter_map(|codec| codec.as_deref())
+        .collect();
+    let duration = info
+        .duration_seconds
+        .map(|secs| humanize_duration(chrono::Duration::seconds(secs.round() as i64)));
+    json!({
+        "resolution": resolution,
+        "codecs": codecs.join(" / "),
+        "duration": duration,
+    })
+}
+
+/// A Content-Disposition that makes browsers save the file rather than show it, under a name
+/// without characters that are unsafe in file names. The name is given in ASCII for old clients,
+/// and in full as the RFC 5987 `filename*` if it has other characters, such as those of titles.
+fn attachment_disposition(file_name: &str) -> header::ContentDisposition {
+    let sanitized: String = file_name
+        .chars()
+        .map(|c| match c {
+            '"' | '\\' | '/' | ':' | '*' | '?' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
         })
         .collect();
+    let ascii: String = sanitized
+        .chars()
+        .map(|c| if c.is_ascii() { c } else { '_' })
+        .collect();
+    let mut parameters = vec![header::DispositionParam::Filename(ascii)];
+    if !sanitized.is_ascii() {
+        parameters.push(header::DispositionParam::FilenameExt(
+            header::ExtendedValue {
+                charset: header::Charset::Ext("UTF-8".to_owned()),
+                language_tag: None,
+                value: sanitized.into_bytes(),
+            },
+        ));
+    }
+    header::ContentDisposition {
+        disposition: header::DispositionType::Attachment,
+        parameters,
+    }
+}
 
-    jobs.sort();
-    jobs.reverse();
+/// NamedFile compares If-Modified-Since against the mtime with sub-second precision, so a client
+/// echoing back Last-Modified never gets a 304. Compare whole seconds as HTTP dates do.
+fn is_not_modified_since(req: &HttpRequest, modified: SystemTime) -> bool {
+    if req.headers().contains_key(header::IF_NONE_MATCH) {
+        return false;
+    }
+    let since = match req.get_header::<header::IfModifiedSince>() {
+        Some(header::IfModifiedSince(since)) => SystemTime::from(since),
+        None => return false,
+    };
+    let secs = |time: SystemTime| {
+        time.duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0)
+    };
+    secs(modified) <= secs(since)
+}
+
+async fn compute_stats(data: &Data<'_>) -> ActixResult<Stats> {
+    let recorder = data.recorder.clone();
+    let disk = data.disk_stats.get();
+    let units = data.config.byte_units;
+    web::block(move || Ok::<_, ()>(Stats::new(&recorder, disk, units)))
+        .await
+        .map_err(|_| error::ErrorInternalServerError("could not compute stats"))
+}
+
+async fn list_uploaders(data: &Data<'_>) -> ActixResult<Vec<UploaderCount>> {
+    let recorder = data.recorder.clone();
+    web::block(move || Ok::<_, ()>(uploader_counts(&recorder)))
+        .await
+        .map_err(|_| error::ErrorInternalServerError("could not list uploaders"))
+}
+
+async fn get_uploaders(data: Data<'_>) -> ActixResult<impl Responder> {
+    let uploaders = list_uploaders(&data).await?;
+
+    let mut h = HashMap::new();
+    h.insert("uploaders", json!(uploaders));
+
+    render_html(&data.handlebars, "uploaders", &h)
+}
+
+async fn get_api_uploaders(data: Data<'_>) -> ApiResult<HttpResponse> {
+    let uploaders = list_uploaders(&data).await?;
+
+    Ok(HttpResponse::Ok().json(json!({ "uploaders": uploaders })))
+}
+
+/// The share target of the web app. Submits the first link shared, which apps put in any of the
+/// fields, with the access key remembered by the browser.
+async fn get_share(
+    data: Data<'_>,
+    query: web::Query<GetShareQuery>,
+) -> ActixResult<impl Responder> {
+    let mut finder = linkify::LinkFinder::new();
+    finder.kinds(&[linkify::LinkKind::Url]);
+    let link = [&query.url, &query.text, &query.title]
+        .iter()
+        .filter_map(|field| field.as_deref())
+        .flat_map(|field| finder.links(field))
+        .map(|link| link.as_str().to_owned())
+        .find(|link| link.starts_with("http://") || link.starts_with("https://"));
+
+    render_html(&data.handlebars, "share", &json!({ "link": link }))
+}
+
+/// Serves an embedded asset. The current version is cached for a year, as its URL changes with
+/// its contents; other requests are revalidated with the ETag.
+async fn get_static(req: HttpRequest, query: web::Query<GetStaticQuery>) -> HttpResponse {
+    let path = req.match_info().query("path");
+    let contents = match assets::asset(path) {
+        Some(contents) => contents,
+        None => return HttpResponse::NotFound().finish(),
+    };
+
+    let version = assets::version(&contents);
+    let etag = format!("\"{}\"", version);
+    let cache_control = if query.v.as_deref() == Some(version.as_str()) {
+        "public, max-age=31536000, immutable"
+    } else {
+        "no-cache"
+    };
+    if req
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .is_some_and(|value| value == etag.as_str())
+    {
+        return HttpResponse::NotModified()
+            .header(header::ETAG, etag)
+            .header(header::CACHE_CONTROL, cache_control)
+            .finish();
+    }
+
+    HttpResponse::Ok()
+        .content_type(
+            mime_guess::from_path(path)
+                .first_or_octet_stream()
+                .to_string(),
+        )
+        .header(header::ETAG, etag)
+        .header(header::CACHE_CONTROL, cache_control)
+        .body(contents.into_owned())
+}
+
+async fn get_stats(data: Data<'_>) -> ActixResult<impl Responder> {
+    let stats = compute_stats(&data).await?;
+
+    let mut h = HashMap::new();
+    h.insert(
+        "success_percent",
+        json!(stats
+            .success_ratio
+            .map(|ratio| format!("{:.1}%", ratio * 100.0))),
+    );
+    h.insert(
+        "total_runtime",
+        json!(humanize_duration(chrono::Duration::seconds(
+            stats.total_runtime_seconds
+        ))),
+    );
+    h.insert(
+        "mean_runtime",
+        json!(stats
+            .mean_runtime_seconds
+            .map(|secs| humanize_duration(chrono::Duration::seconds(secs)))),
+    );
+    h.insert("stats", json!(stats));
+
+    render_html(&data.handlebars, "stats", &h)
+}
+
+async fn get_api_stats(data: Data<'_>) -> ApiResult<HttpResponse> {
+    Ok(HttpResponse::Ok().json(compute_stats(&data).await?))
+}
+
+async fn get_jobs(data: Data<'_>, query: web::Query<GetJobsQuery>) -> ActixResult<impl Responder> {
+    fn first_media_file_name(job_path: &Path, mut file_names: Vec<String>) -> Option<String> {
+        file_names.sort_by(|a, b| compare_file_names(a, b));
+        file_names.into_iter().find(|file_name| {
+            if is_partial_file_name(file_name) {
+                return false;
+            }
+            let mime = guess_mime(&job_path.join(file_name));
+            [mime::AUDIO, mime::VIDEO].contains(&mime.type_())
+        })
+    }
+
+    let range = match TimeRange::parse(query.from.as_deref(), query.to.as_deref()) {
+        Ok(range) => range,
+        Err(err) => {
+            return Ok(HttpResponse::BadRequest()
+                .content_type("text/plain")
+                .body(format!("400 Bad Request\n\n{}\n", err)));
+        }
+    };
+
+    type JobRow = (
+        String,
+        Option<String>,
+        Option<JobState>,
+        Option<String>,
+        Option<String>,
+        Option<String>,
+        Option<String>,
+    );
+    // Reads the job dirs, and the files of each for its media file, off the worker thread.
+    let recorder = data.recorder.clone();
+    let uploader = query.uploader.clone();
+    let rows = move || {
+        recorder
+            .job_summaries_in(&range)
+            .into_iter()
+            .map(|(job, summary)| {
+                let id = job.id().to_string();
+                let media_file_name = first_media_file_name(job.path(), summary.file_names);
+                (
+                    id,
+                    media_file_name,
+                    summary.state,
+                    summary.uploader,
+                    summary.title,
+                    summary
+                        .runtime
+                        .map(|runtime| humanize_duration(runtime.duration())),
+                    summary.thumbnail,
+                )
+            })
+            .filter(|job| uploader.is_none() || job.3 == uploader)
+            .collect::<Vec<JobRow>>()
+    };
+    let mut jobs = web::block(move || Ok::<_, ()>(rows()))
+        .await
+        .map_err(|_| error::ErrorInternalServerError("could not list jobs"))?;
+
+    jobs.sort_by(|a, b| b.0.cmp(&a.0));
 
     let mut h = HashMap::new();
     h.insert("jobs", json!(jobs));
-    if let Some(stat) = DiskStat::new(data.recorder.work_dir_path()) {
-        h.insert("disk_available", json!(humanize_byte_size(stat.available)));
-        h.insert("disk_total", json!(humanize_byte_size(stat.total)));
-        h.insert("disk_used", json!(humanize_byte_size(stat.used)));
+    h.insert("uploader", json!(query.uploader));
+    h.insert("from", json!(query.from));
+    h.insert("to", json!(query.to));
+    let deleted: Vec<&str> = query
+        .deleted
+        .iter()
+        .flat_map(|deleted| deleted.split(','))
+        .filter(|job_id| job_id.parse::<JobId>().is_ok())
+        .collect();
+    h.insert(
+        "deleted_message",
+        json!(format!("Moved {} job(s) to the trash.", deleted.len())),
+    );
+    h.insert("deleted", json!(deleted));
+    h.insert("trash_days", json!(data.config.trash_days));
+    if let Some(stat) = data.disk_stats.get() {
+        let units = data.config.byte_units;
+        h.insert("disk_available", json!(units.humanize(stat.available)));
+        h.insert("disk_total", json!(units.humanize(stat.total)));
+        h.insert("disk_used", json!(units.humanize(stat.used)));
+        h.insert("inodes_available", json!(stat.inodes_available));
+        h.insert("inodes_total", json!(stat.inodes_total));
     } else {
         h.insert("disk_available", json!("N/A"));
         h.insert("disk_total", json!("N/A"));
@@ -247,21 +1919,971 @@ async fn get_jobs(data: Data<'_>) -> ActixResult<impl Responder> {
     render_html(&data.handlebars, "jobs", &h)
 }
 
+/// Lists the jobs, newest first, optionally only those created between `from` and `to`.
+async fn get_api_jobs(
+    data: Data<'_>,
+    query: web::Query<GetApiJobsQuery>,
+) -> ApiResult<HttpResponse> {
+    let range = TimeRange::parse(query.from.as_deref(), query.to.as_deref())
+        .map_err(|err| ApiError::bad_request("invalid_time_range", err))?;
+
+    let recorder = data.recorder.clone();
+    let mut summaries = web::block(move || Ok::<_, ()>(recorder.job_summaries_in(&range)))
+        .await
+        .map_err(|_| ApiError::internal("could not list jobs"))?;
+    summaries.sort_by(|(a, _), (b, _)| b.id().cmp(a.id()));
+
+    let jobs: Vec<_> = summaries
+        .into_iter()
+        .map(|(job, summary)| {
+            let files: Vec<_> = summary
+                .files
+                .iter()
+                .map(|file| {
+                    json!({
+                        "name": file.name,
+                        "size": file.size,
+                        "modifiedAt": file.modified_at,
+                        "mimeType": file.mime_type,
+                    })
+                })
+                .collect();
+            json!({
+                "id": job.id().to_string(),
+                "createdAt": job.created_at(),
+                "state": summary.state,
+                "title": summary.title,
+                "uploader": summary.uploader,
+                "fileNames": summary.file_names,
+                "files": files,
+            })
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(json!({ "jobs": jobs })))
+}
+
+fn subscription_json(subscription: &Subscription) -> Json {
+    json!({
+        "id": subscription.id,
+        "url": subscription.url,
+        "intervalMinutes": subscription.interval_minutes,
+        "createdAt": subscription.created_at,
+        "checkedAt": subscription.checked_at,
+        "backfill": subscription.backfill,
+        "error": subscription.error,
+    })
+}
+
+async fn get_api_subscriptions(req: HttpRequest, data: Data<'_>) -> ApiResult<HttpResponse> {
+    if header_access_key(&req) != Some(data.access_key.as_str()) {
+        return Err(ApiError::unauthorized());
+    }
+
+    let subscriptions: Vec<_> = data
+        .subscriptions
+        .list()
+        .map_err(ApiError::internal)?
+        .iter()
+        .map(subscription_json)
+        .collect();
+    Ok(HttpResponse::Ok().json(json!({ "subscriptions": subscriptions })))
+}
+
+/// Subscribes to a channel or playlist, whose new entries are then downloaded every
+/// `intervalMinutes`.
+async fn post_api_subscriptions(
+    req: HttpRequest,
+    request_id: RequestId,
+    data: Data<'_>,
+    payload: web::Json<PostApiSubscriptionsPayload>,
+) -> ApiResult<HttpResponse> {
+    println!("[{}] post_api_subscriptions {:?}", request_id, &payload);
+
+    if payload.access_key != data.access_key {
+        return Err(ApiError::unauthorized());
+    }
+    let is_http = Url::parse(&payload.url)
+        .map(|url| matches!(url.scheme(), "http" | "https"))
+        .unwrap_or(false);
+    if !is_http {
+        return Err(ApiError::bad_request(
+            "invalid_url",
+            "url must be an http or https URL",
+        ));
+    }
+    if payload.interval_minutes < 5 {
+        return Err(ApiError::bad_request(
+            "invalid_interval",
+            "intervalMinutes must be at least 5",
+        ));
+    }
+
+    let subscription = data
+        .subscriptions
+        .add(&payload.url, payload.interval_minutes, payload.backfill)
+        .map_err(ApiError::internal)?;
+    let details = json!({ "subscription_id": subscription.id, "url": subscription.url });
+    data.audit(&req, &payload.access_key, "add_subscription", details);
+    Ok(HttpResponse::Created().json(subscription_json(&subscription)))
+}
+
+async fn delete_api_subscription(req: HttpRequest, data: Data<'_>) -> ApiResult<HttpResponse> {
+    if header_access_key(&req) != Some(data.access_key.as_str()) {
+        return Err(ApiError::unauthorized());
+    }
+
+    let id = req.match_info().query("id");
+    match data.subscriptions.remove(id) {
+        Ok(true) => {
+            let details = json!({ "subscription_id": id });
+            data.audit(&req, &data.access_key, "remove_subscription", details);
+            Ok(HttpResponse::NoContent().finish())
+        }
+        Ok(false) => Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            "subscription_not_found",
+            "no such subscription",
+        )),
+        Err(err) => Err(ApiError::internal(err)),
+    }
+}
+
+/// Lists the jobs waiting to start at a later time, soonest first.
+async fn get_api_jobs_scheduled(data: Data<'_>) -> ApiResult<HttpResponse> {
+    let recorder = data.recorder.clone();
+    let scheduled = web::block(move || Ok::<_, ()>(recorder.scheduled_jobs()))
+        .await
+        .map_err(|_| ApiError::internal("could not list scheduled jobs"))?;
+
+    let jobs: Vec<_> = scheduled
+        .into_iter()
+        .map(|(start_at, job)| {
+            json!({
+                "id": job.id().to_string(),
+                "createdAt": job.created_at(),
+                "startAt": start_at,
+                "title": job.title(),
+            })
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(json!({ "jobs": jobs })))
+}
+
 async fn delete_jobs(
+    req: HttpRequest,
+    request_id: RequestId,
     data: Data<'_>,
     payload: web::Json<DeleteJobsPayload>,
 ) -> ActixResult<impl Responder> {
-    println!("delete_jobs {:?}", &payload);
+    println!("[{}] delete_jobs {:?}", request_id, &payload);
 
     if payload.access_key != data.access_key {
         return Ok(HttpResponse::Unauthorized().finish());
     }
 
+    let deleted = delete_selected_jobs(&request_id, &data, &payload.job_ids, payload.slim);
+    let details = json!({ "job_ids": deleted, "slim": payload.slim });
+    data.audit(&req, &payload.access_key, "delete_jobs", details);
+
+    Ok(HttpResponse::Ok().json(json!({
+        "deleted": deleted,
+        "trashed": !payload.slim && data.config.trash_retention().is_some(),
+    })))
+}
+
+/// Deletes the jobs checked on the jobs page, which posts them as a form, then goes back to the
+/// page with a message. Jobs moved to the trash are listed in the page's `deleted`, so that it
+/// can offer to restore them.
+async fn post_jobs_delete(
+    req: HttpRequest,
+    request_id: RequestId,
+    data: Data<'_>,
+    params: web::Form<Vec<(String, String)>>,
+) -> impl Responder {
+    let param = |name: &str| {
+        params
+            .iter()
+            .find(|(param_name, _)| param_name == name)
+            .map(|(_, value)| value.trim())
+    };
+
+    let access_key = param("access_key").unwrap_or_default();
+    if access_key != data.access_key {
+        return HttpResponse::Unauthorized()
+            .content_type("text/plain")
+            .body("401 Unauthorized\n\nInvalid access key\n");
+    }
+
+    let job_ids: Result<Vec<JobId>, _> = params
+        .iter()
+        .filter(|(name, _)| name == "job_ids[]")
+        .map(|(_, value)| value.parse())
+        .collect();
+    let job_ids = match job_ids {
+        Ok(job_ids) => job_ids,
+        Err(err) => {
+            return HttpResponse::BadRequest()
+                .content_type("text/plain")
+                .body(format!("400 Bad Request\n\n{}\n", err));
+        }
+    };
+    let slim = param("slim") == Some("true");
+    println!(
+        "[{}] post_jobs_delete {:?} slim = {}",
+        request_id, job_ids, slim
+    );
+
+    let deleted = delete_selected_jobs(&request_id, &data, &job_ids, slim);
+    let details = json!({ "job_ids": deleted, "slim": slim });
+    data.audit(&req, access_key, "delete_jobs", details);
+
+    let is_trashed = !slim && data.config.trash_retention().is_some();
+    let mut response = HttpResponse::SeeOther();
+    // The page says itself what was moved to the trash, next to the button to undo it.
+    if is_trashed && !deleted.is_empty() {
+        let ids: Vec<String> = deleted.iter().map(ToString::to_string).collect();
+        let location = format!("/jobs?deleted={}", ids.join(","));
+        return response.header(http::header::LOCATION, location).finish();
+    }
+    let message = if deleted.is_empty() {
+        "No jobs were deleted.".to_owned()
+    } else if slim {
+        format!("Deleted the media files of {} job(s).", deleted.len())
+    } else {
+        format!("Deleted {} job(s).", deleted.len())
+    };
+    // 303, so that reloading the page it leads to does not post the form again.
+    response
+        .header(http::header::LOCATION, "/jobs")
+        .cookie(flash_cookie(&message))
+        .finish()
+}
+
+/// Deletes the jobs that exist and are not running, or only their media files if `slim`, and
+/// returns the ids of those deleted.
+fn delete_selected_jobs(
+    request_id: &RequestId,
+    data: &AppData<'_>,
+    job_ids: &[JobId],
+    slim: bool,
+) -> Vec<JobId> {
+    let mut deleted = vec![];
+    for job_id in job_ids {
+        if let Some(job) = data.recorder.job(job_id) {
+            println!("[{}] deleting job {}", request_id, job_id);
+            let deleted_job = if slim {
+                job.slim_delete()
+            } else {
+                job.safe_delete()
+            };
+            if deleted_job {
+                deleted.push(job_id.clone());
+            }
+        }
+    }
+    deleted
+}
+
+/// Deletes the jobs matching a filter, such as `failed`, `empty`, or `older_than=30d`, or only
+/// their media files with `slim=true`.
+async fn delete_api_jobs(
+    req: HttpRequest,
+    request_id: RequestId,
+    data: Data<'_>,
+    query: web::Query<DeleteApiJobsQuery>,
+) -> ApiResult<HttpResponse> {
+    println!("[{}] delete_api_jobs {:?}", request_id, &query);
+
+    if header_access_key(&req) != Some(data.access_key.as_str()) {
+        return Err(ApiError::unauthorized());
+    }
+
+    let filter: JobFilter = query
+        .filter
+        .parse()
+        .map_err(|err| ApiError::bad_request("invalid_filter", err))?;
+
+    let deleted = data.recorder.delete_jobs(&filter, query.slim);
+    let details = json!({ "filter": query.filter, "slim": query.slim, "deleted": deleted });
+    data.audit(&req, &data.access_key, "delete_jobs", details);
+    Ok(HttpResponse::Ok().json(json!({ "deleted": deleted })))
+}
+
+/// Deletes a job, or only its media files with `slim=true`, refusing to while it is running.
+async fn delete_api_job(
+    req: HttpRequest,
+    request_id: RequestId,
+    data: Data<'_>,
+    query: web::Query<DeleteApiJobQuery>,
+) -> ApiResult<HttpResponse> {
+    if header_access_key(&req) != Some(data.access_key.as_str()) {
+        return Err(ApiError::unauthorized());
+    }
+
+    let job_id: JobId = req.match_info().query("id").parse()?;
+    println!("[{}] delete_api_job {} {:?}", request_id, job_id, &query);
+    let job = data
+        .recorder
+        .job(&job_id)
+        .ok_or_else(|| RecorderError::JobNotFound(job_id.clone()))?;
+    if job.is_running() {
+        return Err(RecorderError::JobRunning(job_id).into());
+    }
+
+    let deleted = if query.slim {
+        job.slim_delete()
+    } else {
+        job.safe_delete()
+    };
+    if !deleted {
+        return Err(ApiError::internal(format!(
+            "could not delete job {}",
+            job_id
+        )));
+    }
+    let details = json!({ "job_ids": [job_id], "slim": query.slim });
+    data.audit(&req, &data.access_key, "delete_jobs", details);
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Cancels a job that has not finished, stopping it if it is running here or on a worker.
+async fn post_api_job_cancel(
+    req: HttpRequest,
+    request_id: RequestId,
+    data: Data<'_>,
+) -> ApiResult<HttpResponse> {
+    if header_access_key(&req) != Some(data.access_key.as_str()) {
+        return Err(ApiError::unauthorized());
+    }
+
+    let job_id: JobId = req.match_info().query("id").parse()?;
+    println!("[{}] cancel_job {}", request_id, job_id);
+    let job = data.recorder.cancel_job(&job_id)?;
+    let details = json!({ "job_id": job_id });
+    data.audit(&req, &data.access_key, "cancel_job", details);
+    Ok(HttpResponse::Ok().json(json!({
+        "id": job.id().to_string(),
+        "state": job.state(),
+    })))
+}
+
+/// Lists all jobs as CSV (the default) or JSON, with the URL and args of each, to those with the
+/// access key.
+async fn get_api_jobs_export(
+    req: HttpRequest,
+    data: Data<'_>,
+    query: web::Query<GetApiJobsExportQuery>,
+) -> ApiResult<HttpResponse> {
+    if header_access_key(&req) != Some(data.access_key.as_str()) {
+        return Err(ApiError::unauthorized());
+    }
+
+    let format: ExportFormat = query
+        .format
+        .as_deref()
+        .unwrap_or("csv")
+        .parse()
+        .map_err(|err| ApiError::bad_request("invalid_format", err))?;
+
+    let recorder = data.recorder.clone();
+    let rows = web::block(move || Ok::<_, ()>(export::job_rows(&recorder)))
+        .await
+        .map_err(|_| ApiError::internal("could not export jobs"))?;
+
+    let (content_type, file_name, body) = match format {
+        ExportFormat::Csv => ("text/csv; charset=utf-8", "jobs.csv", export::to_csv(&rows)),
+        ExportFormat::Json => (
+            "application/json",
+            "jobs.json",
+            serde_json::to_string(&rows)?,
+        ),
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", file_name),
+        )
+        .body(body))
+}
+
+/// Removes the partial downloads of jobs that are not running, keeping their completed files.
+async fn post_api_jobs_clean_partial(
+    req: HttpRequest,
+    request_id: RequestId,
+    data: Data<'_>,
+    payload: web::Json<CleanPartialFilesPayload>,
+) -> ApiResult<HttpResponse> {
+    println!(
+        "[{}] post_api_jobs_clean_partial {:?}",
+        request_id, &payload
+    );
+
+    if payload.access_key != data.access_key {
+        return Err(ApiError::unauthorized());
+    }
+
+    let details = json!({ "job_ids": payload.job_ids });
+    data.audit(&req, &payload.access_key, "clean_partial_files", details);
+    let recorder = data.recorder.clone();
+    let job_ids = payload.into_inner().job_ids;
+    let freed = web::block(move || {
+        job_ids.into_iter().try_fold(0, |freed, job_id| {
+            let job = recorder
+                .job(&job_id)
+                .ok_or(RecorderError::JobNotFound(job_id))?;
+            Ok::<_, RecorderError>(freed + job.remove_partial_files()?)
+        })
+    })
+    .await;
+
+    match freed {
+        Ok(freed) => Ok(HttpResponse::Ok().json(json!({
+            "freed": freed,
+            "freedSize": data.config.byte_units.humanize(freed),
+        }))),
+        Err(BlockingError::Error(err)) => Err(err.into()),
+        Err(BlockingError::Canceled) => Err(ApiError::internal("canceled")),
+    }
+}
+
+/// Moves jobs back from the trash.
+async fn post_api_jobs_restore(
+    req: HttpRequest,
+    request_id: RequestId,
+    data: Data<'_>,
+    payload: web::Json<RestoreJobsPayload>,
+) -> ApiResult<HttpResponse> {
+    println!("[{}] post_api_jobs_restore {:?}", request_id, &payload);
+
+    if payload.access_key != data.access_key {
+        return Err(ApiError::unauthorized());
+    }
+
+    let mut restored = vec![];
     for job_id in &payload.job_ids {
-        if let Some(job) = data.recorder.job(&job_id.clone().into()) {
-            job.safe_delete();
+        match data.recorder.restore_job(job_id) {
+            Ok(_) => restored.push(job_id),
+            Err(err) => println!("could not restore job {}: {}", job_id, err),
+        }
+    }
+    let details = json!({ "job_ids": restored });
+    data.audit(&req, &payload.access_key, "restore_jobs", details);
+
+    Ok(HttpResponse::Ok().json(json!({ "restored": restored })))
+}
+
+async fn get_admin(data: Data<'_>) -> ActixResult<impl Responder> {
+    render_html(&data.handlebars, "admin", &())
+}
+
+/// Renders the admin status fragment that the admin page loads with the key from its URL hash.
+async fn get_admin_status(req: HttpRequest, data: Data<'_>) -> ActixResult<impl Responder> {
+    if !header_access_key(&req).is_some_and(|key| data.is_admin_key(key)) {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+
+    let downloader_version = downloader_version(&data)
+        .await
+        .unwrap_or_else(|err| format!("unavailable ({})", err));
+
+    let mut jobs: Vec<_> = data
+        .recorder
+        .jobs()
+        .into_iter()
+        .map(|job| {
+            let state = job.state();
+            (job, state)
+        })
+        .collect();
+    jobs.sort_by(|a, b| b.0.id().cmp(a.0.id()));
+
+    let count = |state| jobs.iter().filter(|(_, s)| *s == Some(state)).count();
+    let running = count(JobState::Running);
+    let queued = count(JobState::Queued);
+
+    let recent_errors: Vec<Json> = jobs
+        .iter()
+        .filter(|(_, state)| matches!(state, Some(JobState::Failed) | Some(JobState::Interrupted)))
+        .take(10)
+        .map(|(job, state)| {
+            json!({
+                "id": job.id().to_string(),
+                "state": state,
+                "message": job.last_stderr_line(),
+            })
+        })
+        .collect();
+
+    let mut h = HashMap::new();
+    h.insert("version", json!(VERSION));
+    h.insert("git_hash", json!(GIT_HASH));
+    h.insert("started_at", json!(data.started_at.to_rfc3339()));
+    h.insert("uptime", json!(humanize_duration(Utc::now() - data.started_at)));
+    h.insert("config", json!(data.config.summary()));
+    h.insert("downloader", json!(data.config.downloader));
+    h.insert("downloader_version", json!(downloader_version));
+    h.insert("running", json!(running));
+    h.insert("queued", json!(queued));
+    h.insert("trashed", json!(data.recorder.trashed_job_ids().len()));
+    h.insert("read_only", json!(data.recorder.is_read_only()));
+    h.insert("queue_paused", json!(data.recorder.is_queue_paused()));
+    h.insert("recent_errors", json!(recent_errors));
+    if let Some(vault) = data.recorder.credential_vault() {
+        // Passwords are never sent back.
+        match vault.credentials() {
+            Ok(credentials) => {
+                let credentials: Vec<Json> = credentials
+                    .iter()
+                    .map(|credential| {
+                        json!({
+                            "machine": credential.machine,
+                            "domain": credential.domain,
+                            "username": credential.username,
+                        })
+                    })
+                    .collect();
+                h.insert("credentials", json!(credentials));
+            }
+            Err(err) => {
+                h.insert("credentials_error", json!(err.to_string()));
+            }
         }
+        h.insert("credentials_enabled", json!(true));
     }
 
-    Ok(HttpResponse::Ok().finish())
+    render_html(&data.handlebars, "admin_status", &h)
+}
+
+async fn get_admin_audit(data: Data<'_>) -> ActixResult<impl Responder> {
+    render_html(&data.handlebars, "audit", &())
+}
+
+/// Renders the latest entries of the audit log for the audit page, which loads them with the key
+/// from its URL hash.
+async fn get_admin_audit_entries(req: HttpRequest, data: Data<'_>) -> ActixResult<impl Responder> {
+    if !header_access_key(&req).is_some_and(|key| data.is_admin_key(key)) {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+
+    let entries = data.audit_log.recent(Some(AUDIT_PAGE_SIZE))?;
+    let entries: Vec<_> = entries
+        .into_iter()
+        .map(|entry| {
+            let mut json = json!(entry);
+            json["details"] = json!(entry.details.to_string());
+            json
+        })
+        .collect();
+    render_html(
+        &data.handlebars,
+        "audit_entries",
+        &json!({ "entries": entries }),
+    )
+}
+
+/// Sends the whole audit log as a JSON array, oldest first.
+async fn get_api_admin_audit(req: HttpRequest, data: Data<'_>) -> ApiResult<HttpResponse> {
+    if !header_access_key(&req).is_some_and(|key| data.is_admin_key(key)) {
+        return Err(ApiError::unauthorized());
+    }
+
+    let mut entries = data.audit_log.recent(None).map_err(ApiError::internal)?;
+    entries.reverse();
+    Ok(HttpResponse::Ok()
+        .header(
+            header::CONTENT_DISPOSITION,
+            "attachment; filename=\"audit.json\"",
+        )
+        .json(entries))
+}
+
+async fn post_api_admin_gc(
+    req: HttpRequest,
+    data: Data<'_>,
+    payload: web::Json<AdminPayload>,
+) -> ApiResult<HttpResponse> {
+    if !data.is_admin_key(&payload.access_key) {
+        return Err(ApiError::unauthorized());
+    }
+
+    let removed = data.recorder.prune_job_dirs()?;
+    data.audit(
+        &req,
+        &payload.access_key,
+        "remove_empty_jobs",
+        json!({ "removed": removed }),
+    );
+    Ok(HttpResponse::Ok().json(json!({ "removed": removed })))
+}
+
+async fn post_api_admin_dedup(
+    req: HttpRequest,
+    data: Data<'_>,
+    payload: web::Json<AdminPayload>,
+) -> ApiResult<HttpResponse> {
+    if !data.is_admin_key(&payload.access_key) {
+        return Err(ApiError::unauthorized());
+    }
+
+    data.audit(&req, &payload.access_key, "link_duplicate_files", json!({}));
+    let recorder = data.recorder.clone();
+    match web::block(move || recorder.deduplicate()).await {
+        Ok(report) => Ok(HttpResponse::Ok().json(json!({
+            "linked": report.linked,
+            "reclaimed": report.reclaimed,
+            "reclaimedSize": data.config.byte_units.humanize(report.reclaimed),
+        }))),
+        Err(BlockingError::Error(err)) => Err(err.into()),
+        Err(BlockingError::Canceled) => Err(ApiError::internal("canceled")),
+    }
+}
+
+/// Stops starting queued jobs until the queue is resumed.
+async fn post_api_admin_queue_pause(
+    req: HttpRequest,
+    data: Data<'_>,
+    payload: web::Json<AdminPayload>,
+) -> ApiResult<HttpResponse> {
+    if !data.is_admin_key(&payload.access_key) {
+        return Err(ApiError::unauthorized());
+    }
+
+    println!("post_api_admin_queue_pause");
+    data.recorder.pause_queue()?;
+    data.audit(&req, &payload.access_key, "pause_queue", json!({}));
+    Ok(HttpResponse::Ok().json(json!({ "paused": true })))
+}
+
+/// Starts the jobs queued while the queue was paused.
+async fn post_api_admin_queue_resume(
+    req: HttpRequest,
+    data: Data<'_>,
+    payload: web::Json<AdminPayload>,
+) -> ApiResult<HttpResponse> {
+    if !data.is_admin_key(&payload.access_key) {
+        return Err(ApiError::unauthorized());
+    }
+
+    println!("post_api_admin_queue_resume");
+    data.audit(&req, &payload.access_key, "resume_queue", json!({}));
+    let recorder = data.recorder.clone();
+    match web::block(move || recorder.resume_queue()).await {
+        Ok(started) => Ok(HttpResponse::Ok().json(json!({ "paused": false, "started": started }))),
+        Err(BlockingError::Error(err)) => Err(err.into()),
+        Err(BlockingError::Canceled) => Err(ApiError::internal("canceled")),
+    }
+}
+
+/// Enters or leaves maintenance mode, in which new jobs are refused.
+async fn post_api_admin_read_only(
+    req: HttpRequest,
+    data: Data<'_>,
+    payload: web::Json<AdminReadOnlyPayload>,
+) -> ApiResult<HttpResponse> {
+    if !data.is_admin_key(&payload.access_key) {
+        return Err(ApiError::unauthorized());
+    }
+
+    println!("post_api_admin_read_only {}", payload.read_only);
+    data.recorder.set_read_only(payload.read_only)?;
+    let details = json!({ "read_only": payload.read_only });
+    data.audit(&req, &payload.access_key, "set_read_only", details);
+    Ok(HttpResponse::Ok().json(json!({ "readOnly": payload.read_only })))
+}
+
+/// Adds a site login to the credential vault, replacing the one for the same machine and domain.
+async fn post_api_admin_credentials(
+    req: HttpRequest,
+    data: Data<'_>,
+    payload: web::Json<AdminCredentialPayload>,
+) -> ApiResult<HttpResponse> {
+    if !data.is_admin_key(&payload.access_key) {
+        return Err(ApiError::unauthorized());
+    }
+    let vault = credential_vault(&data)?;
+
+    let payload = payload.into_inner();
+    let credential = payload.credential;
+    println!(
+        "post_api_admin_credentials {} {}",
+        credential.machine, credential.domain
+    );
+    // Without the password, which the log is no place for.
+    let details = json!({
+        "machine": credential.machine,
+        "domain": credential.domain,
+        "username": credential.username,
+    });
+    vault.add(credential)?;
+    data.audit(&req, &payload.access_key, "add_credential", details);
+    Ok(HttpResponse::Ok().json(json!({})))
+}
+
+async fn post_api_admin_credentials_remove(
+    req: HttpRequest,
+    data: Data<'_>,
+    payload: web::Json<AdminRemoveCredentialPayload>,
+) -> ApiResult<HttpResponse> {
+    if !data.is_admin_key(&payload.access_key) {
+        return Err(ApiError::unauthorized());
+    }
+    let vault = credential_vault(&data)?;
+
+    println!(
+        "post_api_admin_credentials_remove {} {}",
+        payload.machine, payload.domain
+    );
+    let removed = vault.remove(&payload.machine, &payload.domain)?;
+    let details = json!({ "machine": payload.machine, "domain": payload.domain });
+    data.audit(&req, &payload.access_key, "remove_credential", details);
+    Ok(HttpResponse::Ok().json(json!({ "removed": removed })))
+}
+
+fn credential_vault<'a>(data: &'a Data<'_>) -> ApiResult<&'a CredentialVault> {
+    data.recorder
+        .credential_vault()
+        .ok_or_else(|| ApiError::bad_request("credentials_disabled", "CREDENTIALS_KEY is not set"))
+}
+
+async fn post_api_admin_update_downloader(
+    req: HttpRequest,
+    data: Data<'_>,
+    payload: web::Json<AdminPayload>,
+) -> ApiResult<HttpResponse> {
+    if !data.is_admin_key(&payload.access_key) {
+        return Err(ApiError::unauthorized());
+    }
+
+    let job = downloader::spawn_update(&data.config, &data.recorder)?;
+    let details = json!({ "job_id": job.id().to_string() });
+    data.audit(&req, &payload.access_key, "update_downloader", details);
+    Ok(HttpResponse::Created().json(json!({ "id": job.id().to_string() })))
+}
+
+/// Hands the oldest job waiting for a worker to the calling worker, or responds with 204 No
+/// Content if there is none.
+async fn post_api_worker_claim(
+    data: Data<'_>,
+    payload: web::Json<WorkerClaimPayload>,
+) -> ApiResult<HttpResponse> {
+    if !data.is_worker_key(&payload.access_key) {
+        return Err(ApiError::unauthorized());
+    }
+
+    let recorder = data.recorder.clone();
+    let worker = payload.into_inner().worker;
+    let claimed = web::block(move || Ok::<_, RecorderError>(recorder.claim_job(&worker))).await;
+    match claimed {
+        Ok(Some(claimed)) => Ok(HttpResponse::Ok().json(json!({
+            "id": claimed.job.id().to_string(),
+            "args": claimed.args,
+            "timeLimitSeconds": claimed.time_limit.map(|limit| limit.as_secs()),
+            "browserCookies": claimed.browser_cookies,
+            "leaseSeconds": claimed.lease.as_secs(),
+        }))),
+        Ok(None) => Ok(HttpResponse::NoContent().finish()),
+        Err(BlockingError::Error(err)) => Err(err.into()),
+        Err(BlockingError::Canceled) => Err(ApiError::internal("canceled")),
+    }
+}
+
+/// Stores a file that a worker downloaded for a job it claimed. The body is the file's contents.
+async fn put_api_worker_job_file(
+    req: HttpRequest,
+    data: Data<'_>,
+    mut payload: web::Payload,
+) -> ApiResult<HttpResponse> {
+    if !header_access_key(&req).is_some_and(|key| data.is_worker_key(key)) {
+        return Err(ApiError::unauthorized());
+    }
+
+    let job_id: JobId = req.match_info().query("id").parse()?;
+    let file_name = percent_decode(req.match_info().query("file_name").as_bytes())
+        .decode_utf8_lossy()
+        .to_string();
+    let job = data.recorder.claimed_job(&job_id)?;
+
+    let mut f = job.create_file(&file_name)?;
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk.map_err(|err| ApiError::bad_request("invalid_payload", err))?;
+        f = match web::block(move || f.write_all(&chunk).map(|_| f)).await {
+            Ok(f) => f,
+            Err(BlockingError::Error(err)) => return Err(RecorderError::from(err).into()),
+            Err(BlockingError::Canceled) => return Err(ApiError::internal("canceled")),
+        };
+    }
+
+    println!("job {} received {:?} from a worker", job_id, file_name);
+    Ok(HttpResponse::Created().json(json!({ "name": file_name })))
+}
+
+/// Sends the command and options a job was started with.
+async fn get_api_job_invocation(req: HttpRequest, data: Data<'_>) -> ApiResult<HttpResponse> {
+    if header_access_key(&req) != Some(data.access_key.as_str()) {
+        return Err(ApiError::unauthorized());
+    }
+
+    let job_id: JobId = req.match_info().query("id").parse()?;
+    let job = data
+        .recorder
+        .job(&job_id)
+        .ok_or(RecorderError::JobNotFound(job_id))?;
+    let invocation = job.invocation().ok_or_else(|| {
+        ApiError::new(
+            StatusCode::NOT_FOUND,
+            "invocation_not_found",
+            "the job has no invocation",
+        )
+    })?;
+    Ok(HttpResponse::Ok().json(invocation))
+}
+
+/// Sends the diagnostics of a job's last run, with the runtime and speed also in readable form.
+async fn get_api_job_diagnostics(req: HttpRequest, data: Data<'_>) -> ApiResult<HttpResponse> {
+    if header_access_key(&req) != Some(data.access_key.as_str()) {
+        return Err(ApiError::unauthorized());
+    }
+
+    let job_id: JobId = req.match_info().query("id").parse()?;
+    let job = data
+        .recorder
+        .job(&job_id)
+        .ok_or(RecorderError::JobNotFound(job_id))?;
+    let diagnostics = web::block(move || Ok::<_, ()>(job.diagnostics()))
+        .await
+        .map_err(|_| ApiError::internal("could not read diagnostics"))?;
+
+    let mut json = serde_json::to_value(&diagnostics)?;
+    json["humanizedRuntime"] = json!(diagnostics
+        .runtime_seconds
+        .map(|secs| humanize_duration(chrono::Duration::seconds(secs))));
+    json["humanizedPeakSpeed"] = json!(diagnostics
+        .peak_speed
+        .map(|speed| format!("{}/s", data.config.byte_units.humanize(speed))));
+    Ok(HttpResponse::Ok().json(json))
+}
+
+/// Sends the duration, resolution, and codecs of each of a job's video and audio files, probing
+/// those that have not been yet.
+async fn get_api_job_media(req: HttpRequest, data: Data<'_>) -> ApiResult<HttpResponse> {
+    if header_access_key(&req) != Some(data.access_key.as_str()) {
+        return Err(ApiError::unauthorized());
+    }
+
+    let job_id: JobId = req.match_info().query("id").parse()?;
+    let job = data
+        .recorder
+        .job(&job_id)
+        .ok_or(RecorderError::JobNotFound(job_id))?;
+    let media_info = web::block(move || Ok::<_, ()>(job.media_info()))
+        .await
+        .map_err(|_| ApiError::internal("could not probe media files"))?;
+
+    let mut files: Vec<_> = media_info.into_iter().collect();
+    files.sort_by(|(a, _), (b, _)| compare_file_names(a, b));
+    let files: Vec<_> = files
+        .into_iter()
+        .map(|(name, info)| {
+            let mut json = json!(info);
+            json["name"] = json!(name);
+            json
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(json!({ "files": files })))
+}
+
+/// Sends the stdout or stderr of a job's downloader as text, only the last `tail` lines if
+/// given.
+async fn get_api_job_log(
+    req: HttpRequest,
+    data: Data<'_>,
+    query: web::Query<GetApiJobLogQuery>,
+) -> ApiResult<HttpResponse> {
+    if header_access_key(&req) != Some(data.access_key.as_str()) {
+        return Err(ApiError::unauthorized());
+    }
+
+    let job_id: JobId = req.match_info().query("id").parse()?;
+    let job = data
+        .recorder
+        .job(&job_id)
+        .ok_or(RecorderError::JobNotFound(job_id))?;
+    let stream = req.match_info().query("stream").to_owned();
+    let tail = query.tail;
+    let log = match web::block(move || job.log(&stream, tail)).await {
+        Ok(log) => log,
+        Err(BlockingError::Error(err)) if err.kind() == io::ErrorKind::NotFound => {
+            return Err(ApiError::new(
+                StatusCode::NOT_FOUND,
+                "log_not_found",
+                "the job has no such log",
+            ))
+        }
+        Err(BlockingError::Error(err)) => return Err(err.into()),
+        Err(BlockingError::Canceled) => return Err(ApiError::internal("canceled")),
+    };
+    Ok(HttpResponse::Ok()
+        .content_type("text/plain; charset=utf-8")
+        .body(log))
+}
+
+/// Renews a worker's claim on a job it is running. Responds with `claimed: false` if the job
+/// was cancelled or deleted, or the claim expired, in which case the worker should stop.
+async fn post_api_worker_job_heartbeat(
+    req: HttpRequest,
+    data: Data<'_>,
+    payload: web::Json<WorkerHeartbeatPayload>,
+) -> ApiResult<HttpResponse> {
+    if !data.is_worker_key(&payload.access_key) {
+        return Err(ApiError::unauthorized());
+    }
+
+    let job_id: JobId = req.match_info().query("id").parse()?;
+    match data.recorder.claimed_job(&job_id) {
+        Ok(_) => Ok(HttpResponse::Ok().json(json!({
+            "claimed": true,
+            "leaseSeconds": data.recorder.worker_lease().as_secs(),
+        }))),
+        Err(RecorderError::NotClaimed(_) | RecorderError::JobNotFound(_)) => {
+            Ok(HttpResponse::Ok().json(json!({ "claimed": false })))
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Records how a worker's run of a job ended, running the post steps if it succeeded.
+async fn post_api_worker_job_finish(
+    req: HttpRequest,
+    data: Data<'_>,
+    payload: web::Json<WorkerFinishPayload>,
+) -> ApiResult<HttpResponse> {
+    if !data.is_worker_key(&payload.access_key) {
+        return Err(ApiError::unauthorized());
+    }
+    if !matches!(
+        payload.state,
+        JobState::Succeeded | JobState::Failed | JobState::Interrupted
+    ) {
+        return Err(ApiError::bad_request(
+            "invalid_state",
+            format!("a worker can't finish a job as {}", payload.state),
+        ));
+    }
+
+    let job_id: JobId = req.match_info().query("id").parse()?;
+    let recorder = data.recorder.clone();
+    let payload = payload.into_inner();
+    let finished = web::block(move || {
+        recorder.finish_remote_job(&job_id, payload.state, &payload.stdout, &payload.stderr)
+    })
+    .await;
+    match finished {
+        Ok(job) => Ok(HttpResponse::Ok().json(json!({
+            "id": job.id().to_string(),
+            "state": job.state(),
+        }))),
+        Err(BlockingError::Error(err)) => Err(err.into()),
+        Err(BlockingError::Canceled) => Err(ApiError::internal("canceled")),
+    }
 }
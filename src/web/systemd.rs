@@ -0,0 +1,52 @@
+//! Notifications for running as a systemd service with `Type=notify` and `WatchdogSec=`.
+
+use std::env;
+use std::time::Duration;
+
+use crate::platform;
+
+/// Tells systemd that the server accepts connections.
+pub fn notify_ready() {
+    notify("READY=1");
+}
+
+/// Tells systemd that the server is shutting down.
+pub fn notify_stopping() {
+    notify("STOPPING=1");
+}
+
+/// Pings the watchdog at half the timeout systemd set, if any. The pings are sent from the event
+/// loop the server runs on rather than a thread of their own, so that they stop, and systemd
+/// restarts the server, if the loop hangs.
+pub fn start_watchdog() {
+    let timeout = match watchdog_timeout() {
+        Some(timeout) => timeout,
+        None => return,
+    };
+    println!("pinging the systemd watchdog every {:?}", timeout / 2);
+    actix_rt::spawn(async move {
+        let mut interval = actix_rt::time::interval(timeout / 2);
+        loop {
+            interval.tick().await;
+            notify("WATCHDOG=1");
+        }
+    });
+}
+
+/// The timeout systemd passes in `WATCHDOG_USEC`, unless `WATCHDOG_PID` says it is meant for
+/// another process.
+fn watchdog_timeout() -> Option<Duration> {
+    if let Ok(pid) = env::var("WATCHDOG_PID") {
+        if pid.parse() != Ok(std::process::id()) {
+            return None;
+        }
+    }
+    let usec: u64 = env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    Some(Duration::from_micros(usec)).filter(|timeout| !timeout.is_zero())
+}
+
+fn notify(state: &str) {
+    if let Err(err) = platform::notify_systemd(state) {
+        println!("notifying systemd of {} failed: {}", state, err);
+    }
+}
@@ -0,0 +1,46 @@
+use std::path::Path;
+
+use handlebars::{Handlebars, TemplateFileError};
+use rust_embed::RustEmbed;
+
+use crate::disk_stat::ByteUnits;
+use crate::display_time::DisplayTimezone;
+use crate::web::helpers;
+
+#[derive(RustEmbed)]
+#[folder = "templates/"]
+struct Templates;
+
+/// Creates a registry with the helpers and templates that pages are rendered with.
+#[allow(clippy::result_large_err)]
+pub fn new_handlebars(
+    override_dir: Option<&Path>,
+    timezone: DisplayTimezone,
+    byte_units: ByteUnits,
+) -> Result<Handlebars<'static>, TemplateFileError> {
+    let mut handlebars = Handlebars::new();
+    helpers::register_handlebars_helpers(&mut handlebars, timezone, byte_units);
+    register_templates(&mut handlebars, override_dir)?;
+    Ok(handlebars)
+}
+
+/// Registers the templates built into the binary, then the `.hbs` files in `override_dir` (if any)
+/// so that individual templates can be replaced without rebuilding.
+#[allow(clippy::result_large_err)]
+fn register_templates(
+    handlebars: &mut Handlebars,
+    override_dir: Option<&Path>,
+) -> Result<(), TemplateFileError> {
+    for file_name in Templates::iter() {
+        if let Some(name) = file_name.strip_suffix(".hbs") {
+            let source = Templates::get(&file_name).expect("embedded template must exist");
+            handlebars.register_template_string(name, String::from_utf8_lossy(&source))?;
+        }
+    }
+
+    if let Some(dir) = override_dir {
+        handlebars.register_templates_directory(".hbs", dir)?;
+    }
+
+    Ok(())
+}
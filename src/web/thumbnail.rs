@@ -0,0 +1,76 @@
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use image::io::Reader as ImageReader;
+use image::{DynamicImage, ImageOutputFormat};
+use sha2::{Digest, Sha256};
+
+use crate::recorder::Job;
+
+/// The widths thumbnails are made in, so that each image is resized and kept in a few sizes at
+/// most.
+pub const THUMBNAIL_WIDTHS: &[u32] = &[160, 320, 640, 1280];
+
+/// The width of the thumbnails in the jobs list.
+pub const DEFAULT_THUMBNAIL_WIDTH: u32 = 320;
+
+/// Images with more pixels than this are not decoded, as they may be made to exhaust memory.
+const MAX_PIXELS: u64 = 64_000_000;
+
+const JPEG_QUALITY: u8 = 80;
+
+/// Returns the path of a copy of the job's image `file_name` resized to `width`, kept as a JPEG
+/// in the job's `info/thumbs/`, making it unless the copy kept is newer than the image. Images
+/// no wider than `width` are copied at their size.
+pub fn cached_thumbnail(job: &Job, file_name: &str, width: u32) -> io::Result<PathBuf> {
+    let source_path = job.path().join(file_name);
+    // Named after a hash, as the name of the image with the width added may be too long.
+    let hash = Sha256::digest(file_name.as_bytes());
+    let cache_name = format!("{:x}-{}.jpg", hash, width);
+    let cache_dir = job.path().join("info/thumbs");
+    let cache_path = cache_dir.join(&cache_name);
+
+    let modified = |path: &Path| fs::metadata(path).and_then(|metadata| metadata.modified());
+    if let (Ok(cached), Ok(source)) = (modified(&cache_path), modified(&source_path)) {
+        if cached >= source {
+            return Ok(cache_path);
+        }
+    }
+
+    let reader = ImageReader::open(&source_path)?.with_guessed_format()?;
+    let (source_width, source_height) = reader.into_dimensions().map_err(io::Error::other)?;
+    if u64::from(source_width) * u64::from(source_height) > MAX_PIXELS {
+        return Err(io::Error::other(format!(
+            "{} is too large ({}x{})",
+            file_name, source_width, source_height
+        )));
+    }
+    let image = ImageReader::open(&source_path)?
+        .with_guessed_format()?
+        .decode()
+        .map_err(io::Error::other)?;
+    let image = if source_width > width {
+        image.thumbnail(width, u32::MAX)
+    } else {
+        image
+    };
+    // JPEG has no alpha channel.
+    let image = DynamicImage::ImageRgb8(image.to_rgb8());
+
+    // Written under another name first, so that a request meanwhile never gets a partial image.
+    fs::create_dir_all(&cache_dir)?;
+    let tmp_path = cache_path.with_extension(format!("{}.tmp", ulid::Ulid::new()));
+    let written = fs::File::create(&tmp_path).and_then(|f| {
+        let mut w = BufWriter::new(f);
+        image
+            .write_to(&mut w, ImageOutputFormat::Jpeg(JPEG_QUALITY))
+            .map_err(io::Error::other)?;
+        w.flush()
+    });
+    if let Err(err) = written.and_then(|_| fs::rename(&tmp_path, &cache_path)) {
+        fs::remove_file(&tmp_path).ok();
+        return Err(err);
+    }
+    Ok(cache_path)
+}
@@ -0,0 +1,228 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde_json::{json, Value as Json};
+
+use crate::cli::{curl_json, post_json};
+use crate::config::Config;
+use crate::recorder::{is_partial_file_name, Job, JobOptions, JobState, Recorder};
+
+/// How long to wait before asking for a job again when there was none.
+const IDLE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How long a claim lasts if the coordinator doesn't say.
+const DEFAULT_LEASE: Duration = Duration::from_secs(10 * 60);
+
+/// How much of the end of each log is sent back to the coordinator.
+const MAX_LOG_SIZE: usize = 4 * 1024 * 1024;
+
+/// The server that jobs are claimed from and whose job dirs receive the files.
+struct Coordinator {
+    url: String,
+    worker_key: String,
+}
+
+impl Coordinator {
+    /// Claims the oldest job waiting for a worker, if any.
+    fn claim(&self, worker: &str) -> io::Result<Option<Json>> {
+        let url = format!("{}/api/worker/claim", self.url);
+        let claimed = post_json(
+            &url,
+            &json!({ "accessKey": self.worker_key, "worker": worker }),
+        )?;
+        Ok(Some(claimed).filter(|claimed| !claimed.is_null()))
+    }
+
+    fn upload(&self, job_id: &str, path: &Path, file_name: &str) -> io::Result<()> {
+        let url = format!(
+            "{}/api/worker/jobs/{}/files/{}",
+            self.url,
+            job_id,
+            utf8_percent_encode(file_name, NON_ALPHANUMERIC)
+        );
+        // The key is passed on stdin so that it does not show up in the process list.
+        let header = format!("X-Access-Key: {}\n", self.worker_key);
+        curl_json(&["-H", "@-", "-T", &path.to_string_lossy(), &url], &header)?;
+        Ok(())
+    }
+
+    /// Renews the claim on a job, and returns whether the job is still this worker's to run.
+    fn heartbeat(&self, job_id: &str) -> io::Result<bool> {
+        let url = format!("{}/api/worker/jobs/{}/heartbeat", self.url, job_id);
+        let response = post_json(&url, &json!({ "accessKey": self.worker_key }))?;
+        Ok(response["claimed"] == true)
+    }
+
+    fn finish(&self, job_id: &str, state: JobState, stdout: &str, stderr: &str) -> io::Result<()> {
+        let url = format!("{}/api/worker/jobs/{}/finish", self.url, job_id);
+        post_json(
+            &url,
+            &json!({
+                "accessKey": self.worker_key,
+                "state": state,
+                "stdout": stdout,
+                "stderr": stderr,
+            }),
+        )?;
+        Ok(())
+    }
+}
+
+/// Runs downloads for the coordinator at `COORDINATOR_URL`, one at a time: claims a job, runs
+/// the downloader on it here, sends the files back, and removes them here. Runs until killed.
+pub fn run() -> io::Result<()> {
+    let config = Config::from_env();
+
+    let coordinator = Coordinator {
+        url: config
+            .coordinator_url
+            .as_deref()
+            .ok_or_else(|| io::Error::other("COORDINATOR_URL must be set"))?
+            .trim_end_matches('/')
+            .to_owned(),
+        worker_key: config
+            .worker_key
+            .clone()
+            .ok_or_else(|| io::Error::other("WORKER_KEY must be set"))?,
+    };
+
+    // Jobs are removed once sent rather than moved to the trash, as the coordinator keeps them.
+    let mut recorder = Recorder::new(config.jobs_dir());
+    recorder.set_sandbox(config.sandbox);
+    recorder.set_job_user(config.job_uid, config.job_gid);
+    if let Some(browser) = &config.cookies_from_browser {
+        recorder.set_cookies_from_browser(&config.downloader, browser);
+    }
+    if let Some(vault) = config.credential_vault() {
+        recorder.set_credential_vault(&config.downloader, vault);
+    }
+    let _lock = recorder
+        .lock()
+        .map_err(|err| io::Error::other(err.to_string()))?;
+
+    println!(
+        "worker {} claims jobs from {}",
+        config.worker_name, coordinator.url
+    );
+    loop {
+        let claimed = match coordinator.claim(&config.worker_name) {
+            Ok(Some(claimed)) => claimed,
+            Ok(None) => {
+                thread::sleep(IDLE_INTERVAL);
+                continue;
+            }
+            Err(err) => {
+                println!("claiming a job failed: {}", err);
+                thread::sleep(IDLE_INTERVAL);
+                continue;
+            }
+        };
+        if let Err(err) = run_job(&config, &recorder, &coordinator, &claimed) {
+            println!("job {} failed on this worker: {}", claimed["id"], err);
+        }
+    }
+}
+
+/// Runs a claimed job in a local job and reports it to the coordinator.
+fn run_job(
+    config: &Config,
+    recorder: &Recorder,
+    coordinator: &Coordinator,
+    claimed: &Json,
+) -> io::Result<()> {
+    let job_id = claimed["id"]
+        .as_str()
+        .ok_or_else(|| io::Error::other("claimed job has no id"))?;
+    let args: Vec<String> = serde_json::from_value(claimed["args"].clone())?;
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let options = JobOptions {
+        time_limit: claimed["timeLimitSeconds"]
+            .as_u64()
+            .map(Duration::from_secs),
+        browser_cookies: claimed["browserCookies"] == true,
+        ..JobOptions::default()
+    };
+
+    let job = match recorder.spawn_job_with(&config.downloader, &args, options) {
+        Ok(job) => job,
+        Err(err) => {
+            coordinator.finish(job_id, JobState::Failed, "", &format!("{}\n", err))?;
+            return Err(io::Error::other(err.to_string()));
+        }
+    };
+    println!("job {} runs here as job {}", job_id, job.id());
+
+    // Renewed well before it runs out, so that one failed heartbeat doesn't lose the job.
+    let heartbeat_interval = claimed["leaseSeconds"]
+        .as_u64()
+        .map_or(DEFAULT_LEASE, Duration::from_secs)
+        / 4;
+    let mut last_heartbeat = Instant::now();
+    let mut cancelled = false;
+    while matches!(
+        job.state(),
+        Some(JobState::Created | JobState::Queued | JobState::Running)
+    ) {
+        thread::sleep(Duration::from_secs(1));
+        if cancelled || last_heartbeat.elapsed() < heartbeat_interval {
+            continue;
+        }
+        last_heartbeat = Instant::now();
+        match coordinator.heartbeat(job_id) {
+            Ok(true) => {}
+            Ok(false) => {
+                println!(
+                    "job {} is no longer claimed by this worker; stopping it",
+                    job_id
+                );
+                if let Err(err) = recorder.cancel_job(job.id()) {
+                    println!("job {} could not be stopped: {}", job.id(), err);
+                }
+                cancelled = true;
+            }
+            Err(err) => println!("job {} heartbeat failed: {}", job_id, err),
+        }
+    }
+    if cancelled {
+        // Wait for the process to exit so that its files can be removed.
+        while job.is_running() {
+            thread::sleep(Duration::from_secs(1));
+        }
+        job.safe_delete();
+        return Ok(());
+    }
+
+    let result = send_job(coordinator, job_id, &job);
+    job.safe_delete();
+    result
+}
+
+/// Uploads the files of a finished job, except partial downloads, and reports how it ended.
+fn send_job(coordinator: &Coordinator, job_id: &str, job: &Job) -> io::Result<()> {
+    let mut file_names = job.file_names();
+    file_names.sort();
+    for file_name in file_names
+        .iter()
+        .filter(|file_name| !is_partial_file_name(file_name))
+    {
+        coordinator.upload(job_id, &job.path().join(file_name), file_name)?;
+    }
+
+    let state = job.state().unwrap_or(JobState::Failed);
+    let stdout = read_log(job, "info/stdout.txt");
+    let stderr = read_log(job, "info/stderr.txt");
+    coordinator.finish(job_id, state, &stdout, &stderr)?;
+    println!("job {} {} and was sent back", job_id, state);
+    Ok(())
+}
+
+/// The end of a log of the job, up to `MAX_LOG_SIZE`.
+fn read_log(job: &Job, file_name: &str) -> String {
+    let log = fs::read(job.path().join(file_name)).unwrap_or_default();
+    let tail = &log[log.len().saturating_sub(MAX_LOG_SIZE)..];
+    String::from_utf8_lossy(tail).into_owned()
+}